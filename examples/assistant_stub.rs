@@ -0,0 +1,68 @@
+//! Minimal OpenAI-compatible chat completions server for trying the `ai`
+//! feature without a real model. Every request is answered with a canned
+//! reply streamed word by word as server-sent events.
+//!
+//!     cargo run --example assistant_stub
+//!     SHINDENSEN_AI_URL=http://127.0.0.1:8080/v1/chat/completions cargo run --features ai
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+const ADDR: &str = "127.0.0.1:8080";
+const REPLY: &str = "Hi! I am a stub assistant. I got **your message** and counted the \
+    conversation I was sent: this is a canned reply streamed word by word.";
+
+fn main() -> std::io::Result<()> {
+    let listener = TcpListener::bind(ADDR)?;
+    println!(
+        "assistant stub listening on http://{}/v1/chat/completions",
+        ADDR
+    );
+    for stream in listener.incoming() {
+        let stream = stream?;
+        thread::spawn(move || {
+            if let Err(err) = handle(stream) {
+                eprintln!("request failed: {}", err);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle(mut stream: TcpStream) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse().unwrap_or(0);
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    let messages = String::from_utf8_lossy(&body).matches("\"role\"").count();
+    println!("completion request with {} messages", messages);
+
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n"
+    )?;
+    for word in REPLY.split_inclusive(' ') {
+        let chunk = format!(
+            "{{\"choices\":[{{\"delta\":{{\"content\":\"{}\"}}}}]}}",
+            word.replace('\\', "\\\\").replace('"', "\\\"")
+        );
+        write!(stream, "data: {}\n\n", chunk)?;
+        stream.flush()?;
+        thread::sleep(Duration::from_millis(60));
+    }
+    write!(stream, "data: [DONE]\n\n")?;
+    stream.flush()
+}
@@ -1,6 +1,15 @@
+#[cfg(feature = "ai")]
+use crate::assistant::{AssistantAction, ReplyTarget};
+use crate::autho::AUTH_SCREEN;
+use crate::dialog::{CHATS_SCREEN, chat_route};
+use crate::new_chat::NEW_CHAT_SCREEN;
+use crate::notifications::Notification;
+use crate::outbox::UploadFailure;
+use crate::router::{self, Route, RouterAction};
 use crate::shindensen_client::*;
+use crate::shortcuts::ShortcutCommand;
 use crate::state::*;
-use makepad_micro_serde::*;
+use crate::storage::Store;
 use makepad_widgets::*;
 
 pub const API_URL: &str = "http://127.0.0.1:3000";
@@ -8,13 +17,6 @@ pub const WS_URL: &str = "ws://127.0.0.1:3000/ws";
 // pub const API_URL: &str = "https://api.shindensen.strizhkindenis.ru";
 // pub const WS_URL: &str = "wss://api.shindensen.strizhkindenis.ru/ws";
 
-#[derive(Clone, Debug, Default, PartialEq)]
-pub enum AppAction {
-    SwitchWindow(Screen),
-    #[default]
-    None,
-}
-
 script_mod! {
     use mod.prelude.widgets.*
     use mod.widgets.*
@@ -27,17 +29,13 @@ script_mod! {
                     main_view := View{
                         width: Fill
                         height: Fill
-                        flow: Down
-                        spacing: 0.0
-                        auth_page := LoginForm {
-                            visible: true
-                        }
-                        dialog_page := DialogPage {
-                            visible: false
-                        }
-                        new_chat := NewChat {
-                            visible: false
-                        }
+                        flow: Overlay
+                        pages := Pages{}
+                        notification_center := NotificationCenter{}
+                        mentions_inbox := MentionsInbox{}
+                        quick_switcher := QuickSwitcher{}
+                        image_viewer := ImageViewer{}
+                        toasts := ToastOverlay{}
                     }
                 }
             }
@@ -51,17 +49,30 @@ pub struct App {
     ui: WidgetRef,
     #[rust]
     state: State,
+    /// The socket dropped and hasn't come back yet.
+    #[rust]
+    connection_lost: bool,
+    /// `State::theme_version` the widgets were last styled for.
+    #[rust]
+    theme_version: u64,
 }
 
 impl App {
     fn run(vm: &mut ScriptVm) -> Self {
         crate::makepad_widgets::script_mod(vm);
+        makepad_code_editor::script_mod(vm);
         crate::ui::script_mod(vm);
         crate::layout::script_mod(vm);
         crate::dialog_list::script_mod(vm);
-        crate::dialog::script_mod(vm);
-        crate::autho::script_mod(vm);
-        crate::new_chat::script_mod(vm);
+        crate::search_panel::script_mod(vm);
+        crate::summary_panel::script_mod(vm);
+        crate::notification_center::script_mod(vm);
+        crate::quick_switcher::script_mod(vm);
+        crate::mentions_inbox::script_mod(vm);
+        crate::emoji_picker::script_mod(vm);
+        crate::attachment_strip::script_mod(vm);
+        crate::image_viewer::script_mod(vm);
+        crate::screens::script_mods(vm);
 
         let mut app = App::from_script_mod(vm, self::script_mod);
         app.state = State::new(API_URL.into(), WS_URL.into());
@@ -70,58 +81,125 @@ impl App {
 }
 
 impl App {
-    fn switch_screen(&mut self, cx: &mut Cx, screen: Screen) {
-        let auth_page = self.ui.widget(cx, ids!(auth_page));
-        let dialog_page = self.ui.widget(cx, ids!(dialog_page));
-        let new_chat = self.ui.widget(cx, ids!(new_chat));
-        match self.state.screen {
-            Screen::Auth => {
-                auth_page.set_visible(cx, false);
-                log!("off auth");
-            }
-            Screen::Dialog => {
-                dialog_page.set_visible(cx, false);
-                log!("off dialog");
-            }
-            Screen::NewChatInit => {
-                new_chat.set_visible(cx, false);
-                log!("off new chat init");
-            }
-        }
-        match screen {
-            Screen::Auth => {
-                auth_page.set_visible(cx, true);
-                auth_page.redraw(cx);
-                log!("display auth");
-            }
-            Screen::Dialog => {
-                dialog_page.set_visible(cx, true);
-                dialog_page.redraw(cx);
-                log!("display dialog");
-            }
-            Screen::NewChatInit => {
-                new_chat.set_visible(cx, true);
-                new_chat.redraw(cx);
-                log!("display new chat init");
-            }
-        }
-        self.state.screen = screen;
+    fn navigate(&mut self, cx: &mut Cx, action: RouterAction) {
+        router::navigate(cx, &self.ui, &mut self.state, action);
+    }
+
+    fn notify(&mut self, cx: &mut Cx, notification: Notification) {
+        self.state.notifications.push(notification);
+        self.ui.widget(cx, ids!(toasts)).redraw(cx);
+        self.ui.widget(cx, ids!(notification_center)).redraw(cx);
     }
 
     pub fn load_chats(&mut self, cx: &mut Cx) {
         self.state.client.get_chats(cx);
     }
 
-    fn new_chat_init(&mut self, cx: &mut Cx) {
-        self.ui
-            .text_input(cx, ids!(main_window.body.new_chat.chat_name))
-            .set_text(cx, "");
+    /// Closes the topmost overlay, or goes back a screen when none is open.
+    fn close_overlay(&mut self, cx: &mut Cx) {
+        if self.state.lightbox.is_some() {
+            self.state.lightbox = None;
+        } else if self.state.show_switcher {
+            self.state.show_switcher = false;
+        } else if self.state.show_notifications {
+            self.state.show_notifications = false;
+        } else if self.state.show_mentions {
+            self.state.show_mentions = false;
+        } else if self.state.summary.is_some() {
+            self.state.close_summary();
+        } else if self.state.router.current().screen == CHATS_SCREEN
+            && self.state.single_pane()
+            && !self.state.show_chat_list
+        {
+            self.state.show_chat_list = true;
+        } else if self.state.router.can_go_back() {
+            self.navigate(cx, RouterAction::Pop);
+        }
+        cx.redraw_all();
     }
 }
 
 impl MatchEvent for App {
+    fn handle_startup(&mut self, cx: &mut Cx) {
+        // Render the cached chats of the last account right away and log in behind them.
+        if let Some(session) = Store::last_session()
+            && session.server == API_URL
+            && !session.username.is_empty()
+        {
+            log!("Resuming session of {}", session.username);
+            self.state.username = session.username.clone();
+            self.state.open_store();
+            self.state.client.authorize(cx, session.username);
+            self.navigate(cx, RouterAction::Reset(Route::new(CHATS_SCREEN)));
+        }
+    }
+
+    fn handle_shutdown(&mut self, _cx: &mut Cx) {
+        self.state.flush_drafts();
+    }
+
+    fn handle_window_geom_change(&mut self, cx: &mut Cx, e: &WindowGeomChangeEvent) {
+        self.state.window_width = e.new_geom.inner_size.x;
+        self.ui.redraw(cx);
+    }
+
+    fn handle_key_down(&mut self, cx: &mut Cx, ke: &KeyEvent) {
+        let Some(command) = self.state.shortcuts.command(ke) else {
+            return;
+        };
+        if self.state.router.current().screen == AUTH_SCREEN {
+            return;
+        }
+        match command {
+            ShortcutCommand::QuickSwitcher => {
+                self.state.show_switcher = !self.state.show_switcher;
+                cx.redraw_all();
+            }
+            ShortcutCommand::NextChat | ShortcutCommand::PreviousChat
+                if self.state.router.current().screen == CHATS_SCREEN =>
+            {
+                let step = if command == ShortcutCommand::NextChat {
+                    1
+                } else {
+                    -1
+                };
+                self.state.step_chat(cx, step);
+                cx.redraw_all();
+            }
+            ShortcutCommand::Close => self.close_overlay(cx),
+            ShortcutCommand::Back => self.navigate(cx, RouterAction::Pop),
+            // The image viewer zooms the image with the same keys.
+            ShortcutCommand::ZoomIn
+            | ShortcutCommand::ZoomOut
+            | ShortcutCommand::ZoomReset
+            | ShortcutCommand::LargerText
+            | ShortcutCommand::SmallerText
+                if self.state.lightbox.is_some() => {}
+            ShortcutCommand::ZoomIn => self.state.step_ui_scale(1),
+            ShortcutCommand::ZoomOut => self.state.step_ui_scale(-1),
+            ShortcutCommand::ZoomReset => self.state.step_ui_scale(0),
+            ShortcutCommand::LargerText => self.state.step_message_font(1),
+            ShortcutCommand::SmallerText => self.state.step_message_font(-1),
+            ShortcutCommand::FocusSearch => {
+                if self.state.router.current().screen != CHATS_SCREEN {
+                    self.navigate(cx, RouterAction::Reset(Route::new(CHATS_SCREEN)));
+                }
+                self.ui
+                    .text_input(cx, ids!(dialog_page.contacts.search.query))
+                    .set_key_focus(cx);
+            }
+            _ => {}
+        }
+    }
+
     fn handle_network_responses(&mut self, cx: &mut Cx, responses: &NetworkResponsesEvent) {
         self.state.client.handle_network_responses(cx, responses);
+        self.state.images.handle_network_responses(cx, responses);
+        if self.state.downloads.handle_network_responses(cx, responses) {
+            self.state.save_downloads();
+        }
+        #[cfg(feature = "ai")]
+        self.state.assistant.handle_network_responses(cx, responses);
     }
 
     fn handle_actions(&mut self, cx: &mut Cx, actions: &Actions) {
@@ -132,29 +210,51 @@ impl MatchEvent for App {
                         .client
                         .user_search(cx, self.state.username.clone());
                     self.load_chats(cx);
+                    self.state.remember_session();
                     log!("Authenticated successfully as {}", self.state.username);
-                    cx.action(AppAction::SwitchWindow(Screen::Dialog));
+                    cx.action(RouterAction::Reset(Route::new(CHATS_SCREEN)));
                 }
                 ShinDensenClientAction::Ready(user_id) => {
-                    self.state.current_user_id = Some(user_id);
+                    self.state.set_current_user_id(user_id);
                     self.state.client.user_get_by_id(cx, user_id);
+                    if self.connection_lost {
+                        self.connection_lost = false;
+                        self.notify(cx, Notification::connection("Connected again"));
+                    }
+                    let sent = self.state.flush_outbox(cx);
+                    if sent > 0 {
+                        self.notify(
+                            cx,
+                            Notification::success(format!("Sent {} queued messages", sent)),
+                        );
+                    }
                     log!("WebSocket READY: user_id = {}", user_id);
+                    self.ui.widget(cx, ids!(dialog_page)).redraw(cx);
                 }
                 ShinDensenClientAction::NewMessage(msg) => {
                     let sender_id = msg.sender_id;
-                    self.state.add_message(msg);
+                    if !self.state.chat_info.contains_key(&msg.chat_id) {
+                        // Someone started a chat with us, pick it up for the list.
+                        self.load_chats(cx);
+                    }
                     self.state.fetch_user(cx, sender_id);
+                    self.state.notify_message(&msg);
+                    self.state.add_message(msg);
                     self.ui.widget(cx, ids!(dialog_page)).redraw(cx);
+                    self.ui.widget(cx, ids!(toasts)).redraw(cx);
                 }
                 ShinDensenClientAction::Chats(chats) => {
+                    self.state.forget_missing_chats(&chats);
                     for chat in chats {
                         for &p_id in &chat.participants {
                             self.state.fetch_user(cx, p_id);
                         }
                         let chat_id = chat.id;
                         self.state.chat_info.insert(chat_id, chat);
-                        self.state.client.get_history(cx, chat_id);
+                        self.state.sync_history(cx, chat_id);
                     }
+                    self.state.save_chats();
+                    self.state.refresh_chat_order();
                     log!("Chats loaded: {}", self.state.chat_info.len());
                     self.ui.widget(cx, ids!(dialog_page)).redraw(cx);
                 }
@@ -162,22 +262,61 @@ impl MatchEvent for App {
                     for msg in &res.messages {
                         self.state.fetch_user(cx, msg.sender_id);
                     }
-                    self.state
-                        .msg_history
-                        .insert(res.chat_id, res.messages.clone());
+                    let count = res.messages.len();
+                    self.state.merge_history(res.chat_id, res.messages);
+                    self.state.save_history(res.chat_id);
                     log!(
-                        "History loaded for chat: {}: {} messages",
+                        "History loaded for chat: {}: {} new messages",
                         res.chat_id,
-                        res.messages.len()
+                        count
                     );
                     self.ui.widget(cx, ids!(dialog_page)).redraw(cx);
                 }
                 ShinDensenClientAction::Token(_) => {
                     // Token is handled internally by client, but we could store it if needed
                 }
+                ShinDensenClientAction::SessionExpired => {
+                    self.state.end_session(cx);
+                    self.connection_lost = false;
+                    self.notify(
+                        cx,
+                        Notification::connection("Session expired, please log in again"),
+                    );
+                    self.ui
+                        .widget(cx, ids!(main_window.body.auth_page.notice))
+                        .set_visible(cx, true);
+                    cx.action(RouterAction::Reset(Route::new(AUTH_SCREEN)));
+                }
+                ShinDensenClientAction::Disconnected => {
+                    if !self.connection_lost {
+                        self.connection_lost = true;
+                        self.notify(cx, Notification::connection("Connection lost"));
+                    }
+                }
+                ShinDensenClientAction::FileUploaded(tag, file) => {
+                    if self.state.upload_finished(cx, &tag, file) {
+                        self.ui.widget(cx, ids!(dialog_page)).redraw(cx);
+                    }
+                }
+                ShinDensenClientAction::UploadFailed(tag, err) => {
+                    if let Some(failure) = self.state.upload_failed(cx, &tag) {
+                        let message = match failure {
+                            UploadFailure::Retrying(delay) => {
+                                format!("Upload failed: {}. Trying again in {}s.", err, delay)
+                            }
+                            UploadFailure::GaveUp(filename) => format!(
+                                "Upload of {} failed: {}. Sending the message without it.",
+                                filename, err
+                            ),
+                        };
+                        error!("{}", message);
+                        self.notify(cx, Notification::error(message));
+                        self.ui.widget(cx, ids!(dialog_page)).redraw(cx);
+                    }
+                }
                 ShinDensenClientAction::UserSearchResponse(users) => {
                     if let Some(info) = users.iter().find(|u| u.username == self.state.username) {
-                        self.state.current_user_id = Some(info.id);
+                        self.state.set_current_user_id(info.id);
                         self.state.user_info.insert(info.id, info.clone());
                         log!("Current user ID identified: {}", info.id);
                     }
@@ -185,74 +324,105 @@ impl MatchEvent for App {
                     if let Some(info) = users.first() {
                         self.state.user_info.insert(info.id, info.clone());
                         // If we were initiating a chat (from new_chat screen)
-                        if self.state.screen == Screen::NewChatInit {
+                        if self.state.router.current().screen == NEW_CHAT_SCREEN {
                             self.state.client.initiate_chat(cx, info.id);
                             self.ui
                                 .widget(cx, ids!(main_window.body.new_chat.error_label))
                                 .set_visible(cx, false);
                             log!("User found: {}, initiating chat...", info.username);
                         }
-                    } else if self.state.screen == Screen::NewChatInit {
+                    } else if self.state.router.current().screen == NEW_CHAT_SCREEN {
                         self.ui
                             .widget(cx, ids!(main_window.body.new_chat.error_label))
                             .set_visible(cx, true);
                     }
+                    self.state.save_users();
                     self.ui.widget(cx, ids!(dialog_page)).redraw(cx);
                 }
                 ShinDensenClientAction::UserInfo(info) => {
                     self.state.pending_user_fetches.remove(&info.id);
                     self.state.user_info.insert(info.id, info);
+                    self.state.save_users();
                     self.ui.widget(cx, ids!(dialog_page)).redraw(cx);
                 }
                 ShinDensenClientAction::UserNotFound => {
                     error!("User not found");
                 }
                 ShinDensenClientAction::InitiateChat(res) => {
-                    self.state.open_chat_id = Some(res.chat_id);
-                    self.state.client.get_history(cx, res.chat_id);
                     self.state.client.get_chats(cx);
-                    self.new_chat_init(cx);
                     log!(
                         "Chat initiated/found: id {}, status: {}",
                         res.chat_id,
                         res.status
                     );
-                    cx.action(AppAction::SwitchWindow(Screen::Dialog));
+                    let chat_name = self.state.get_chat_name(res.chat_id);
+                    self.notify(
+                        cx,
+                        Notification::success(format!("Opened chat {}", chat_name)),
+                    );
+                    cx.action(RouterAction::Reset(chat_route(res.chat_id)));
                 }
                 ShinDensenClientAction::Error(e) => {
                     error!("Client Error: {}", e);
+                    self.notify(cx, Notification::error(e));
                 }
                 ShinDensenClientAction::NetworkError(e) => {
                     error!("Network Error: {}", e);
+                    self.notify(cx, Notification::error(e));
                 }
                 ShinDensenClientAction::None => (),
             }
+            #[cfg(feature = "ai")]
             match action.cast() {
-                AppAction::SwitchWindow(screen) => {
-                    log!("app action switch window");
-                    self.switch_screen(cx, screen);
+                AssistantAction::Finished(ReplyTarget::SummaryPart(_)) => {
+                    self.state.continue_summary(cx);
+                    self.ui.widget(cx, ids!(dialog_page)).redraw(cx);
+                }
+                AssistantAction::Finished(ReplyTarget::Chat(_)) => {
+                    self.state.save_assistant_replies();
+                    self.ui.widget(cx, ids!(dialog_page)).redraw(cx);
+                }
+                AssistantAction::Updated(_) | AssistantAction::Finished(_) => {
+                    self.ui.widget(cx, ids!(dialog_page)).redraw(cx);
                 }
-                AppAction::None => (),
+                AssistantAction::None => (),
+            }
+            match action.cast() {
+                RouterAction::None => (),
+                navigation => self.navigate(cx, navigation),
             }
         }
     }
 
     fn handle_signal(&mut self, cx: &mut Cx) {
         self.state.client.handle_signal(cx);
+        if self.state.images.handle_signal(cx) {
+            self.ui.redraw(cx);
+        }
+        if self.state.handle_pasted_images() {
+            self.ui.widget(cx, ids!(dialog_page)).redraw(cx);
+            self.ui.widget(cx, ids!(toasts)).redraw(cx);
+            self.ui.widget(cx, ids!(notification_center)).redraw(cx);
+        }
     }
 }
 
 impl AppMain for App {
     fn handle_event(&mut self, cx: &mut Cx, event: &Event) {
         self.match_event(cx, event);
+        self.state.client.handle_event(cx, event);
+        if self.state.outbox.handle_event(event) {
+            self.state.flush_outbox(cx);
+            self.ui.widget(cx, ids!(dialog_page)).redraw(cx);
+        }
         self.ui
             .handle_event(cx, event, &mut Scope::with_data(&mut self.state));
+        if self.theme_version != self.state.theme_version {
+            // Widgets restyle themselves on their next draw.
+            self.theme_version = self.state.theme_version;
+            cx.redraw_all();
+        }
     }
 }
 
-#[derive(DeJson, Debug)]
-pub struct Delta {
-    pub content: Option<String>,
-}
-
 app_main!(App);
@@ -0,0 +1,431 @@
+use crate::storage::Store;
+use crate::time::Timestamp;
+use makepad_micro_serde::*;
+use makepad_widgets::*;
+use std::collections::HashMap;
+
+const CONFIG_FILE: &str = "assistant.json";
+const REPLIES_FILE: &str = "assistant_replies.json";
+/// Mention that routes a message to the assistant.
+pub const ASSISTANT_MENTION: &str = "@assistant";
+pub const ASSISTANT_NAME: &str = "Assistant";
+
+/// Where and how to reach the OpenAI-compatible chat completions endpoint.
+/// Read from `assistant.json` in the data directory; `SHINDENSEN_AI_URL` and
+/// `SHINDENSEN_AI_KEY` override the file.
+#[derive(Clone, Debug, DeJson, SerJson, PartialEq)]
+pub struct AssistantConfig {
+    pub url: String,
+    pub model: String,
+    pub api_key: Option<String>,
+    /// How many recent chat messages are sent along as context.
+    pub context_messages: usize,
+    /// Rough size in characters of the text sent in a single summary request.
+    pub context_budget_chars: usize,
+}
+
+impl Default for AssistantConfig {
+    fn default() -> Self {
+        Self {
+            url: "http://127.0.0.1:8080/v1/chat/completions".to_string(),
+            model: "gpt-4o-mini".to_string(),
+            api_key: None,
+            context_messages: 20,
+            context_budget_chars: 12_000,
+        }
+    }
+}
+
+impl AssistantConfig {
+    pub fn load() -> Self {
+        let mut config: AssistantConfig = Store::root()
+            .and_then(|root| std::fs::read_to_string(root.join(CONFIG_FILE)).ok())
+            .and_then(|data| AssistantConfig::deserialize_json(&data).ok())
+            .unwrap_or_default();
+        if let Ok(url) = std::env::var("SHINDENSEN_AI_URL") {
+            config.url = url;
+        }
+        if let Ok(key) = std::env::var("SHINDENSEN_AI_KEY") {
+            config.api_key = Some(key);
+        }
+        config
+    }
+}
+
+#[derive(Clone, Debug, DeJson, SerJson, PartialEq)]
+pub struct CompletionMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl CompletionMessage {
+    pub fn new(role: &str, content: impl Into<String>) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.into(),
+        }
+    }
+}
+
+#[derive(SerJson, Debug)]
+struct CompletionRequest {
+    model: String,
+    stream: bool,
+    messages: Vec<CompletionMessage>,
+}
+
+#[derive(DeJson, Debug)]
+pub struct Delta {
+    pub content: Option<String>,
+}
+
+#[derive(DeJson, Debug)]
+struct ChunkChoice {
+    delta: Delta,
+}
+
+#[derive(DeJson, Debug)]
+struct CompletionChunk {
+    choices: Vec<ChunkChoice>,
+}
+
+/// What a streamed reply belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ReplyTarget {
+    /// An `@assistant` answer shown in the chat feed.
+    Chat(i64),
+    /// Summary of one chunk of a conversation too long for a single request.
+    SummaryPart(usize),
+    /// The summary shown in the side panel.
+    Summary,
+}
+
+/// A reply being streamed in, or finished.
+#[derive(Clone, Debug, Default)]
+pub struct AssistantReply {
+    pub text: String,
+    pub done: bool,
+    pub error: Option<String>,
+    /// When the reply finished, which places it among the chat messages.
+    pub time: Timestamp,
+    /// Tail of the stream that didn't end in a newline yet.
+    pending: String,
+}
+
+/// A finished chat reply as written to the account's store.
+#[derive(Clone, Debug, DeJson, SerJson, PartialEq)]
+struct SavedReply {
+    chat_id: i64,
+    time: Timestamp,
+    text: String,
+}
+
+impl AssistantReply {
+    fn finish(&mut self) {
+        self.done = true;
+        self.time = Timestamp::now();
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum AssistantAction {
+    Updated(ReplyTarget),
+    Finished(ReplyTarget),
+    #[default]
+    None,
+}
+
+/// Streams completions from the configured endpoint. Replies are kept per
+/// target so the UI can draw them while they grow.
+#[derive(Default)]
+pub struct Assistant {
+    config: AssistantConfig,
+    streams: HashMap<LiveId, ReplyTarget>,
+    replies: HashMap<ReplyTarget, Vec<AssistantReply>>,
+}
+
+impl Assistant {
+    pub fn new() -> Self {
+        Self {
+            config: AssistantConfig::load(),
+            ..Default::default()
+        }
+    }
+
+    pub fn config(&self) -> &AssistantConfig {
+        &self.config
+    }
+
+    pub fn chat_replies(&self, chat_id: i64) -> &[AssistantReply] {
+        self.replies
+            .get(&ReplyTarget::Chat(chat_id))
+            .map_or(&[], |replies| replies.as_slice())
+    }
+
+    /// The latest reply for `target`.
+    pub fn reply(&self, target: ReplyTarget) -> Option<&AssistantReply> {
+        self.replies.get(&target)?.last()
+    }
+
+    /// Drops the replies of `target`; a stream still running for it is ignored.
+    pub fn forget(&mut self, target: ReplyTarget) {
+        self.replies.remove(&target);
+        self.streams.retain(|_, t| *t != target);
+    }
+
+    pub fn clear(&mut self) {
+        self.streams.clear();
+        self.replies.clear();
+    }
+
+    /// Restores the chat replies saved by `save_replies`.
+    pub fn load_replies(&mut self, store: &Store) {
+        for saved in store
+            .load::<Vec<SavedReply>>(REPLIES_FILE)
+            .unwrap_or_default()
+        {
+            self.replies
+                .entry(ReplyTarget::Chat(saved.chat_id))
+                .or_default()
+                .push(AssistantReply {
+                    text: saved.text,
+                    done: true,
+                    time: saved.time,
+                    ..Default::default()
+                });
+        }
+    }
+
+    /// Writes the finished chat replies. Failed and unfinished ones are not kept.
+    pub fn save_replies(&self, store: &Store) {
+        let mut saved = vec![];
+        for (target, replies) in &self.replies {
+            let ReplyTarget::Chat(chat_id) = *target else {
+                continue;
+            };
+            saved.extend(
+                replies
+                    .iter()
+                    .filter(|reply| reply.done && reply.error.is_none())
+                    .map(|reply| SavedReply {
+                        chat_id,
+                        time: reply.time,
+                        text: reply.text.clone(),
+                    }),
+            );
+        }
+        if saved.is_empty() {
+            store.remove(REPLIES_FILE);
+        } else {
+            saved.sort_by_key(|reply| (reply.chat_id, reply.time));
+            store.save(REPLIES_FILE, &saved);
+        }
+    }
+
+    /// Starts a streamed completion for `messages`.
+    pub fn ask(&mut self, cx: &mut Cx, target: ReplyTarget, messages: Vec<CompletionMessage>) {
+        let body = CompletionRequest {
+            model: self.config.model.clone(),
+            stream: true,
+            messages,
+        };
+        let mut request = HttpRequest::new(self.config.url.clone(), HttpMethod::POST);
+        request.set_header("Content-Type".to_string(), "application/json".to_string());
+        if let Some(key) = &self.config.api_key {
+            request.set_header("Authorization".to_string(), format!("Bearer {}", key));
+        }
+        request.set_is_streaming();
+        request.set_body(body.serialize_json().into_bytes());
+        let request_id = LiveId::unique();
+        self.streams.insert(request_id, target);
+        self.replies
+            .entry(target)
+            .or_default()
+            .push(AssistantReply::default());
+        cx.http_request(request_id, request);
+    }
+
+    fn reply_mut(&mut self, target: ReplyTarget) -> Option<&mut AssistantReply> {
+        self.replies.get_mut(&target)?.last_mut()
+    }
+
+    pub fn handle_network_responses(&mut self, cx: &mut Cx, responses: &NetworkResponsesEvent) {
+        for event in responses {
+            match event {
+                NetworkResponse::HttpStreamResponse {
+                    request_id,
+                    response,
+                } => {
+                    let Some(&target) = self.streams.get(request_id) else {
+                        continue;
+                    };
+                    let chunk = response.get_string_body().unwrap_or_default();
+                    if let Some(reply) = self.reply_mut(target) {
+                        reply.pending.push_str(&chunk);
+                        apply_sse_lines(reply);
+                    }
+                    cx.action(AssistantAction::Updated(target));
+                }
+                NetworkResponse::HttpStreamComplete { request_id, .. } => {
+                    let Some(target) = self.streams.remove(request_id) else {
+                        continue;
+                    };
+                    if let Some(reply) = self.reply_mut(target) {
+                        reply.pending.push('\n');
+                        apply_sse_lines(reply);
+                        reply.finish();
+                    }
+                    cx.action(AssistantAction::Finished(target));
+                }
+                NetworkResponse::HttpResponse {
+                    request_id,
+                    response,
+                } => {
+                    // Errors, or the whole event stream at once where streaming isn't supported.
+                    let Some(target) = self.streams.remove(request_id) else {
+                        continue;
+                    };
+                    if let Some(reply) = self.reply_mut(target) {
+                        let body = response.get_string_body().unwrap_or_default();
+                        if response.status_code == 200 {
+                            reply.pending.push_str(&body);
+                            reply.pending.push('\n');
+                            apply_sse_lines(reply);
+                        } else {
+                            reply.error = Some(format!(
+                                "Assistant endpoint returned {}",
+                                response.status_code
+                            ));
+                        }
+                        reply.finish();
+                    }
+                    cx.action(AssistantAction::Finished(target));
+                }
+                NetworkResponse::HttpError { request_id, error } => {
+                    let Some(target) = self.streams.remove(request_id) else {
+                        continue;
+                    };
+                    if let Some(reply) = self.reply_mut(target) {
+                        reply.error = Some(format!("Assistant unreachable: {:?}", error.message));
+                        reply.finish();
+                    }
+                    cx.action(AssistantAction::Finished(target));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Messages asking the assistant to answer `prompt`, a "name: message" line,
+/// in the chat `chat_name`, with its recent `transcript` lines as context.
+pub fn chat_prompt(
+    chat_name: &str,
+    transcript: &[String],
+    prompt: String,
+) -> Vec<CompletionMessage> {
+    let mut messages = vec![CompletionMessage::new(
+        "system",
+        format!(
+            "You are {}, a helpful participant of the chat \"{}\". Lines are \"name: message\".",
+            ASSISTANT_NAME, chat_name
+        ),
+    )];
+    messages.extend(
+        transcript
+            .iter()
+            .map(|line| CompletionMessage::new("user", line.clone())),
+    );
+    messages.push(CompletionMessage::new("user", prompt));
+    messages
+}
+
+/// Consumes complete `data: {...}` lines of the server-sent event stream.
+fn apply_sse_lines(reply: &mut AssistantReply) {
+    while let Some(end) = reply.pending.find('\n') {
+        let line: String = reply.pending.drain(..=end).collect();
+        let Some(data) = line.trim().strip_prefix("data:") else {
+            continue;
+        };
+        let data = data.trim();
+        if data == "[DONE]" {
+            continue;
+        }
+        match CompletionChunk::deserialize_json(data) {
+            Ok(chunk) => {
+                for choice in chunk.choices {
+                    if let Some(content) = choice.delta.content {
+                        reply.text.push_str(&content);
+                    }
+                }
+            }
+            Err(err) => error!("Skipping malformed assistant chunk: {:?}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(reply: &mut AssistantReply, chunk: &str) {
+        reply.pending.push_str(chunk);
+        apply_sse_lines(reply);
+    }
+
+    #[test]
+    fn sse_lines_append_deltas() {
+        let mut reply = AssistantReply::default();
+        feed(
+            &mut reply,
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\n\
+             data: {\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}\n\n\
+             data: [DONE]\n\n",
+        );
+        assert_eq!(reply.text, "Hello");
+        assert!(reply.pending.is_empty());
+    }
+
+    #[test]
+    fn sse_lines_wait_for_the_end_of_a_line() {
+        let mut reply = AssistantReply::default();
+        feed(&mut reply, "data: {\"choices\":[{\"delta\":{\"con");
+        assert_eq!(reply.text, "");
+        feed(&mut reply, "tent\":\"Hi\"}}]}\n");
+        assert_eq!(reply.text, "Hi");
+        assert!(reply.pending.is_empty());
+    }
+
+    #[test]
+    fn sse_lines_skip_other_and_malformed_lines() {
+        let mut reply = AssistantReply::default();
+        feed(
+            &mut reply,
+            ": keep-alive\n\
+             event: message\n\
+             data: {not json}\n\
+             data: {\"choices\":[{\"delta\":{}}]}\n\
+             data: {\"choices\":[{\"delta\":{\"content\":\"ok\"}}]}\n",
+        );
+        assert_eq!(reply.text, "ok");
+    }
+
+    #[test]
+    fn chat_prompt_ends_with_the_question() {
+        let transcript = vec!["ann: hi".to_string(), "bob: hello".to_string()];
+        let messages = chat_prompt("Team", &transcript, "me: @assistant help".to_string());
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0].role, "system");
+        assert!(messages[0].content.contains(ASSISTANT_NAME));
+        assert!(messages[0].content.contains("\"Team\""));
+        assert_eq!(
+            messages[1..],
+            [
+                CompletionMessage::new("user", "ann: hi"),
+                CompletionMessage::new("user", "bob: hello"),
+                CompletionMessage::new("user", "me: @assistant help"),
+            ]
+        );
+        assert_eq!(chat_prompt("Team", &[], "me: hi".to_string()).len(), 2);
+    }
+}
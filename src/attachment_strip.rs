@@ -0,0 +1,155 @@
+use crate::images::ImageState;
+use crate::state::*;
+use crate::theme::StyledRows;
+use makepad_widgets::*;
+
+script_mod! {
+    use mod.prelude.widgets.*
+    use mod.widgets.*
+
+    mod.widgets.AttachmentStrip = #(AttachmentStrip::register_widget(vm)) {
+        width: Fill
+        height: Fit
+        padding: Inset { left: 10.0, right: 10.0 }
+        strip := PortalList{
+            width: Fill
+            height: 84.0
+            flow: Right
+            chip := RoundedView {
+                width: 150.0
+                height: Fill
+                flow: Down
+                spacing: 4.0
+                margin: Inset { right: 6.0 }
+                padding: Inset { top: 6.0, right: 6.0, bottom: 6.0, left: 6.0 }
+                draw_bg +: {
+                    color: mod.sd_theme.surface
+                    border_radius: 4.0
+                }
+                preview := View {
+                    width: Fill
+                    height: 40.0
+                    align: Align { x: 0.5, y: 0.5 }
+                    icon := Label {
+                        text: "📎"
+                        draw_text +: { text_style +: { font_size: 18.0 } }
+                    }
+                    image := Image {
+                        visible: false
+                        width: Fill
+                        height: Fill
+                    }
+                }
+                footer := View {
+                    width: Fill
+                    height: Fit
+                    flow: Right
+                    align: Align { y: 0.5 }
+                    name := Label {
+                        width: Fill
+                        text: ""
+                        draw_text +: {
+                            color: mod.sd_theme.text
+                            text_style +: { font_size: 9.0 }
+                        }
+                    }
+                    remove := SDButton {
+                        padding: Inset { top: 2.0, right: 6.0, bottom: 2.0, left: 6.0 }
+                        text: "x"
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Files added to the composer of the open chat, with a thumbnail for
+/// images and a button to take each one out again before sending.
+#[derive(Script, ScriptHook, Widget)]
+struct AttachmentStrip {
+    #[deref]
+    view: View,
+    #[rust]
+    rows: StyledRows,
+}
+
+impl Widget for AttachmentStrip {
+    fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        let state = scope.data.get::<State>().expect("State not found.");
+        let Some(chat_id) = state.open_chat_id else {
+            return DrawStep::done();
+        };
+        if state.attachments(chat_id).is_empty() {
+            return DrawStep::done();
+        }
+        // Local images seen for the first time, decoded once the strip is drawn.
+        let mut wanted_images = vec![];
+        while let Some(item) = self.view.draw_walk(cx, scope, walk).step() {
+            if let Some(mut list) = item.as_portal_list().borrow_mut() {
+                let state = scope.data.get::<State>().expect("State not found.");
+                let attachments = state.attachments(chat_id);
+                list.set_item_range(cx, 0, attachments.len());
+                while let Some(item_id) = list.next_visible_item(cx) {
+                    let Some(attachment) = attachments.get(item_id) else {
+                        continue;
+                    };
+                    let item = list.item(cx, item_id, id!(chip));
+                    if self.rows.needs_style(state.theme_version, &item) {
+                        let (name, remove) = (
+                            item.widget(cx, ids!(footer.name)),
+                            item.widget(cx, ids!(footer.remove)),
+                        );
+                        state.theme.style_background(cx, &item, state.theme.surface);
+                        state.theme.style_label(cx, &name, state.theme.text);
+                        state.theme.style_button(cx, &remove);
+                    }
+                    item.label(cx, ids!(footer.name))
+                        .set_text(cx, &attachment.filename);
+                    let image = item.image(cx, ids!(preview.image));
+                    let texture = match state.images.get_local(&attachment.path) {
+                        Some(ImageState::Ready { texture, .. }) => Some(texture.clone()),
+                        None if attachment.is_image() => {
+                            wanted_images.push(attachment.path.clone());
+                            None
+                        }
+                        _ => None,
+                    };
+                    let shown = texture.is_some();
+                    if shown {
+                        image.set_texture(cx, texture);
+                    }
+                    image.set_visible(cx, shown);
+                    item.label(cx, ids!(preview.icon)).set_visible(cx, !shown);
+                    item.draw_all_unscoped(cx);
+                }
+            }
+        }
+        if let Some(state) = scope.data.get_mut::<State>() {
+            for path in wanted_images {
+                state.images.request_local(&path);
+            }
+        }
+        DrawStep::done()
+    }
+
+    fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
+        let actions = cx.capture_actions(|cx| {
+            self.view.handle_event(cx, event, scope);
+        });
+        let state = scope.data.get_mut::<State>().expect("State not found.");
+        if let Some(chat_id) = state.open_chat_id {
+            let portal_list = self.view.portal_list(cx, ids!(strip));
+            for (item_id, _) in portal_list.items_with_actions(&actions) {
+                if portal_list
+                    .item(cx, item_id, id!(chip))
+                    .button(cx, ids!(footer.remove))
+                    .clicked(&actions)
+                {
+                    state.remove_attachment(chat_id, item_id);
+                    cx.redraw_all();
+                }
+            }
+        }
+        cx.extend_actions(actions);
+    }
+}
@@ -1,4 +1,6 @@
+use crate::router::Screen;
 use crate::state::*;
+use crate::theme::{Theme, ThemeStamp};
 use makepad_widgets::*;
 
 script_mod! {
@@ -6,16 +8,16 @@ script_mod! {
     use mod.widgets.*
 
     mod.widgets.LoginForm = #(LoginForm::register_widget(vm)) {
-        SolidView {
+        background := SolidView {
             width: Fill
             height: Fill
             flow: Down
             align: Align { x: 0.5, y: 0.5 }
             show_bg: true
             draw_bg +: {
-                color: #26242b
+                color: mod.sd_theme.background
             }
-            SDLabel{
+            prompt := SDLabel{
                 text: "Enter your nickname:"
                 draw_text +: {
                     text_style +: {
@@ -29,24 +31,57 @@ script_mod! {
             enter := SDButton{
                 text: "Start"
             }
+            notice := AlertField{
+                alert_text +: {
+                    text: "Your session has expired, please log in again."
+                }
+            }
         }
     }
 }
 
+/// Id of the login screen, where the app starts.
+pub const AUTH_SCREEN: LiveId = live_id!(auth);
+
+/// Logging in with a nickname.
+pub struct LoginScreen;
+
+impl Screen for LoginScreen {
+    fn id(&self) -> LiveId {
+        AUTH_SCREEN
+    }
+
+    fn page(&self) -> &'static [LiveId] {
+        ids!(auth_page)
+    }
+}
+
 #[derive(Script, ScriptHook, Widget)]
 struct LoginForm {
     #[deref]
     view: View,
+    #[rust]
+    theme: ThemeStamp,
 }
 
 impl LoginForm {
+    fn apply_theme(&self, cx: &mut Cx, theme: &Theme) {
+        theme.style_background(cx, &self.widget(cx, ids!(background)), theme.background);
+        theme.style_label(cx, &self.widget(cx, ids!(prompt)), theme.text);
+        theme.style_input(cx, &self.widget(cx, ids!(nickname)));
+        theme.style_alert(cx, &self.widget(cx, ids!(notice)));
+        theme.style_button(cx, &self.widget(cx, ids!(enter)));
+    }
+
     fn set_user(&mut self, cx: &mut Cx, scope: &mut Scope) {
         let state = scope.data.get_mut::<State>().expect("State not found.");
         let input = self.text_input(cx, ids!(nickname));
         let nick = input.text().trim().to_owned();
         if !nick.is_empty() {
             input.set_text(cx, "");
+            self.widget(cx, ids!(notice)).set_visible(cx, false);
             state.username = nick.to_owned();
+            state.open_store();
             state.client.authorize(cx, nick);
         }
     }
@@ -54,6 +89,10 @@ impl LoginForm {
 
 impl Widget for LoginForm {
     fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        let state = scope.data.get::<State>().expect("State not found.");
+        if self.theme.update(state.theme_version) {
+            self.apply_theme(cx, &state.theme);
+        }
         self.view.draw_walk(cx, scope, walk)
     }
 
@@ -0,0 +1,165 @@
+use std::io::ErrorKind;
+use std::process::{Command, Stdio};
+
+/// Clipboard types that mean the copy was text.
+const TEXT_TYPES: &[&str] = &["text/plain", "UTF8_STRING", "STRING", "TEXT"];
+
+/// PNG data on the system clipboard. Makepad only hands pastes to widgets as
+/// text, so the image is read through the platform's own tools: `wl-paste`
+/// or `xclip` on Linux, `osascript` on macOS and PowerShell on Windows.
+/// `Ok(None)` when the clipboard holds no image, or text as well, which the
+/// text input pastes already.
+pub fn read_image() -> Result<Option<Vec<u8>>, String> {
+    if cfg!(target_os = "macos") {
+        read_image_macos()
+    } else if cfg!(windows) {
+        read_image_windows()
+    } else {
+        read_image_linux()
+    }
+}
+
+fn read_image_linux() -> Result<Option<Vec<u8>>, String> {
+    let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+    let types = if wayland {
+        run("wl-paste", &["--list-types"])?
+    } else {
+        run("xclip", &["-selection", "clipboard", "-t", "TARGETS", "-o"])?
+    };
+    let types = String::from_utf8_lossy(&types);
+    if !offers_only_image(types.lines(), "image/png") {
+        return Ok(None);
+    }
+    let data = if wayland {
+        run("wl-paste", &["--no-newline", "--type", "image/png"])?
+    } else {
+        run(
+            "xclip",
+            &["-selection", "clipboard", "-t", "image/png", "-o"],
+        )?
+    };
+    Ok(Some(data))
+}
+
+fn read_image_macos() -> Result<Option<Vec<u8>>, String> {
+    // Lists the types as "«class PNGf», 1234, string, 5, ...".
+    let info = run("osascript", &["-e", "clipboard info"])?;
+    let info = String::from_utf8_lossy(&info);
+    let types = info
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.chars().all(|c| c.is_ascii_digit()))
+        .map(|part| match part {
+            "string" | "Unicode text" | "«class utf8»" => "text/plain",
+            "«class PNGf»" => "image/png",
+            other => other,
+        });
+    if !offers_only_image(types, "image/png") {
+        return Ok(None);
+    }
+    let out = run("osascript", &["-e", "the clipboard as «class PNGf»"])?;
+    applescript_data(&String::from_utf8_lossy(&out))
+        .map(Some)
+        .ok_or_else(|| "unreadable clipboard image".to_string())
+}
+
+fn read_image_windows() -> Result<Option<Vec<u8>>, String> {
+    let path = std::env::temp_dir().join(format!("shindensen-paste-{}.png", std::process::id()));
+    let script = format!(
+        "Add-Type -AssemblyName System.Windows.Forms; Add-Type -AssemblyName System.Drawing; \
+         if ([Windows.Forms.Clipboard]::ContainsImage() -and -not [Windows.Forms.Clipboard]::ContainsText()) {{ \
+         [Windows.Forms.Clipboard]::GetImage().Save('{}', [Drawing.Imaging.ImageFormat]::Png) }}",
+        path.display().to_string().replace('\'', "''")
+    );
+    let _ = std::fs::remove_file(&path);
+    run("powershell", &["-NoProfile", "-STA", "-Command", &script])?;
+    match std::fs::read(&path) {
+        Ok(data) => {
+            let _ = std::fs::remove_file(&path);
+            Ok(Some(data))
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Runs a clipboard tool and returns what it printed.
+fn run(program: &str, args: &[&str]) -> Result<Vec<u8>, String> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|err| match err.kind() {
+            ErrorKind::NotFound => format!("{} is not installed", program),
+            _ => format!("{}: {}", program, err),
+        })?;
+    if !output.status.success() {
+        return Err(format!("{} failed with {}", program, output.status));
+    }
+    Ok(output.stdout)
+}
+
+/// Whether the clipboard `types` include `image` and no text.
+fn offers_only_image<'a>(types: impl IntoIterator<Item = &'a str>, image: &str) -> bool {
+    let mut has_image = false;
+    for kind in types {
+        let kind = kind.trim();
+        // "text/plain;charset=utf-8" counts as text too.
+        let base = kind.split(';').next().unwrap_or(kind).trim();
+        if TEXT_TYPES.contains(&base) {
+            return false;
+        }
+        has_image |= base == image;
+    }
+    has_image
+}
+
+/// Bytes of an AppleScript data literal like `«data PNGf89504E47»`.
+fn applescript_data(text: &str) -> Option<Vec<u8>> {
+    let hex = text
+        .trim()
+        .strip_prefix("«data ")?
+        .strip_suffix('»')?
+        .get(4..)?;
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_pure_images_count() {
+        assert!(offers_only_image(["TARGETS", "image/png"], "image/png"));
+        assert!(offers_only_image(["image/png", "image/bmp"], "image/png"));
+        assert!(!offers_only_image(
+            ["image/png", "UTF8_STRING"],
+            "image/png"
+        ));
+        assert!(!offers_only_image(
+            ["image/png", "text/plain;charset=utf-8"],
+            "image/png"
+        ));
+        assert!(!offers_only_image(["image/jpeg"], "image/png"));
+        assert!(!offers_only_image([], "image/png"));
+    }
+
+    #[test]
+    fn reads_applescript_data_literals() {
+        assert_eq!(
+            applescript_data("«data PNGf89504E47»\n"),
+            Some(vec![0x89, 0x50, 0x4E, 0x47])
+        );
+        assert_eq!(applescript_data("«data PNGf8950»"), Some(vec![0x89, 0x50]));
+        assert_eq!(applescript_data("«data PNGf895»"), None);
+        assert_eq!(applescript_data("«data PNGfzz»"), None);
+        assert_eq!(applescript_data("missing value"), None);
+    }
+}
@@ -0,0 +1,237 @@
+use std::ops::Range;
+
+const INDENT: &str = "    ";
+
+/// Replaces the byte range `selection` of `text` with `insert`. Returns the
+/// new text and the byte offset right after the inserted part, where the
+/// cursor goes. Offsets past the end or inside a character are pulled back.
+pub fn insert_at(text: &str, selection: Range<usize>, insert: &str) -> (String, usize) {
+    let floor = |mut index: usize| {
+        index = index.min(text.len());
+        while !text.is_char_boundary(index) {
+            index -= 1;
+        }
+        index
+    };
+    let start = floor(selection.start.min(selection.end));
+    let end = floor(selection.start.max(selection.end));
+    let mut out = String::with_capacity(text.len() + insert.len());
+    out.push_str(&text[..start]);
+    out.push_str(insert);
+    out.push_str(&text[end..]);
+    (out, start + insert.len())
+}
+
+/// Text to insert when Enter is pressed in code-block mode, given the text
+/// before the cursor: a newline plus the indentation of the current line,
+/// one level deeper after an opener.
+pub fn code_newline(text: &str) -> String {
+    let line = text.rsplit('\n').next().unwrap_or("");
+    let indent: String = line
+        .chars()
+        .take_while(|c| *c == ' ' || *c == '\t')
+        .collect();
+    let opens = matches!(line.trim_end().chars().last(), Some('{' | '(' | '[' | ':'));
+    if opens {
+        format!("\n{}{}", indent, INDENT)
+    } else {
+        format!("\n{}", indent)
+    }
+}
+
+pub fn code_indent() -> &'static str {
+    INDENT
+}
+
+/// Wraps composer text in a fenced block, unless the user already did.
+pub fn wrap_code_block(text: &str, lang: &str) -> String {
+    if text.trim_start().starts_with("```") {
+        return text.to_string();
+    }
+    format!("```{}\n{}\n```", lang, text.trim_end_matches('\n'))
+}
+
+/// Cuts `text` into messages of at most `max` characters, preferring to break
+/// after a newline, then after whitespace, and only then mid-word.
+pub fn split_message(text: &str, max: usize) -> Vec<String> {
+    let max = max.max(1);
+    let mut parts = vec![];
+    let mut rest = text.trim();
+    while rest.chars().count() > max {
+        let limit = rest
+            .char_indices()
+            .nth(max)
+            .map_or(rest.len(), |(index, _)| index);
+        let head = &rest[..limit];
+        let cut = if rest[limit..].starts_with(char::is_whitespace) {
+            Some(limit)
+        } else {
+            head.rfind('\n').or_else(|| head.rfind(char::is_whitespace))
+        }
+        .filter(|&index| index > 0)
+        .unwrap_or(limit);
+        parts.push(rest[..cut].trim_end().to_string());
+        rest = rest[cut..].trim_start();
+    }
+    if !rest.is_empty() {
+        parts.push(rest.to_string());
+    }
+    parts
+}
+
+/// Rough number of lines the composer needs for `text`, wrapping at
+/// `line_chars` characters.
+pub fn visual_lines(text: &str, line_chars: usize) -> usize {
+    let line_chars = line_chars.max(1);
+    text.split('\n')
+        .map(|line| line.chars().count().div_ceil(line_chars).max(1))
+        .sum()
+}
+
+/// Local files named by pasted text: one absolute path or `file://` URL per
+/// line. `None` if any line is something else, so ordinary text pastes stay text.
+pub fn pasted_file_paths(text: &str) -> Option<Vec<String>> {
+    let lines: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    if lines.is_empty() {
+        return None;
+    }
+    lines
+        .into_iter()
+        .map(|line| {
+            if let Some(rest) = line.strip_prefix("file://") {
+                // `file:///home/x` or `file://localhost/home/x`.
+                let path = rest.strip_prefix("localhost").unwrap_or(rest);
+                return path.starts_with('/').then(|| percent_decode(path));
+            }
+            let windows_drive = line.len() > 2
+                && line.as_bytes()[0].is_ascii_alphabetic()
+                && line[1..].starts_with(":\\");
+            (line.starts_with('/') || windows_drive).then(|| line.to_string())
+        })
+        .collect()
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = text
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_at_replaces_the_selection() {
+        assert_eq!(
+            insert_at("hello world", 5..5, ","),
+            ("hello, world".into(), 6)
+        );
+        assert_eq!(
+            insert_at("hello world", 6..11, "there"),
+            ("hello there".into(), 11)
+        );
+        assert_eq!(
+            insert_at("hello world", Range { start: 11, end: 6 }, "there"),
+            ("hello there".into(), 11)
+        );
+    }
+
+    #[test]
+    fn insert_at_clamps_bad_offsets() {
+        assert_eq!(insert_at("ab", 10..10, "c"), ("abc".into(), 3));
+        // Inside the two-byte "é", pulled back to its start.
+        assert_eq!(insert_at("é", 1..1, "x"), ("xé".into(), 1));
+    }
+
+    #[test]
+    fn code_newline_keeps_and_deepens_indentation() {
+        assert_eq!(code_newline("let a = 1;"), "\n");
+        assert_eq!(code_newline("fn main() {\n    let a = 1;"), "\n    ");
+        assert_eq!(code_newline("    if a {"), "\n        ");
+        assert_eq!(code_newline("def f():"), "\n    ");
+    }
+
+    #[test]
+    fn wrap_code_block_adds_a_fence_once() {
+        assert_eq!(wrap_code_block("x = 1\n", "py"), "```py\nx = 1\n```");
+        assert_eq!(wrap_code_block("x", ""), "```\nx\n```");
+        assert_eq!(wrap_code_block("```\nx\n```", "py"), "```\nx\n```");
+    }
+
+    #[test]
+    fn split_message_prefers_newlines_then_spaces() {
+        assert_eq!(
+            split_message("one two\nthree four", 12),
+            vec!["one two", "three four"]
+        );
+        assert_eq!(
+            split_message("aaa bbb ccc ddd", 8),
+            vec!["aaa bbb", "ccc ddd"]
+        );
+        assert_eq!(split_message("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+    }
+
+    #[test]
+    fn split_message_counts_characters_not_bytes() {
+        assert_eq!(split_message("ääää", 2), vec!["ää", "ää"]);
+        assert_eq!(split_message("  short  ", 100), vec!["short"]);
+        assert!(split_message("   ", 10).is_empty());
+    }
+
+    #[test]
+    fn visual_lines_wraps_long_lines() {
+        assert_eq!(visual_lines("", 10), 1);
+        assert_eq!(visual_lines("short", 10), 1);
+        assert_eq!(visual_lines("a\n\nb", 10), 3);
+        assert_eq!(visual_lines(&"x".repeat(25), 10), 3);
+        assert_eq!(visual_lines("abc", 0), 3);
+    }
+
+    #[test]
+    fn pasted_file_paths_reads_paths_and_file_urls() {
+        assert_eq!(
+            pasted_file_paths("/home/a/x.png\n\n  /tmp/y.txt \n"),
+            Some(vec!["/home/a/x.png".to_string(), "/tmp/y.txt".to_string()])
+        );
+        assert_eq!(
+            pasted_file_paths("file:///home/a/my%20photo.png\nfile://localhost/tmp/%C3%A4.txt"),
+            Some(vec![
+                "/home/a/my photo.png".to_string(),
+                "/tmp/ä.txt".to_string()
+            ])
+        );
+        assert_eq!(
+            pasted_file_paths("C:\\Users\\a\\x.png"),
+            Some(vec!["C:\\Users\\a\\x.png".to_string()])
+        );
+    }
+
+    #[test]
+    fn pasted_file_paths_leaves_ordinary_text_alone() {
+        assert_eq!(pasted_file_paths(""), None);
+        assert_eq!(pasted_file_paths("  \n "), None);
+        assert_eq!(pasted_file_paths("hello"), None);
+        assert_eq!(pasted_file_paths("/tmp/x.png\nsee above"), None);
+        assert_eq!(pasted_file_paths("file://server/share/x"), None);
+        assert_eq!(pasted_file_paths("C:"), None);
+    }
+}
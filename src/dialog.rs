@@ -1,5 +1,28 @@
+#[cfg(feature = "ai")]
+use crate::assistant::{ASSISTANT_MENTION, ASSISTANT_NAME};
+use crate::composer::{
+    code_indent, code_newline, insert_at, pasted_file_paths, split_message, visual_lines,
+    wrap_code_block,
+};
+use crate::downloads::DownloadStatus;
+use crate::emoji::expand_shortcode_at;
+use crate::emoji_picker::{EmojiPicker, EmojiPickerAction};
+use crate::images::{ImageState, THUMB_MAX_HEIGHT, THUMB_MAX_WIDTH, fit_size};
+use crate::markdown::{Segment, sanitize_markdown, split_code_blocks};
+use crate::mentions::{complete_mention, mention_query};
+use crate::notifications::Notification;
+use crate::router::{Route, RouterAction, Screen};
+use crate::search_panel::SearchPanelAction;
+use crate::settings_panel::SETTINGS_SCREEN;
+use crate::shindensen_client::{ChatMessage, FileMetadata};
 use crate::state::*;
+use crate::theme::{StyledRows, Theme, ThemeStamp};
+use crate::time::{Timestamp, day_label};
+use chrono::NaiveDate;
 use makepad_widgets::*;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
 script_mod! {
     use mod.prelude.widgets.*
@@ -16,32 +39,277 @@ script_mod! {
                 flow: Down
                 user_msg := Post{}
             }
+            post_part := CachedView{
+                flow: Down
+                user_msg := Post{
+                    margin: Inset { top: 0.0, right: 10.0, bottom: 10.0, left: 10.0 }
+                    body +: { username +: { visible: false } }
+                }
+            }
+            code_block := View{
+                width: Fill
+                height: Fit
+                flow: Down
+                margin: Inset { top: 0.0, right: 10.0, bottom: 10.0, left: 20.0 }
+                header := View{
+                    width: Fill
+                    height: Fit
+                    flow: Right
+                    align: Align { y: 0.5 }
+                    lang := Label {
+                        width: Fill
+                        text: ""
+                        draw_text +: {
+                            color: mod.sd_theme.text_muted
+                            text_style +: { font_size: 9.0 }
+                        }
+                    }
+                    copy := SDButton {
+                        padding: Inset { top: 2.0, right: 6.0, bottom: 2.0, left: 6.0 }
+                        text: "Copy"
+                    }
+                }
+                ScrollXView{
+                    width: Fill
+                    height: Fit
+                    code := CodeView{}
+                }
+            }
+            attachment := View{
+                width: Fill
+                height: Fit
+                flow: Down
+                spacing: 4.0
+                margin: Inset { top: 0.0, right: 10.0, bottom: 10.0, left: 20.0 }
+                info := View{
+                    width: Fill
+                    height: Fit
+                    flow: Right
+                    align: Align { y: 0.5 }
+                    spacing: 8.0
+                    name := Label {
+                        width: Fit
+                        text: ""
+                        draw_text +: {
+                            color: mod.sd_theme.text
+                            text_style +: { font_size: 10.0 }
+                        }
+                    }
+                    size := Label {
+                        width: Fit
+                        text: ""
+                        draw_text +: {
+                            color: mod.sd_theme.text_muted
+                            text_style +: { font_size: 9.0 }
+                        }
+                    }
+                    status := Label {
+                        width: Fit
+                        text: ""
+                        draw_text +: {
+                            color: mod.sd_theme.text_muted
+                            text_style +: { font_size: 9.0 }
+                        }
+                    }
+                    progress := Label {
+                        width: Fit
+                        text: ""
+                        draw_text +: {
+                            color: mod.sd_theme.text_muted
+                            text_style +: { font_size: 9.0 }
+                        }
+                    }
+                    download := SDButton {
+                        padding: Inset { top: 2.0, right: 6.0, bottom: 2.0, left: 6.0 }
+                        text: "Download"
+                    }
+                    cancel := SDButton {
+                        visible: false
+                        padding: Inset { top: 2.0, right: 6.0, bottom: 2.0, left: 6.0 }
+                        text: "Cancel"
+                    }
+                    open_folder := SDButton {
+                        visible: false
+                        padding: Inset { top: 2.0, right: 6.0, bottom: 2.0, left: 6.0 }
+                        text: "Open folder"
+                    }
+                }
+                thumb := View{
+                    visible: false
+                    width: 320.0
+                    height: 240.0
+                    cursor: MouseCursor.Hand
+                    image := Image {
+                        width: Fill
+                        height: Fill
+                    }
+                }
+            }
+            day_separator := View{
+                width: Fill
+                height: Fit
+                align: Align { x: 0.5, y: 0.5 }
+                padding: Inset { top: 6.0, bottom: 6.0 }
+                label := Label {
+                    text: ""
+                    draw_text +: {
+                        color: mod.sd_theme.text_muted
+                        text_style +: { font_size: 10.0 }
+                    }
+                }
+            }
         }
     }
 
     mod.widgets.DialogPage = #(DialogPage::register_widget(vm)) {
         MessageListPage {
             contacts +: {
-                ChatList{}
+                header := View {
+                    width: Fill
+                    height: Fit
+                    flow: Right
+                    spacing: 6.0
+                    notifications_btn := SDButton{width: Fill, text: "Notifications"}
+                    mentions_btn := SDButton{width: Fill, text: "Mentions"}
+                    settings_btn := SDButton{text: "⚙"}
+                    collapse_btn := SDButton{text: "«"}
+                }
+                search := SearchPanel{}
+                chats := ChatList{}
             }
             dialog +: {
+                chat_header := View {
+                    visible: false
+                    width: Fill
+                    height: Fit
+                    flow: Right
+                    align: Align { y: 0.5 }
+                    spacing: 8.0
+                    padding: Inset { top: 6.0, left: 10.0 }
+                    back := SDButton{text: "< Chats"}
+                    title := H4 { width: Fill, text: "" }
+                }
                 news_feed := NewsFeed{}
+                long_text := View {
+                    visible: false
+                    width: Fill
+                    height: Fit
+                    flow: Right
+                    align: Align { y: 0.5 }
+                    spacing: 6.0
+                    padding: Inset { left: 10.0 }
+                    info := Label {
+                        width: Fill
+                        text: ""
+                        draw_text +: {
+                            color: mod.sd_theme.text_muted
+                            text_style +: { font_size: 10.0 }
+                        }
+                    }
+                    split := SDButton{text: "Split"}
+                    attach := SDButton{text: "Send as file"}
+                    keep_editing := SDButton{text: "Keep editing"}
+                }
+                mention_popup := View {
+                    visible: false
+                    width: Fill
+                    height: Fit
+                    flow: Right
+                    spacing: 6.0
+                    padding: Inset { left: 10.0 }
+                    mention0 := SDButton{text: ""}
+                    mention1 := SDButton{text: ""}
+                    mention2 := SDButton{text: ""}
+                    mention3 := SDButton{text: ""}
+                    mention4 := SDButton{text: ""}
+                }
+                emoji_picker := EmojiPicker{
+                    margin: Inset { left: 10.0 }
+                }
+                drop_hint := View {
+                    visible: false
+                    width: Fill
+                    height: Fit
+                    align: Align { x: 0.5 }
+                    padding: Inset { top: 10.0, bottom: 10.0 }
+                    show_bg: true
+                    draw_bg +: {
+                        color: mod.sd_theme.surface
+                    }
+                    text := Label {
+                        text: "Drop files to attach them"
+                        draw_text +: {
+                            color: mod.sd_theme.text
+                            text_style +: { font_size: 11.0 }
+                        }
+                    }
+                }
+                attachment_strip := AttachmentStrip{}
                 input_bar := View {
                     width: Fill
                     height: Fit
                     flow: Right
-                    View {
+                    align: Align { y: 1.0 }
+                    composer := View {
                         width: Fill
-                        height: 150.0
+                        height: 48.0
                         scroll_bars := ScrollBars{}
                         msg := SDTextInput{
                             width: Fill
-                            empty_text: "Type a message..."
+                            empty_text: "Type a message... (Shift+Enter for a new line)"
                         }
                     }
+                    counter := Label {
+                        width: Fit
+                        margin: Inset { bottom: 16.0 }
+                        text: ""
+                        draw_text +: {
+                            color: mod.sd_theme.text_muted
+                            text_style +: { font_size: 9.0 }
+                        }
+                    }
+                    code_lang := SDTextInput{
+                        visible: false
+                        width: 100.0
+                        empty_text: "language"
+                    }
+                    code_mode := SDButton{text: "</>"}
+                    emoji := SDButton{text: "😊"}
                     send := SDButton{text: "Send"}
                 }
             }
+            summary := SummaryPanel{}
+        }
+    }
+}
+
+/// Id of the chat list screen. Its routes may carry an `OpenChat`.
+pub const CHATS_SCREEN: LiveId = live_id!(chats);
+
+/// Parameters of a chat list route: the chat to open.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OpenChat(pub i64);
+
+/// Route to the chat list with `chat_id` open.
+pub fn chat_route(chat_id: i64) -> Route {
+    Route::with_params(CHATS_SCREEN, OpenChat(chat_id))
+}
+
+/// The chat list and the open chat.
+pub struct ChatsScreen;
+
+impl Screen for ChatsScreen {
+    fn id(&self) -> LiveId {
+        CHATS_SCREEN
+    }
+
+    fn page(&self) -> &'static [LiveId] {
+        ids!(dialog_page)
+    }
+
+    fn enter(&self, cx: &mut Cx, _page: &WidgetRef, state: &mut State, route: &Route) {
+        if let Some(OpenChat(chat_id)) = route.params() {
+            state.open_chat(cx, *chat_id);
         }
     }
 }
@@ -50,46 +318,695 @@ script_mod! {
 struct DialogPage {
     #[deref]
     view: View,
+    /// Chat whose draft is currently in the composer.
+    #[rust]
+    draft_chat: Option<i64>,
+    /// Enter inserts an indented newline and the message is sent as a code block.
+    #[rust]
+    code_mode: bool,
+    /// Lines the composer is currently sized for.
+    #[rust]
+    composer_lines: usize,
+    /// Usernames offered for the mention being typed, in button order.
+    #[rust]
+    mention_suggestions: Vec<String>,
+    /// Whether the emoji picker above the composer is open.
+    #[rust]
+    emoji_open: bool,
+    /// Single-pane mode and chat list width the panes were last sized for.
+    #[rust]
+    pane_sizing: Option<(bool, f64)>,
+    /// Whether the chat list header was last laid out collapsed.
+    #[rust]
+    header_collapsed: Option<bool>,
+    /// Pointer x and chat list width where a drag of the splitter started.
+    #[rust]
+    sidebar_drag: Option<(f64, f64)>,
+    #[rust]
+    theme: ThemeStamp,
 }
 
+const MAX_MENTION_SUGGESTIONS: usize = 5;
+
+/// Composer sizing: characters per wrapped line, pixels per line, and the
+/// height it stops growing at and scrolls instead.
+const COMPOSER_LINE_CHARS: usize = 70;
+const COMPOSER_LINE_HEIGHT: f64 = 20.0;
+const COMPOSER_PADDING: f64 = 28.0;
+const COMPOSER_MAX_HEIGHT: f64 = 200.0;
+
 impl DialogPage {
-    pub fn send_message_ws(&mut self, scope: &mut Scope, cx: &mut Cx) {
+    /// Styles the page itself; the panels in it style their own parts.
+    fn apply_theme(&self, cx: &mut Cx, theme: &Theme) {
+        theme.style_background(cx, &self.widget(cx, ids!(contacts)), theme.surface);
+        theme.style_background(cx, &self.widget(cx, ids!(splitter)), theme.border);
+        theme.style_background(cx, &self.widget(cx, ids!(dialog)), theme.background);
+        theme.style_background(cx, &self.widget(cx, ids!(dialog.drop_hint)), theme.surface);
+        theme.style_label(
+            cx,
+            &self.widget(cx, ids!(dialog.drop_hint.text)),
+            theme.text,
+        );
+        theme.style_label(
+            cx,
+            &self.widget(cx, ids!(dialog.chat_header.title)),
+            theme.text,
+        );
+        for label in [ids!(dialog.long_text.info), ids!(dialog.input_bar.counter)] {
+            theme.style_label(cx, &self.widget(cx, label), theme.text_muted);
+        }
+        for input in [ids!(dialog.input_bar.msg), ids!(dialog.input_bar.code_lang)] {
+            theme.style_input(cx, &self.widget(cx, input));
+        }
+        for button in [
+            ids!(contacts.header.notifications_btn),
+            ids!(contacts.header.mentions_btn),
+            ids!(contacts.header.settings_btn),
+            ids!(contacts.header.collapse_btn),
+            ids!(dialog.chat_header.back),
+            ids!(dialog.long_text.split),
+            ids!(dialog.long_text.attach),
+            ids!(dialog.long_text.keep_editing),
+            ids!(dialog.mention_popup.mention0),
+            ids!(dialog.mention_popup.mention1),
+            ids!(dialog.mention_popup.mention2),
+            ids!(dialog.mention_popup.mention3),
+            ids!(dialog.mention_popup.mention4),
+            ids!(dialog.input_bar.code_mode),
+            ids!(dialog.input_bar.emoji),
+            ids!(dialog.input_bar.send),
+        ] {
+            theme.style_button(cx, &self.widget(cx, button));
+        }
+    }
+
+    /// Shows the chat list and the open chat side by side, or in a narrow
+    /// window only one of them with a back button from the chat to the list.
+    /// Both panes are only hidden, so the feed keeps its scroll position.
+    fn layout_panes(&mut self, cx: &mut Cx, state: &State) {
+        let single = state.single_pane();
+        let width = state.sidebar_width() * state.theme.ui_scale;
+        if self.pane_sizing != Some((single, width)) {
+            self.pane_sizing = Some((single, width));
+            let contacts = self.view(cx, ids!(contacts));
+            if single {
+                script_apply_eval!(cx, contacts, {
+                    width: Fill
+                });
+            } else {
+                script_apply_eval!(cx, contacts, {
+                    width: #(width)
+                });
+            }
+        }
+        self.view(cx, ids!(splitter)).set_visible(cx, !single);
+        // Collapsed to avatars the header buttons stack as icons and there is
+        // no room for the search.
+        let collapsed = state.sidebar_collapsed();
+        if self.header_collapsed != Some(collapsed) {
+            self.header_collapsed = Some(collapsed);
+            let header = self.view(cx, ids!(contacts.header));
+            if collapsed {
+                script_apply_eval!(cx, header, {
+                    flow: Down
+                });
+            } else {
+                script_apply_eval!(cx, header, {
+                    flow: Right
+                });
+            }
+        }
+        self.widget(cx, ids!(contacts.search))
+            .set_visible(cx, !collapsed);
+        let collapse = self.button(cx, ids!(contacts.header.collapse_btn));
+        collapse.set_visible(cx, !single);
+        collapse.set_text(cx, if collapsed { "»" } else { "«" });
+        let list_only = single && (state.show_chat_list || state.open_chat_id.is_none());
+        self.view(cx, ids!(contacts))
+            .set_visible(cx, !single || list_only);
+        self.view(cx, ids!(dialog)).set_visible(cx, !list_only);
+        self.view(cx, ids!(dialog.chat_header))
+            .set_visible(cx, single);
+        if single && let Some(chat_id) = state.open_chat_id {
+            self.label(cx, ids!(dialog.chat_header.title))
+                .set_text(cx, &state.get_chat_name(chat_id));
+        }
+    }
+
+    /// Resizes the chat list by dragging the splitter; a double-click resets it.
+    fn handle_splitter(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
         let state = scope.data.get_mut::<State>().expect("State not found.");
-        let input = self.text_input(cx, ids!(dialog.input_bar.msg));
-        let text = input.text();
-        input.set_text(cx, "");
+        let area = self.view(cx, ids!(splitter)).area();
+        match event.hits(cx, area) {
+            Hit::FingerDown(fe) if fe.tap_count == 2 => {
+                self.sidebar_drag = None;
+                state.reset_sidebar();
+                self.redraw(cx);
+            }
+            Hit::FingerDown(fe) => {
+                let width = self.view(cx, ids!(contacts)).area().rect(cx).size.x;
+                self.sidebar_drag = Some((fe.abs.x, width));
+            }
+            Hit::FingerMove(fe) => {
+                if let Some((start_x, start_width)) = self.sidebar_drag {
+                    let width = start_width + fe.abs.x - start_x;
+                    state.resize_sidebar(width / state.theme.ui_scale);
+                    self.redraw(cx);
+                }
+            }
+            Hit::FingerUp(_) => {
+                if self.sidebar_drag.take().is_some() {
+                    state.save_preferences();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The composer text as it would be sent.
+    fn outgoing_text(&self, cx: &mut Cx) -> String {
+        let text = self.text_input(cx, ids!(dialog.input_bar.msg)).text();
+        if self.code_mode {
+            let lang = self.text_input(cx, ids!(dialog.input_bar.code_lang)).text();
+            wrap_code_block(&text, lang.trim())
+        } else {
+            text
+        }
+    }
+
+    /// Empties the composer once its text has been queued.
+    fn clear_composer(&mut self, cx: &mut Cx, state: &mut State) {
+        self.text_input(cx, ids!(dialog.input_bar.msg))
+            .set_text(cx, "");
+        if self.code_mode {
+            self.set_code_mode(cx, false);
+        }
         if let Some(chat_id) = state.open_chat_id {
-            log!("Sending message to chat_id: {}", chat_id);
-            state.client.send_message(cx, chat_id, text);
+            state.set_draft(chat_id, "");
+            state.flush_drafts();
+        }
+        self.view(cx, ids!(dialog.long_text)).set_visible(cx, false);
+        self.emoji_open = false;
+        self.widget(cx, ids!(dialog.emoji_picker))
+            .set_visible(cx, false);
+        self.fit_composer(cx, "");
+        self.update_mentions(cx, state, "");
+    }
+
+    pub fn send_message_ws(&mut self, scope: &mut Scope, cx: &mut Cx) {
+        let state = scope.data.get_mut::<State>().expect("State not found.");
+        let Some(chat_id) = state.open_chat_id else {
+            log!("Error: dialog is not opened!");
+            return;
+        };
+        let text = self.outgoing_text(cx);
+        let max = state.client.max_message_length();
+        let length = text.chars().count();
+        if length > max {
+            // Too long for one message; let the user pick what to do with it.
+            let parts = split_message(&text, max).len();
+            self.label(cx, ids!(dialog.long_text.info)).set_text(
+                cx,
+                &format!(
+                    "This message has {} characters, the limit is {}.",
+                    length, max
+                ),
+            );
+            self.button(cx, ids!(dialog.long_text.split))
+                .set_text(cx, &format!("Send as {} messages", parts));
+            self.view(cx, ids!(dialog.long_text)).set_visible(cx, true);
+            self.redraw(cx);
+            return;
+        }
+        log!("Sending message to chat_id: {}", chat_id);
+        self.clear_composer(cx, state);
+        #[cfg(feature = "ai")]
+        if text.contains(ASSISTANT_MENTION) {
+            state.ask_assistant(cx, chat_id, &text);
+        }
+        let attachments = state.take_attachments(chat_id);
+        state.queue_with_attachments(cx, chat_id, text, attachments);
+        self.view(cx, ids!(news_feed)).redraw(cx);
+    }
+
+    /// Applies the choice made for an over-long message.
+    fn handle_long_text(&mut self, cx: &mut Cx, scope: &mut Scope, actions: &Actions) {
+        let state = scope.data.get_mut::<State>().expect("State not found.");
+        let Some(chat_id) = state.open_chat_id else {
+            return;
+        };
+        if self
+            .button(cx, ids!(dialog.long_text.split))
+            .clicked(actions)
+        {
+            let text = self.outgoing_text(cx);
+            self.clear_composer(cx, state);
+            let parts = split_message(&text, state.client.max_message_length());
+            let last = parts.len().saturating_sub(1);
+            for (index, part) in parts.into_iter().enumerate() {
+                // Attachments go out with the last part.
+                let attachments = if index == last {
+                    state.take_attachments(chat_id)
+                } else {
+                    vec![]
+                };
+                state.queue_with_attachments(cx, chat_id, part, attachments);
+            }
             self.view(cx, ids!(news_feed)).redraw(cx);
-        } else {
-            log!("Error: dialog is not opened!")
+        } else if self
+            .button(cx, ids!(dialog.long_text.attach))
+            .clicked(actions)
+        {
+            let text = self.outgoing_text(cx);
+            match state.queue_text_file(cx, chat_id, &text) {
+                Ok(()) => self.clear_composer(cx, state),
+                Err(err) => state.notifications.push(Notification::error(format!(
+                    "Couldn't attach the text: {}",
+                    err
+                ))),
+            }
+            cx.redraw_all();
+        } else if self
+            .button(cx, ids!(dialog.long_text.keep_editing))
+            .clicked(actions)
+        {
+            self.view(cx, ids!(dialog.long_text)).set_visible(cx, false);
+            self.redraw(cx);
+        }
+    }
+
+    /// Adds dropped or pasted files to the composer of the open chat.
+    fn attach_paths(&mut self, cx: &mut Cx, scope: &mut Scope, paths: Vec<PathBuf>) {
+        let state = scope.data.get_mut::<State>().expect("State not found.");
+        let Some(chat_id) = state.open_chat_id else {
+            return;
+        };
+        for path in paths {
+            if let Err(err) = state.attach_file(chat_id, &path) {
+                state
+                    .notifications
+                    .push(Notification::error(format!("Couldn't attach {}", err)));
+            }
+        }
+        cx.redraw_all();
+    }
+
+    fn show_drop_hint(&mut self, cx: &mut Cx, show: bool) {
+        let hint = self.view(cx, ids!(dialog.drop_hint));
+        if hint.visible() != show {
+            hint.set_visible(cx, show);
+            self.redraw(cx);
+        }
+    }
+
+    fn mention_slot(&self, cx: &mut Cx, slot: usize) -> ButtonRef {
+        match slot {
+            0 => self.button(cx, ids!(dialog.mention_popup.mention0)),
+            1 => self.button(cx, ids!(dialog.mention_popup.mention1)),
+            2 => self.button(cx, ids!(dialog.mention_popup.mention2)),
+            3 => self.button(cx, ids!(dialog.mention_popup.mention3)),
+            _ => self.button(cx, ids!(dialog.mention_popup.mention4)),
+        }
+    }
+
+    /// Offers the participants matching the `@name` being typed, if any.
+    /// `before_cursor` is the composer text up to the cursor.
+    fn update_mentions(&mut self, cx: &mut Cx, state: &State, before_cursor: &str) {
+        let users = match (state.open_chat_id, mention_query(before_cursor)) {
+            (Some(chat_id), Some(query)) => {
+                state.mention_candidates(chat_id, query, MAX_MENTION_SUGGESTIONS)
+            }
+            _ => vec![],
+        };
+        for slot in 0..MAX_MENTION_SUGGESTIONS {
+            let button = self.mention_slot(cx, slot);
+            match users.get(slot) {
+                Some(user) => {
+                    let label = match &user.display_name {
+                        Some(name) => format!("{} (@{})", name, user.username),
+                        None => format!("@{}", user.username),
+                    };
+                    button.set_text(cx, &label);
+                    button.set_visible(cx, true);
+                }
+                None => button.set_visible(cx, false),
+            }
+        }
+        self.mention_suggestions = users.iter().map(|user| user.username.clone()).collect();
+        self.view(cx, ids!(dialog.mention_popup))
+            .set_visible(cx, !self.mention_suggestions.is_empty());
+        self.redraw(cx);
+    }
+
+    /// Puts the mention token of the `slot`-th suggestion into the composer.
+    fn complete_mention(&mut self, cx: &mut Cx, scope: &mut Scope, slot: usize) {
+        let Some(username) = self.mention_suggestions.get(slot).cloned() else {
+            return;
+        };
+        let input = self.text_input(cx, ids!(dialog.input_bar.msg));
+        let cursor = self.composer_selection(cx).start;
+        let (text, cursor) = complete_mention(&input.text(), cursor, &username);
+        self.set_composer_text(cx, scope, &text, cursor);
+    }
+
+    /// Grows the composer with its text up to `COMPOSER_MAX_HEIGHT`, past
+    /// which it scrolls.
+    fn fit_composer(&mut self, cx: &mut Cx, text: &str) {
+        let lines = visual_lines(text, COMPOSER_LINE_CHARS);
+        if lines == self.composer_lines {
+            return;
+        }
+        self.composer_lines = lines;
+        let height =
+            (lines as f64 * COMPOSER_LINE_HEIGHT + COMPOSER_PADDING).min(COMPOSER_MAX_HEIGHT);
+        let composer = self.view(cx, ids!(dialog.input_bar.composer));
+        script_apply_eval!(cx, composer, {
+            height: #(height)
+        });
+        self.redraw(cx);
+    }
+
+    fn set_code_mode(&mut self, cx: &mut Cx, on: bool) {
+        self.code_mode = on;
+        self.button(cx, ids!(dialog.input_bar.code_mode))
+            .set_text(cx, if on { "</> Code" } else { "</>" });
+        self.text_input(cx, ids!(dialog.input_bar.code_lang))
+            .set_visible(cx, on);
+    }
+
+    /// Byte range selected in the composer, empty at the cursor.
+    fn composer_selection(&self, cx: &mut Cx) -> Range<usize> {
+        let input = self.text_input(cx, ids!(dialog.input_bar.msg));
+        match input.borrow() {
+            Some(input) => {
+                let selection = input.selection();
+                selection.start().index..selection.end().index
+            }
+            None => 0..0,
+        }
+    }
+
+    /// Puts `insert` into the composer over the selection, as if typed.
+    fn insert_at_cursor(&mut self, cx: &mut Cx, scope: &mut Scope, insert: &str) {
+        let input = self.text_input(cx, ids!(dialog.input_bar.msg));
+        let selection = self.composer_selection(cx);
+        let (text, cursor) = insert_at(&input.text(), selection, insert);
+        self.set_composer_text(cx, scope, &text, cursor);
+    }
+
+    /// Replaces the composer text after an edit made on the user's behalf,
+    /// with the cursor at byte offset `cursor`.
+    fn set_composer_text(&mut self, cx: &mut Cx, scope: &mut Scope, text: &str, cursor: usize) {
+        let input = self.text_input(cx, ids!(dialog.input_bar.msg));
+        input.set_text(cx, text);
+        if let Some(mut input) = input.borrow_mut() {
+            let cursor = Cursor {
+                index: cursor,
+                prefer_next_row: false,
+            };
+            input.set_cursor(cx, cursor, false);
+        }
+        input.set_key_focus(cx);
+        self.fit_composer(cx, text);
+        let state = scope.data.get_mut::<State>().expect("State not found.");
+        if let Some(chat_id) = self.draft_chat {
+            state.set_draft(chat_id, text);
         }
+        self.update_mentions(cx, state, text.get(..cursor).unwrap_or(text));
     }
 }
 
 impl Widget for DialogPage {
     fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
+        self.handle_splitter(cx, event, scope);
+        let input = self.text_input(cx, ids!(dialog.input_bar.msg));
+        let area = self.view(cx, ids!(dialog)).area();
+        match event.drag_hits(cx, area) {
+            DragHit::Drag(de) => {
+                let over = de.state != DragState::Out;
+                if over {
+                    de.response.set(DragResponse::Copy);
+                }
+                self.show_drop_hint(cx, over);
+            }
+            DragHit::Drop(de) => {
+                self.show_drop_hint(cx, false);
+                let paths = de
+                    .items
+                    .iter()
+                    .filter_map(|item| match item {
+                        DragItem::FilePath { path, .. } => Some(PathBuf::from(path)),
+                        _ => None,
+                    })
+                    .collect();
+                self.attach_paths(cx, scope, paths);
+            }
+            DragHit::DragEnd => self.show_drop_hint(cx, false),
+            _ => {}
+        }
+        // Makepad hands pastes to widgets as text only, so a copied image is
+        // read off the clipboard separately. Nothing is attached when the
+        // clipboard holds text, which the input pastes as usual.
+        if let Event::KeyDown(ke) = event
+            && ke.key_code == KeyCode::KeyV
+            && (ke.modifiers.control || ke.modifiers.logo)
+            && !ke.modifiers.alt
+            && cx.has_key_focus(input.area())
+        {
+            let state = scope.data.get_mut::<State>().expect("State not found.");
+            if let Some(chat_id) = state.open_chat_id {
+                state.paste_image(chat_id);
+            }
+        }
+        // Pasting copied files attaches them instead of inserting their paths.
+        if let Event::TextInput(te) = event
+            && te.was_paste
+            && cx.has_key_focus(input.area())
+            && let Some(paths) = pasted_file_paths(&te.input)
+            && paths.iter().all(|path| Path::new(path).is_file())
+        {
+            self.attach_paths(cx, scope, paths.into_iter().map(PathBuf::from).collect());
+            return;
+        }
+        if !self.mention_suggestions.is_empty()
+            && let Event::KeyDown(ke) = event
+            && matches!(ke.key_code, KeyCode::Tab | KeyCode::ReturnKey)
+            && !ke.modifiers.shift
+            && cx.has_key_focus(input.area())
+        {
+            // Tab or Enter picks the first suggestion instead of sending.
+            self.complete_mention(cx, scope, 0);
+            return;
+        }
+        if self.code_mode
+            && let Event::KeyDown(ke) = event
+            && ke.key_code == KeyCode::Tab
+            && cx.has_key_focus(input.area())
+        {
+            // Tab indents the code instead of moving focus.
+            self.insert_at_cursor(cx, scope, code_indent());
+            return;
+        }
+        if let Event::KeyDown(ke) = event
+            && ke.key_code == KeyCode::ReturnKey
+            && ke.modifiers.shift
+            && cx.has_key_focus(input.area())
+        {
+            // Shift+Enter starts a new line, plain Enter sends.
+            self.insert_at_cursor(cx, scope, "\n");
+            return;
+        }
         let actions = cx.capture_actions(|cx| {
             self.view.handle_event(cx, event, scope);
         });
         if self
+            .button(cx, ids!(dialog.input_bar.code_mode))
+            .clicked(&actions)
+        {
+            self.set_code_mode(cx, !self.code_mode);
+        }
+        let returned = input.returned(&actions).is_some();
+        if returned && self.code_mode {
+            let text = input.text();
+            let cursor = self.composer_selection(cx).start.min(text.len());
+            let newline = code_newline(text.get(..cursor).unwrap_or(&text));
+            self.insert_at_cursor(cx, scope, &newline);
+        } else if self
             .button(cx, ids!(dialog.input_bar.send))
             .clicked(&actions)
-            || self
-                .text_input(cx, ids!(dialog.input_bar.msg))
-                .returned(&actions)
-                .is_some()
+            || returned
         {
-            let text = self.text_input(cx, ids!(dialog.input_bar.msg)).text();
-            if !text.is_empty() {
+            let state = scope.data.get::<State>().expect("State not found.");
+            let has_attachments = state
+                .open_chat_id
+                .is_some_and(|chat_id| !state.attachments(chat_id).is_empty());
+            if !input.text().is_empty() || has_attachments {
                 self.send_message_ws(scope, cx);
             }
         }
+        if self
+            .button(cx, ids!(dialog.input_bar.emoji))
+            .clicked(&actions)
+        {
+            self.emoji_open = !self.emoji_open;
+            self.widget(cx, ids!(dialog.emoji_picker))
+                .set_visible(cx, self.emoji_open);
+            self.redraw(cx);
+        }
+        let picker = self.widget(cx, ids!(dialog.emoji_picker)).widget_uid();
+        for action in &actions {
+            if let EmojiPickerAction::Picked { picker: uid, glyph } = action.cast()
+                && uid == picker
+            {
+                let state = scope.data.get_mut::<State>().expect("State not found.");
+                state.use_emoji(&glyph);
+                self.insert_at_cursor(cx, scope, &glyph);
+                self.redraw(cx);
+            }
+        }
+        if let Some(text) = self
+            .text_input(cx, ids!(dialog.input_bar.msg))
+            .changed(&actions)
+        {
+            let cursor = self.composer_selection(cx).start.min(text.len());
+            let expanded = if self.code_mode {
+                None
+            } else {
+                expand_shortcode_at(&text, cursor)
+            };
+            if let Some((expanded, cursor)) = expanded {
+                // A `:shortcode:` was just closed; show the emoji instead.
+                self.set_composer_text(cx, scope, &expanded, cursor);
+            } else {
+                let state = scope.data.get_mut::<State>().expect("State not found.");
+                if let Some(chat_id) = self.draft_chat {
+                    state.set_draft(chat_id, &text);
+                }
+                self.fit_composer(cx, &text);
+                self.update_mentions(cx, state, text.get(..cursor).unwrap_or(&text));
+            }
+            if self.draft_chat.is_some() {
+                self.widget(cx, ids!(contacts.chats)).redraw(cx);
+            }
+            self.redraw(cx);
+        }
+        for slot in 0..MAX_MENTION_SUGGESTIONS {
+            if self.mention_slot(cx, slot).clicked(&actions) {
+                self.complete_mention(cx, scope, slot);
+            }
+        }
+        self.handle_long_text(cx, scope, &actions);
+        if self
+            .button(cx, ids!(contacts.notifications_btn))
+            .clicked(&actions)
+        {
+            let state = scope.data.get_mut::<State>().expect("State not found.");
+            state.show_notifications = !state.show_notifications;
+            state.show_mentions = false;
+            cx.redraw_all();
+        }
+        if self
+            .button(cx, ids!(contacts.mentions_btn))
+            .clicked(&actions)
+        {
+            let state = scope.data.get_mut::<State>().expect("State not found.");
+            state.show_mentions = !state.show_mentions;
+            state.show_notifications = false;
+            cx.redraw_all();
+        }
+        if self
+            .button(cx, ids!(contacts.collapse_btn))
+            .clicked(&actions)
+        {
+            let state = scope.data.get_mut::<State>().expect("State not found.");
+            state.set_sidebar_collapsed(!state.sidebar_collapsed());
+            self.redraw(cx);
+        }
+        if self
+            .button(cx, ids!(dialog.chat_header.back))
+            .clicked(&actions)
+        {
+            let state = scope.data.get_mut::<State>().expect("State not found.");
+            state.show_chat_list = true;
+            self.redraw(cx);
+        }
+        if self
+            .button(cx, ids!(contacts.settings_btn))
+            .clicked(&actions)
+        {
+            let state = scope.data.get_mut::<State>().expect("State not found.");
+            state.show_notifications = false;
+            state.show_mentions = false;
+            cx.action(RouterAction::Push(Route::new(SETTINGS_SCREEN)));
+        }
+        for action in &actions {
+            if let SearchPanelAction::Active(active) = action.cast() {
+                self.widget(cx, ids!(contacts.chats))
+                    .set_visible(cx, !active);
+                self.redraw(cx);
+            }
+        }
         cx.extend_actions(actions);
     }
 
     fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        let state = scope.data.get::<State>().expect("State not found.");
+        if self.theme.update(state.theme_version) {
+            self.apply_theme(cx, &state.theme);
+        }
+        self.layout_panes(cx, state);
+        if self.emoji_open {
+            let picker = self.widget(cx, ids!(dialog.emoji_picker));
+            if let Some(mut picker) = picker.borrow_mut::<EmojiPicker>() {
+                picker.set_recent(cx, &state.recent_emoji);
+            }
+        }
+        if state.open_chat_id != self.draft_chat {
+            // The previous chat's draft was saved as it was typed.
+            let draft = state
+                .open_chat_id
+                .map_or("", |chat_id| state.draft(chat_id));
+            self.text_input(cx, ids!(dialog.input_bar.msg))
+                .set_text(cx, draft);
+            self.fit_composer(cx, draft);
+            self.view(cx, ids!(dialog.long_text)).set_visible(cx, false);
+            self.mention_suggestions.clear();
+            self.view(cx, ids!(dialog.mention_popup))
+                .set_visible(cx, false);
+            self.draft_chat = state.open_chat_id;
+        }
+        let length = self
+            .text_input(cx, ids!(dialog.input_bar.msg))
+            .text()
+            .chars()
+            .count();
+        let counter = if length == 0 {
+            String::new()
+        } else {
+            format!("{} / {}", length, state.client.max_message_length())
+        };
+        self.label(cx, ids!(dialog.input_bar.counter))
+            .set_text(cx, &counter);
+        // Collapsed to avatars the header buttons show only an icon.
+        let collapsed = state.sidebar_collapsed();
+        let count = state.notifications.history().len();
+        let name = if collapsed { "🔔" } else { "Notifications" };
+        let label = match (count, collapsed) {
+            (0, _) => name.to_string(),
+            (_, true) => format!("{} {}", name, count),
+            (_, false) => format!("{} ({})", name, count),
+        };
+        self.button(cx, ids!(contacts.notifications_btn))
+            .set_text(cx, &label);
+        let mentions = state.mention_total();
+        let name = if collapsed { "@" } else { "Mentions" };
+        let label = match (mentions, collapsed) {
+            (0, _) => name.to_string(),
+            (_, true) => format!("{}{}", name, mentions),
+            (_, false) => format!("{} ({})", name, mentions),
+        };
+        self.button(cx, ids!(contacts.mentions_btn))
+            .set_text(cx, &label);
         self.view.draw_walk(cx, scope, walk)
     }
 }
@@ -98,44 +1015,616 @@ impl Widget for DialogPage {
 struct NewsFeed {
     #[deref]
     view: View,
+    #[rust]
+    pending_scroll: Option<i64>,
+    /// Messages of the open chat split and sanitized for display, by id.
+    #[rust]
+    rendered: HashMap<i64, RenderedMessage>,
+    /// Rows of the feed and what they were built from.
+    #[rust]
+    rows: Vec<FeedRow>,
+    #[rust]
+    rows_key: Option<FeedKey>,
+    #[rust]
+    styled: StyledRows,
+}
+
+/// One entry of the feed: a day separator, a message of the open chat by
+/// index, or a message still waiting in the outbox.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FeedRow {
+    Day(NaiveDate),
+    Message(usize),
+    /// A segment after the first of a message split at code blocks.
+    Part(usize, usize),
+    /// An attached file of a message, by index into its files.
+    File(usize, usize),
+    Queued(usize),
+    /// A reply of the assistant, streamed or finished.
+    #[cfg(feature = "ai")]
+    Assistant(usize),
+}
+
+/// A message split into prose and code blocks, or its whole text when shown
+/// raw, with the markdown of each prose part already sanitized. Messages
+/// don't change once received, so this is built once per raw toggle.
+struct RenderedMessage {
+    raw: bool,
+    segments: Vec<Segment>,
+    /// Sanitized markdown by segment; `None` for code and for text shown as is.
+    markdown: Vec<Option<String>>,
+}
+
+impl RenderedMessage {
+    fn new(msg: &ChatMessage, raw: bool) -> Self {
+        let content = msg.content.as_deref().unwrap_or("");
+        let mut segments = if raw {
+            vec![]
+        } else {
+            split_code_blocks(content)
+        };
+        if segments.is_empty() {
+            segments.push(Segment::Text(content.to_string()));
+        }
+        let markdown = segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) if !raw => sanitize_markdown(text),
+                _ => None,
+            })
+            .collect();
+        Self {
+            raw,
+            segments,
+            markdown,
+        }
+    }
+
+    /// Text and markdown shown inside the post bubble itself; code blocks get
+    /// rows of their own.
+    fn first_text(&self) -> (&str, Option<&str>) {
+        match self.segments.first() {
+            Some(Segment::Text(text)) => (text, self.markdown[0].as_deref()),
+            _ => ("", None),
+        }
+    }
+}
+
+/// What the rows of the feed depend on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct FeedKey {
+    chat_id: Option<i64>,
+    history_version: u64,
+    queued: usize,
+    replies: usize,
+    finished_replies: usize,
+    today: NaiveDate,
+}
+
+impl NewsFeed {
+    /// Rebuilds the rows if the history, the outbox or the replies changed.
+    fn update_rows(&mut self, state: &State) {
+        let queued = state
+            .open_chat_id
+            .map_or(0, |chat_id| state.outbox.queued_for(chat_id).count());
+        #[cfg(feature = "ai")]
+        let replies = state
+            .open_chat_id
+            .map_or(&[][..], |chat_id| state.assistant.chat_replies(chat_id));
+        #[cfg(feature = "ai")]
+        let (reply_count, finished_replies) = (
+            replies.len(),
+            replies.iter().filter(|reply| reply.done).count(),
+        );
+        #[cfg(not(feature = "ai"))]
+        let (reply_count, finished_replies) = (0, 0);
+        let today = Timestamp::now().local_day();
+        let key = FeedKey {
+            chat_id: state.open_chat_id,
+            history_version: state.history_version,
+            queued,
+            replies: reply_count,
+            finished_replies,
+            today,
+        };
+        if self.rows_key == Some(key) {
+            return;
+        }
+        if self.rows_key.is_some_and(|old| old.chat_id != key.chat_id) {
+            self.rendered.clear();
+        }
+        self.rows_key = Some(key);
+        let mut rows = vec![];
+        let mut last_day = None;
+        let mut push_day = |rows: &mut Vec<FeedRow>, day: NaiveDate| {
+            if last_day != Some(day) {
+                rows.push(FeedRow::Day(day));
+                last_day = Some(day);
+            }
+        };
+        // Finished replies sit among the messages by the time they finished,
+        // ones still streaming stay at the bottom.
+        #[cfg(feature = "ai")]
+        let mut finished: Vec<usize> = (0..replies.len())
+            .filter(|&reply| replies[reply].done)
+            .collect();
+        #[cfg(feature = "ai")]
+        finished.sort_by_key(|&reply| replies[reply].time);
+        #[cfg(feature = "ai")]
+        let mut finished = finished.into_iter().peekable();
+        if let Some(chat_id) = state.open_chat_id
+            && let Some(messages) = state.msg_history.get(&chat_id)
+        {
+            for (index, msg) in messages.iter().enumerate() {
+                #[cfg(feature = "ai")]
+                while let Some(reply) =
+                    finished.next_if(|&reply| replies[reply].time < msg.timestamp)
+                {
+                    push_day(&mut rows, replies[reply].time.local_day());
+                    rows.push(FeedRow::Assistant(reply));
+                }
+                push_day(&mut rows, msg.timestamp.local_day());
+                rows.push(FeedRow::Message(index));
+                let segments = &rendered(&mut self.rendered, state, msg).segments;
+                let skip = usize::from(matches!(segments.first(), Some(Segment::Text(_))));
+                rows.extend((skip..segments.len()).map(|part| FeedRow::Part(index, part)));
+                rows.extend((0..msg.files.len()).map(|file| FeedRow::File(index, file)));
+            }
+        }
+        #[cfg(feature = "ai")]
+        for reply in finished {
+            push_day(&mut rows, replies[reply].time.local_day());
+            rows.push(FeedRow::Assistant(reply));
+        }
+        if queued > 0 {
+            push_day(&mut rows, today);
+            rows.extend((0..queued).map(FeedRow::Queued));
+        }
+        #[cfg(feature = "ai")]
+        for (reply, _) in replies.iter().enumerate().filter(|(_, reply)| !reply.done) {
+            push_day(&mut rows, today);
+            rows.push(FeedRow::Assistant(reply));
+        }
+        self.rows = rows;
+    }
 }
 
 impl Widget for NewsFeed {
     fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        let scroll_to = scope
+            .data
+            .get_mut::<State>()
+            .and_then(|state| state.scroll_to_message.take())
+            .or(self.pending_scroll.take());
+        // Image previews seen for the first time, fetched once the list is drawn.
+        let mut wanted_images = vec![];
         while let Some(item) = self.view.draw_walk(cx, scope, walk).step() {
             if let Some(mut list) = item.as_portal_list().borrow_mut() {
                 let state = scope.data.get::<State>().expect("State not found.");
-                let msg_count = state.get_message_number();
-                list.set_item_range(cx, 0, msg_count);
+                let messages = state
+                    .open_chat_id
+                    .and_then(|chat_id| state.msg_history.get(&chat_id))
+                    .map(|msgs| msgs.as_slice())
+                    .unwrap_or_default();
+                let queued: Vec<_> = match state.open_chat_id {
+                    Some(chat_id) => state.outbox.queued_for(chat_id).collect(),
+                    None => vec![],
+                };
+                self.update_rows(state);
+                if let Some(msg_id) = scroll_to {
+                    let row = messages
+                        .iter()
+                        .position(|msg| msg.id == msg_id)
+                        .and_then(|index| {
+                            self.rows.iter().position(|r| *r == FeedRow::Message(index))
+                        });
+                    match row {
+                        Some(row) => list.set_first_id_and_scroll(row, 0.0),
+                        // History isn't loaded yet, try again on the next draw.
+                        None => self.pending_scroll = Some(msg_id),
+                    }
+                }
+                let today = Timestamp::now().local_day();
+                list.set_item_range(cx, 0, self.rows.len());
                 while let Some(item_id) = list.next_visible_item(cx) {
-                    if item_id < msg_count {
-                        let item = list.item(cx, item_id, id!(post));
-                        if let Some(chat_id) = state.open_chat_id
-                            && let Some(messages) = state.msg_history.get(&chat_id)
-                            && let Some(msg) = messages.get(item_id)
-                        {
-                            let sender_name =
-                                if let Some(user) = state.user_info.get(&msg.sender_id) {
-                                    user.display_name
-                                        .clone()
-                                        .unwrap_or_else(|| user.username.clone())
+                    match self.rows.get(item_id).copied() {
+                        Some(FeedRow::Day(day)) => {
+                            let item = list.item(cx, item_id, id!(day_separator));
+                            if self.styled.needs_style(state.theme_version, &item) {
+                                style_feed_row(cx, &state.theme, &item, id!(day_separator));
+                            }
+                            item.label(cx, ids!(label))
+                                .set_text(cx, &day_label(day, today));
+                            item.draw_all_unscoped(cx);
+                        }
+                        Some(FeedRow::Message(index)) => {
+                            let item = list.item(cx, item_id, id!(post));
+                            if self.styled.needs_style(state.theme_version, &item) {
+                                style_feed_row(cx, &state.theme, &item, id!(post));
+                            }
+                            if let Some(msg) = messages.get(index) {
+                                let sender_name = state.user_name(msg.sender_id);
+                                item.label(cx, ids!(user_msg.body.username.text))
+                                    .set_text(cx, &sender_name);
+                                item.label(cx, ids!(user_msg.body.username.time))
+                                    .set_text(cx, &msg.timestamp.time_label());
+                                let mention = state.mentions_me(msg);
+                                item.label(cx, ids!(user_msg.body.username.status))
+                                    .set_text(cx, if mention { "mentioned you" } else { "" });
+                                item.view(cx, ids!(user_msg.body.mention_bar))
+                                    .set_visible(cx, mention);
+                                let rendered = rendered(&mut self.rendered, state, msg);
+                                let (text, markdown) = rendered.first_text();
+                                set_post_content(cx, &item, text, markdown, Some(rendered.raw));
+                            }
+                            item.draw_all_unscoped(cx);
+                        }
+                        Some(FeedRow::Part(index, part)) => {
+                            let Some(msg) = messages.get(index) else {
+                                continue;
+                            };
+                            let rendered = rendered(&mut self.rendered, state, msg);
+                            match rendered.segments.get(part) {
+                                Some(Segment::Code { lang, code }) => {
+                                    let item = list.item(cx, item_id, id!(code_block));
+                                    if self.styled.needs_style(state.theme_version, &item) {
+                                        style_feed_row(cx, &state.theme, &item, id!(code_block));
+                                    }
+                                    item.label(cx, ids!(header.lang))
+                                        .set_text(cx, if lang.is_empty() { "code" } else { lang });
+                                    item.widget(cx, ids!(code)).set_text(cx, code);
+                                    item.draw_all_unscoped(cx);
+                                }
+                                Some(Segment::Text(text)) => {
+                                    let item = list.item(cx, item_id, id!(post_part));
+                                    if self.styled.needs_style(state.theme_version, &item) {
+                                        style_feed_row(cx, &state.theme, &item, id!(post_part));
+                                    }
+                                    let markdown = rendered.markdown[part].as_deref();
+                                    set_post_content(cx, &item, text, markdown, None);
+                                    item.draw_all_unscoped(cx);
+                                }
+                                None => {}
+                            }
+                        }
+                        Some(FeedRow::File(index, file_index)) => {
+                            let item = list.item(cx, item_id, id!(attachment));
+                            if self.styled.needs_style(state.theme_version, &item) {
+                                style_feed_row(cx, &state.theme, &item, id!(attachment));
+                            }
+                            if let Some(file) = messages
+                                .get(index)
+                                .and_then(|msg| msg.files.get(file_index))
+                            {
+                                set_attachment(cx, &item, state, file, &mut wanted_images);
+                            }
+                            item.draw_all_unscoped(cx);
+                        }
+                        Some(FeedRow::Queued(index)) => {
+                            let item = list.item(cx, item_id, id!(post));
+                            if self.styled.needs_style(state.theme_version, &item) {
+                                style_feed_row(cx, &state.theme, &item, id!(post));
+                            }
+                            if let Some(entry) = queued.get(index) {
+                                let sender_name = match state.current_user_id {
+                                    Some(user_id) => state.user_name(user_id),
+                                    None => state.username.clone(),
+                                };
+                                item.label(cx, ids!(user_msg.body.username.text))
+                                    .set_text(cx, &sender_name);
+                                item.label(cx, ids!(user_msg.body.username.time))
+                                    .set_text(cx, "");
+                                item.view(cx, ids!(user_msg.body.mention_bar))
+                                    .set_visible(cx, false);
+                                let status = if state.outbox.is_retrying(entry) {
+                                    "upload failed, retrying"
+                                } else if entry.attachments.iter().any(|a| a.uploaded.is_none()) {
+                                    "uploading"
                                 } else {
-                                    msg.sender_id.to_string()
+                                    "queued"
                                 };
-                            item.label(cx, ids!(user_msg.body.username.text))
-                                .set_text(cx, &sender_name);
-                            item.label(cx, ids!(user_msg.body.content.text))
-                                .set_text(cx, msg.content.as_deref().unwrap_or(""));
+                                item.label(cx, ids!(user_msg.body.username.status))
+                                    .set_text(cx, status);
+                                let mut content = entry.content.clone();
+                                for attachment in &entry.attachments {
+                                    content.push_str(&format!("\n\n📎 {}", attachment.filename));
+                                }
+                                let content = content.trim_start();
+                                let markdown = sanitize_markdown(content);
+                                set_post_content(cx, &item, content, markdown.as_deref(), None);
+                            }
+                            item.draw_all_unscoped(cx);
                         }
-                        item.draw_all_unscoped(cx);
+                        #[cfg(feature = "ai")]
+                        Some(FeedRow::Assistant(index)) => {
+                            let item = list.item(cx, item_id, id!(post));
+                            if self.styled.needs_style(state.theme_version, &item) {
+                                style_feed_row(cx, &state.theme, &item, id!(post));
+                            }
+                            if let Some(reply) = state.open_chat_id.and_then(|chat_id| {
+                                state.assistant.chat_replies(chat_id).get(index)
+                            }) {
+                                let status = match (&reply.error, reply.done) {
+                                    (Some(_), _) => "failed",
+                                    (None, false) => "typing...",
+                                    (None, true) => "",
+                                };
+                                item.label(cx, ids!(user_msg.body.username.text))
+                                    .set_text(cx, ASSISTANT_NAME);
+                                let time = if reply.done {
+                                    reply.time.time_label()
+                                } else {
+                                    String::new()
+                                };
+                                item.label(cx, ids!(user_msg.body.username.time))
+                                    .set_text(cx, &time);
+                                item.label(cx, ids!(user_msg.body.username.status))
+                                    .set_text(cx, status);
+                                item.view(cx, ids!(user_msg.body.mention_bar))
+                                    .set_visible(cx, false);
+                                let text = reply.error.as_deref().unwrap_or(&reply.text);
+                                let markdown = sanitize_markdown(text);
+                                set_post_content(cx, &item, text, markdown.as_deref(), None);
+                            }
+                            item.draw_all_unscoped(cx);
+                        }
+                        None => {}
                     }
                 }
             }
         }
+        if let Some(state) = scope.data.get_mut::<State>() {
+            for file in wanted_images {
+                state.request_image(cx, &file);
+            }
+        }
         DrawStep::done()
     }
 
     fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
-        self.view.handle_event(cx, event, scope)
+        let actions = cx.capture_actions(|cx| {
+            self.view.handle_event(cx, event, scope);
+        });
+        let state = scope.data.get_mut::<State>().expect("State not found.");
+        let portal_list = self.view.portal_list(cx, ids!(list));
+        // Rows as last drawn, which is what the user clicked on.
+        let clicked: Vec<(usize, FeedRow)> = portal_list
+            .items_with_actions(&actions)
+            .into_iter()
+            .filter_map(|(item_id, _)| Some((item_id, *self.rows.get(item_id)?)))
+            .collect();
+        for (item_id, row) in clicked {
+            if let FeedRow::Part(index, part) = row
+                && let Some(chat_id) = state.open_chat_id
+                && let Some(msg) = state
+                    .msg_history
+                    .get(&chat_id)
+                    .and_then(|msgs| msgs.get(index))
+                && let Some(rendered) = self.rendered.get(&msg.id)
+                && let Some(Segment::Code { code, .. }) = rendered.segments.get(part)
+                && portal_list
+                    .item(cx, item_id, id!(code_block))
+                    .button(cx, ids!(header.copy))
+                    .clicked(&actions)
+            {
+                cx.copy_to_clipboard(code);
+            }
+            if let FeedRow::File(index, file_index) = row
+                && let Some(chat_id) = state.open_chat_id
+                && let Some(file) = state
+                    .msg_history
+                    .get(&chat_id)
+                    .and_then(|msgs| msgs.get(index))
+                    .and_then(|msg| msg.files.get(file_index))
+                    .cloned()
+            {
+                let item = portal_list.item(cx, item_id, id!(attachment));
+                let thumb = item.view(cx, ids!(thumb));
+                for action in &actions {
+                    if let ViewAction::FingerUp(fe) = action.as_widget_action().cast()
+                        && thumb.area().rect(cx).contains(fe.abs)
+                        && matches!(state.images.get(&file.url), Some(ImageState::Ready { .. }))
+                    {
+                        state.lightbox = Some(file.clone());
+                        cx.redraw_all();
+                    }
+                }
+                if item.button(cx, ids!(info.download)).clicked(&actions) {
+                    state.downloads.dismiss(&file.url);
+                    state.download(cx, &file);
+                    self.redraw(cx);
+                }
+                if item.button(cx, ids!(info.cancel)).clicked(&actions) {
+                    state.downloads.cancel(cx, &file.url);
+                    self.redraw(cx);
+                }
+                if item.button(cx, ids!(info.open_folder)).clicked(&actions)
+                    && let Some(done) = state.downloads.finished(&file.url)
+                    && let Some(dir) = Path::new(&done.path).parent()
+                {
+                    cx.open_url(&format!("file://{}", dir.display()), OpenUrlInPlace::No);
+                }
+            }
+            if let FeedRow::Message(index) = row
+                && let Some(chat_id) = state.open_chat_id
+                && let Some(msg_id) = state
+                    .msg_history
+                    .get(&chat_id)
+                    .and_then(|msgs| msgs.get(index))
+                    .map(|msg| msg.id)
+                && portal_list
+                    .item(cx, item_id, id!(post))
+                    .button(cx, ids!(user_msg.body.username.raw_toggle))
+                    .clicked(&actions)
+            {
+                state.toggle_raw(msg_id);
+                self.redraw(cx);
+            }
+        }
+        for action in &actions {
+            if let MarkdownAction::LinkNavigated(url) = action.as_widget_action().cast()
+                && is_safe_link(&url)
+            {
+                cx.open_url(&url, OpenUrlInPlace::No);
+            }
+        }
+        cx.extend_actions(actions);
+    }
+}
+
+/// `msg` split and sanitized for display, from `cache` unless the message
+/// was switched to or from its source text since.
+fn rendered<'a>(
+    cache: &'a mut HashMap<i64, RenderedMessage>,
+    state: &State,
+    msg: &ChatMessage,
+) -> &'a RenderedMessage {
+    let raw = state.raw_messages.contains(&msg.id);
+    let rendered = cache
+        .entry(msg.id)
+        .or_insert_with(|| RenderedMessage::new(msg, raw));
+    if rendered.raw != raw {
+        *rendered = RenderedMessage::new(msg, raw);
     }
+    rendered
+}
+
+/// Styles a row of the feed made from `template` for `theme`.
+fn style_feed_row(cx: &mut Cx, theme: &Theme, item: &WidgetRef, template: LiveId) {
+    if template == id!(post) || template == id!(post_part) {
+        theme.style_post(cx, &item.widget(cx, ids!(user_msg)));
+    } else if template == id!(code_block) {
+        theme.style_button(cx, &item.widget(cx, ids!(header.copy)));
+        theme.style_label(cx, &item.widget(cx, ids!(header.lang)), theme.text_muted);
+    } else if template == id!(attachment) {
+        theme.style_label(cx, &item.widget(cx, ids!(info.name)), theme.text);
+        for label in [ids!(info.size), ids!(info.status), ids!(info.progress)] {
+            theme.style_label(cx, &item.widget(cx, label), theme.text_muted);
+        }
+        for button in [
+            ids!(info.download),
+            ids!(info.cancel),
+            ids!(info.open_folder),
+        ] {
+            theme.style_button(cx, &item.widget(cx, button));
+        }
+    } else if template == id!(day_separator) {
+        theme.style_label(cx, &item.widget(cx, ids!(label)), theme.text_muted);
+    }
+}
+
+/// Fills an attachment row: name, size and download state, plus a thumbnail
+/// for images. Images not requested yet are added to `wanted`.
+fn set_attachment(
+    cx: &mut Cx,
+    item: &WidgetRef,
+    state: &State,
+    file: &FileMetadata,
+    wanted: &mut Vec<FileMetadata>,
+) {
+    item.label(cx, ids!(info.name))
+        .set_text(cx, &format!("📎 {}", file.filename));
+    item.label(cx, ids!(info.size))
+        .set_text(cx, &file.size_label());
+    let mut status = "";
+    let mut preview = None;
+    if file.is_image() {
+        match state.images.get(&file.url) {
+            None => {
+                wanted.push(file.clone());
+                status = "loading preview...";
+            }
+            Some(ImageState::Loading) => status = "loading preview...",
+            Some(ImageState::Ready {
+                texture,
+                width,
+                height,
+            }) => preview = Some((texture, *width, *height)),
+            Some(ImageState::Failed(reason)) => status = reason.as_str(),
+        }
+    }
+    item.label(cx, ids!(info.status)).set_text(cx, status);
+    let thumb = item.view(cx, ids!(thumb));
+    thumb.set_visible(cx, preview.is_some());
+    if let Some((texture, width, height)) = preview {
+        let (width, height) = fit_size(width, height, THUMB_MAX_WIDTH, THUMB_MAX_HEIGHT);
+        script_apply_eval!(cx, thumb, {
+            width: #(width)
+            height: #(height)
+        });
+        item.image(cx, ids!(thumb.image))
+            .set_texture(cx, Some(texture.clone()));
+    }
+    let download = state.downloads.get(&file.url);
+    let finished = state.downloads.finished(&file.url);
+    let running = download.is_some_and(|d| d.status == DownloadStatus::Running);
+    let progress = match download {
+        Some(download) => match &download.status {
+            DownloadStatus::Running => match download.progress() {
+                Some(done) => format!("{:.0}% of {}", done * 100.0, file.size_label()),
+                None => format!("{} bytes", download.received),
+            },
+            DownloadStatus::Failed(reason) => format!("download failed: {}", reason),
+        },
+        None if finished.is_some() => "saved".to_string(),
+        None => String::new(),
+    };
+    item.label(cx, ids!(info.progress)).set_text(cx, &progress);
+    let download_button = item.button(cx, ids!(info.download));
+    download_button.set_visible(cx, !running);
+    download_button.set_text(
+        cx,
+        if download.is_some() {
+            "Retry"
+        } else if finished.is_some() {
+            "Download again"
+        } else {
+            "Download"
+        },
+    );
+    item.button(cx, ids!(info.cancel)).set_visible(cx, running);
+    item.button(cx, ids!(info.open_folder))
+        .set_visible(cx, finished.is_some());
+}
+
+/// Fills the content of a post with its sanitized `markdown`, or with the
+/// plain `content` when there is none because the post is shown raw or
+/// can't be rendered safely. `show_raw` of `None` hides the raw toggle.
+fn set_post_content(
+    cx: &mut Cx,
+    item: &WidgetRef,
+    content: &str,
+    markdown: Option<&str>,
+    show_raw: Option<bool>,
+) {
+    item.view(cx, ids!(user_msg.body.content))
+        .set_visible(cx, !content.trim().is_empty());
+    item.widget(cx, ids!(user_msg.body.content.md))
+        .set_visible(cx, markdown.is_some());
+    item.label(cx, ids!(user_msg.body.content.text))
+        .set_visible(cx, markdown.is_none());
+    match markdown {
+        Some(markdown) => item
+            .widget(cx, ids!(user_msg.body.content.md))
+            .set_text(cx, markdown),
+        None => item
+            .label(cx, ids!(user_msg.body.content.text))
+            .set_text(cx, content),
+    }
+    let toggle = item.button(cx, ids!(user_msg.body.username.raw_toggle));
+    toggle.set_visible(cx, show_raw.is_some());
+    toggle.set_text(
+        cx,
+        if show_raw == Some(true) {
+            "Formatted"
+        } else {
+            "Raw"
+        },
+    );
+}
+
+fn is_safe_link(url: &str) -> bool {
+    ["https://", "http://", "mailto:"]
+        .iter()
+        .any(|scheme| url.starts_with(scheme))
 }
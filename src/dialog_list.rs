@@ -1,4 +1,7 @@
-use crate::{app::AppAction, state::*};
+use crate::new_chat::NEW_CHAT_SCREEN;
+use crate::router::{Route, RouterAction};
+use crate::theme::{StyledRows, ThemeStamp};
+use crate::{state::*, time::Timestamp};
 use makepad_widgets::*;
 
 script_mod! {
@@ -15,6 +18,12 @@ script_mod! {
             chat := CachedView{
                 user_chat := ChatItem{}
             }
+            archived_header := View{
+                width: Fill
+                height: Fit
+                padding: Inset { top: 10.0, right: 10.0, bottom: 0.0, left: 10.0 }
+                toggle := SDButton{ width: Fill, text: "Archived" }
+            }
         }
         new_chat_btn := SDButton{
             width: Fill, height: Fit
@@ -27,46 +36,232 @@ script_mod! {
 struct ChatList {
     #[deref]
     view: View,
+    /// Chat whose settings menu is unfolded.
+    #[rust]
+    menu_chat: Option<i64>,
+    #[rust]
+    theme: ThemeStamp,
+    #[rust]
+    rows: StyledRows,
+}
+
+/// One entry of the chat list. Built the same way for drawing and for
+/// click handling so an index always means the same chat.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ChatRow {
+    Chat(i64),
+    ArchivedHeader(usize),
 }
 
 impl ChatList {
-    pub fn load_msg_history(&mut self, cx: &mut Cx, state: &mut State) {
-        if let Some(chat_id) = state.open_chat_id {
-            state.client.get_history(cx, chat_id);
-            log!("Requesting message history for chat: {}", chat_id);
+    fn rows(&self, state: &State) -> Vec<ChatRow> {
+        let (active, archived) = state.listed_chats();
+        let mut rows: Vec<ChatRow> = active.into_iter().map(ChatRow::Chat).collect();
+        if !archived.is_empty() {
+            rows.push(ChatRow::ArchivedHeader(archived.len()));
+            if state.show_archived {
+                rows.extend(archived.into_iter().map(ChatRow::Chat));
+            }
+        }
+        rows
+    }
+
+    fn draw_chat(
+        &self,
+        cx: &mut Cx2d,
+        item: &WidgetRef,
+        state: &State,
+        chat_id: i64,
+        now: Timestamp,
+    ) {
+        let chat_name = state.get_chat_name(chat_id);
+        let collapsed = state.sidebar_collapsed();
+        item.view(cx, ids!(user_chat.body.avatar))
+            .set_visible(cx, collapsed);
+        item.view(cx, ids!(user_chat.body.target_usr))
+            .set_visible(cx, !collapsed);
+        item.view(cx, ids!(user_chat.body.last_msg))
+            .set_visible(cx, !collapsed);
+        let initial: String = chat_name
+            .chars()
+            .take(1)
+            .flat_map(char::to_uppercase)
+            .collect();
+        item.label(cx, ids!(user_chat.body.avatar.circle.initial))
+            .set_text(cx, &initial);
+        item.label(cx, ids!(user_chat.body.target_usr.text))
+            .set_text(cx, &chat_name);
+
+        let last_msg = state.msg_history.get(&chat_id).and_then(|msgs| msgs.last());
+        let draft = state.draft(chat_id);
+        let preview = if !draft.is_empty() && state.open_chat_id != Some(chat_id) {
+            format!("Draft: {}", draft)
+        } else {
+            last_msg
+                .and_then(|msg| msg.content.clone())
+                .unwrap_or_default()
+        };
+        item.label(cx, ids!(user_chat.body.last_msg.text))
+            .set_text(cx, &preview);
+        item.label(cx, ids!(user_chat.body.target_usr.time))
+            .set_text(
+                cx,
+                &last_msg.map_or(String::new(), |msg| msg.timestamp.relative_label(now)),
+            );
+
+        // Mentions are counted on their own and show even in muted chats.
+        let mentions = state.mention_count(chat_id);
+        item.view(cx, ids!(user_chat.body.target_usr.mentions))
+            .set_visible(cx, mentions > 0);
+        item.label(cx, ids!(user_chat.body.target_usr.mentions.count))
+            .set_text(cx, &format!("@{}", mentions));
+
+        let unread = state.unread_count(chat_id);
+        let highlight = unread > 0 && !state.is_muted(chat_id);
+        item.view(cx, ids!(user_chat.body.target_usr.unread))
+            .set_visible(cx, highlight);
+        item.label(cx, ids!(user_chat.body.target_usr.unread.count))
+            .set_text(cx, &unread.to_string());
+        // Collapsed to avatars, the counts go under the avatar instead.
+        item.view(cx, ids!(user_chat.body.avatar.badges.mention_badge))
+            .set_visible(cx, mentions > 0);
+        item.label(cx, ids!(user_chat.body.avatar.badges.mention_badge.count))
+            .set_text(cx, &format!("@{}", mentions));
+        item.view(cx, ids!(user_chat.body.avatar.badges.unread_badge))
+            .set_visible(cx, highlight);
+        item.label(cx, ids!(user_chat.body.avatar.badges.unread_badge.count))
+            .set_text(cx, &unread.to_string());
+        item.button(cx, ids!(user_chat.body.target_usr.menu_btn))
+            .set_visible(cx, true);
+        item.button(cx, ids!(user_chat.body.menu.summarize))
+            .set_visible(cx, cfg!(feature = "ai") && unread > 0);
+
+        let settings = state.chat_settings(chat_id);
+        let muted = state.is_muted(chat_id);
+        item.view(cx, ids!(user_chat.body.menu))
+            .set_visible(cx, !collapsed && self.menu_chat == Some(chat_id));
+        item.button(cx, ids!(user_chat.body.menu.pin))
+            .set_text(cx, if settings.pinned { "Unpin" } else { "Pin" });
+        item.button(cx, ids!(user_chat.body.menu.mute))
+            .set_text(cx, if muted { "Unmute" } else { "Mute" });
+        item.button(cx, ids!(user_chat.body.menu.mute_hour))
+            .set_visible(cx, !muted);
+        item.button(cx, ids!(user_chat.body.menu.archive)).set_text(
+            cx,
+            if settings.archived {
+                "Unarchive"
+            } else {
+                "Archive"
+            },
+        );
+    }
+
+    /// Applies a click on the settings menu of `chat_id`. Returns whether the
+    /// click was consumed by the menu.
+    fn handle_menu(
+        &mut self,
+        cx: &mut Cx,
+        item: &WidgetRef,
+        actions: &Actions,
+        state: &mut State,
+        chat_id: i64,
+    ) -> bool {
+        if item
+            .button(cx, ids!(user_chat.body.target_usr.menu_btn))
+            .clicked(actions)
+        {
+            self.menu_chat = if self.menu_chat == Some(chat_id) {
+                None
+            } else {
+                Some(chat_id)
+            };
+        } else if item
+            .button(cx, ids!(user_chat.body.menu.pin))
+            .clicked(actions)
+        {
+            state.toggle_pinned(chat_id);
+            self.menu_chat = None;
+        } else if item
+            .button(cx, ids!(user_chat.body.menu.mute))
+            .clicked(actions)
+        {
+            if state.is_muted(chat_id) {
+                state.unmute(chat_id);
+            } else {
+                state.mute(chat_id, None);
+            }
+            self.menu_chat = None;
+        } else if item
+            .button(cx, ids!(user_chat.body.menu.mute_hour))
+            .clicked(actions)
+        {
+            state.mute(chat_id, Some(Timestamp::after_hours(1)));
+            self.menu_chat = None;
+        } else if item
+            .button(cx, ids!(user_chat.body.menu.archive))
+            .clicked(actions)
+        {
+            state.toggle_archived(chat_id);
+            self.menu_chat = None;
+        } else if item
+            .button(cx, ids!(user_chat.body.menu.summarize))
+            .clicked(actions)
+        {
+            #[cfg(feature = "ai")]
+            state.summarize_since_last_read(cx, chat_id);
+            self.menu_chat = None;
+            cx.redraw_all();
         } else {
-            log!("Warning: Attempted to load history without an open chat.");
+            return false;
         }
+        self.redraw(cx);
+        true
     }
 }
 
 impl Widget for ChatList {
     fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        let state = scope.data.get::<State>().expect("State not found.");
+        if self.theme.update(state.theme_version) {
+            let new_chat_btn = self.widget(cx, ids!(new_chat_btn));
+            state.theme.style_button(cx, &new_chat_btn);
+        }
+        let collapsed = state.sidebar_collapsed();
+        self.button(cx, ids!(new_chat_btn))
+            .set_text(cx, if collapsed { "+" } else { "Add new chat" });
         while let Some(item) = self.view.draw_walk(cx, scope, walk).step() {
             if let Some(mut list) = item.as_portal_list().borrow_mut() {
                 let state = scope.data.get::<State>().expect("State not found.");
-                let chat_ids: Vec<i64> = state.chat_info.keys().copied().collect();
-                let chat_count = chat_ids.len();
-                list.set_item_range(cx, 0, chat_count);
+                let rows = self.rows(state);
+                let now = Timestamp::now();
+                list.set_item_range(cx, 0, rows.len());
                 while let Some(item_id) = list.next_visible_item(cx) {
-                    if item_id < chat_count {
-                        let item = list.item(cx, item_id, id!(chat));
-                        if let Some(chat_id) = chat_ids.get(item_id) {
-                            let chat_name = state.get_chat_name(*chat_id);
-                            item.label(cx, ids!(user_chat.body.target_usr.text))
-                                .set_text(cx, &chat_name);
-
-                            if let Some(msgs) = state.msg_history.get(chat_id) {
-                                if let Some(last_msg) = msgs.last() {
-                                    item.label(cx, ids!(user_chat.body.last_msg.text))
-                                        .set_text(cx, last_msg.content.as_deref().unwrap_or(""));
-                                } else {
-                                    item.label(cx, ids!(user_chat.body.last_msg.text))
-                                        .set_text(cx, "");
-                                }
+                    match rows.get(item_id) {
+                        Some(ChatRow::Chat(chat_id)) => {
+                            let item = list.item(cx, item_id, id!(chat));
+                            if self.rows.needs_style(state.theme_version, &item) {
+                                let user_chat = item.widget(cx, ids!(user_chat));
+                                state.theme.style_chat_item(cx, &user_chat);
+                            }
+                            self.draw_chat(cx, &item, state, *chat_id, now);
+                            item.draw_all_unscoped(cx);
+                        }
+                        Some(ChatRow::ArchivedHeader(count)) => {
+                            let item = list.item(cx, item_id, id!(archived_header));
+                            if self.rows.needs_style(state.theme_version, &item) {
+                                let toggle = item.widget(cx, ids!(toggle));
+                                state.theme.style_button(cx, &toggle);
                             }
+                            let arrow = if state.show_archived { "v" } else { ">" };
+                            let text = if collapsed {
+                                format!("{} {}", arrow, count)
+                            } else {
+                                format!("{} Archived ({})", arrow, count)
+                            };
+                            item.button(cx, ids!(toggle)).set_text(cx, &text);
+                            item.draw_all_unscoped(cx);
                         }
-                        item.draw_all_unscoped(cx);
+                        None => {}
                     }
                 }
             }
@@ -79,25 +274,36 @@ impl Widget for ChatList {
             self.view.handle_event(cx, event, scope);
         });
         let state = scope.data.get_mut::<State>().expect("State not found.");
-        let chat_ids = state.chat_info.keys().copied().collect::<Vec<_>>();
+        let rows = self.rows(state);
         let portal_list = self.view.portal_list(cx, ids!(chat_list));
         for (item_id, _) in portal_list.items_with_actions(&actions) {
-            let item_widget = portal_list.item(cx, item_id, id!(chat));
-            let body_view = item_widget.view(cx, ids!(body));
-            for action in &actions {
-                if let ViewAction::FingerUp(fe) = action.as_widget_action().cast()
-                    && body_view.area().rect(cx).contains(fe.abs)
-                    && let Some(selected_id) = chat_ids.get(item_id)
-                {
-                    state.open_chat_id = Some(*selected_id);
-                    self.load_msg_history(cx, state);
-                    log!("Opened chat: {}", *selected_id);
+            match rows.get(item_id) {
+                Some(ChatRow::Chat(chat_id)) => {
+                    let item_widget = portal_list.item(cx, item_id, id!(chat));
+                    if self.handle_menu(cx, &item_widget, &actions, state, *chat_id) {
+                        continue;
+                    }
+                    let body_view = item_widget.view(cx, ids!(body));
+                    for action in &actions {
+                        if let ViewAction::FingerUp(fe) = action.as_widget_action().cast()
+                            && body_view.area().rect(cx).contains(fe.abs)
+                        {
+                            state.go_to_chat(cx, *chat_id);
+                        }
+                    }
+                }
+                Some(ChatRow::ArchivedHeader(_)) => {
+                    let item_widget = portal_list.item(cx, item_id, id!(archived_header));
+                    if item_widget.button(cx, ids!(toggle)).clicked(&actions) {
+                        state.show_archived = !state.show_archived;
+                        self.redraw(cx);
+                    }
                 }
+                None => {}
             }
         }
         if self.view.button(cx, ids!(new_chat_btn)).clicked(&actions) {
-            log!("handle event chat list");
-            cx.action(AppAction::SwitchWindow(Screen::NewChatInit));
+            cx.action(RouterAction::Push(Route::new(NEW_CHAT_SCREEN)));
         }
         cx.extend_actions(actions);
     }
@@ -0,0 +1,452 @@
+use crate::shindensen_client::{FileMetadata, ShinDensenClient};
+use crate::storage::Store;
+use crate::time::Timestamp;
+use makepad_micro_serde::*;
+use makepad_widgets::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const CONFIG_FILE: &str = "downloads.json";
+const HISTORY_FILE: &str = "download_history.json";
+const MAX_HISTORY: usize = 500;
+
+/// Where downloads go, kept in `downloads.json` in the data directory, e.g.
+/// `{"directory": "/home/me/Downloads"}`, and set from the settings panel.
+/// Without it files go to `~/Downloads`.
+#[derive(Clone, Debug, Default, DeJson, SerJson, PartialEq)]
+pub struct DownloadsConfig {
+    pub directory: Option<String>,
+}
+
+impl DownloadsConfig {
+    pub fn load() -> Self {
+        Store::root()
+            .and_then(|root| std::fs::read_to_string(root.join(CONFIG_FILE)).ok())
+            .and_then(|data| DownloadsConfig::deserialize_json(&data).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        if let Some(root) = Store::root() {
+            let path = root.join(CONFIG_FILE);
+            if let Err(err) = std::fs::write(&path, self.serialize_json()) {
+                error!("Failed to write {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    pub fn directory(&self) -> Option<PathBuf> {
+        if let Some(dir) = &self.directory {
+            return Some(PathBuf::from(dir));
+        }
+        let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+        match home {
+            Some(home) => Some(PathBuf::from(home).join("Downloads")),
+            None => Store::root().map(|root| root.join("downloads")),
+        }
+    }
+}
+
+/// A file saved to disk, kept per account so it can be found again.
+#[derive(Clone, Debug, Default, DeJson, SerJson, PartialEq)]
+pub struct FinishedDownload {
+    pub url: String,
+    pub filename: String,
+    pub path: String,
+    pub size_bytes: i64,
+    pub finished_at: Timestamp,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DownloadStatus {
+    Running,
+    Failed(String),
+}
+
+/// A download in flight or one that failed, shown in its attachment row.
+#[derive(Debug)]
+pub struct Download {
+    pub filename: String,
+    pub expected: i64,
+    pub received: u64,
+    pub status: DownloadStatus,
+    request_id: LiveId,
+    /// Partial file, renamed to `target` once complete.
+    part_path: PathBuf,
+    target: PathBuf,
+    file: Option<File>,
+}
+
+impl Download {
+    /// Fraction done, when the size is known.
+    pub fn progress(&self) -> Option<f64> {
+        (self.expected > 0).then(|| (self.received as f64 / self.expected as f64).min(1.0))
+    }
+}
+
+/// Streams attachments to the downloads directory. Downloads are keyed by
+/// the file URL, so one file is never fetched twice at once.
+#[derive(Default)]
+pub struct Downloads {
+    config: DownloadsConfig,
+    active: HashMap<String, Download>,
+    finished: Vec<FinishedDownload>,
+}
+
+impl Downloads {
+    pub fn load(store: &Store) -> Self {
+        Self {
+            config: DownloadsConfig::load(),
+            active: HashMap::new(),
+            finished: store.load(HISTORY_FILE).unwrap_or_default(),
+        }
+    }
+
+    pub fn save(&self, store: &Store) {
+        store.save(HISTORY_FILE, &self.finished);
+    }
+
+    /// Where new downloads are saved.
+    pub fn directory(&self) -> Option<PathBuf> {
+        self.config.directory()
+    }
+
+    /// Saves new downloads to `directory`, or to the default one for `None`.
+    /// The directory must be an absolute path; it is created when needed.
+    pub fn set_directory(&mut self, directory: Option<String>) -> Result<(), String> {
+        if let Some(dir) = &directory
+            && !Path::new(dir).is_absolute()
+        {
+            return Err(format!("{} is not an absolute path", dir));
+        }
+        self.config.directory = directory;
+        self.config.save();
+        Ok(())
+    }
+
+    pub fn get(&self, url: &str) -> Option<&Download> {
+        self.active.get(url)
+    }
+
+    /// The latest finished download of `url` whose file is still there.
+    pub fn finished(&self, url: &str) -> Option<&FinishedDownload> {
+        self.finished
+            .iter()
+            .rev()
+            .find(|done| done.url == url && Path::new(&done.path).exists())
+    }
+
+    pub fn start(&mut self, cx: &mut Cx, client: &ShinDensenClient, file: &FileMetadata) {
+        if self
+            .active
+            .get(&file.url)
+            .is_some_and(|download| download.status == DownloadStatus::Running)
+        {
+            return;
+        }
+        let request_id = LiveId::unique();
+        let mut download = Download {
+            filename: file.filename.clone(),
+            expected: file.size_bytes,
+            received: 0,
+            status: DownloadStatus::Running,
+            request_id,
+            part_path: PathBuf::new(),
+            target: PathBuf::new(),
+            file: None,
+        };
+        let opened = self
+            .config
+            .directory()
+            .ok_or("no downloads directory".to_string())
+            .and_then(|dir| {
+                std::fs::create_dir_all(&dir).map_err(|err| err.to_string())?;
+                let in_use: Vec<&Path> = self
+                    .active
+                    .values()
+                    .filter(|active| active.status == DownloadStatus::Running)
+                    .map(|active| active.target.as_path())
+                    .collect();
+                download.target = unique_path(&dir, &file.filename, &in_use);
+                download.part_path = download
+                    .target
+                    .with_extension(part_extension(&download.target));
+                File::create(&download.part_path).map_err(|err| err.to_string())
+            });
+        match opened {
+            Ok(handle) => {
+                download.file = Some(handle);
+                let mut request = HttpRequest::new(client.resolve_url(&file.url), HttpMethod::GET);
+                client.sign_request(&mut request);
+                request.set_is_streaming();
+                cx.http_request(request_id, request);
+            }
+            Err(err) => download.status = DownloadStatus::Failed(err),
+        }
+        self.active.insert(file.url.clone(), download);
+    }
+
+    pub fn cancel(&mut self, cx: &mut Cx, url: &str) {
+        if let Some(download) = self.active.remove(url) {
+            if download.status == DownloadStatus::Running {
+                cx.cancel_http_request(download.request_id);
+            }
+            drop(download.file);
+            let _ = std::fs::remove_file(&download.part_path);
+        }
+    }
+
+    /// Drops a failed download so its row offers a fresh one.
+    pub fn dismiss(&mut self, url: &str) {
+        if self
+            .active
+            .get(url)
+            .is_some_and(|download| download.status != DownloadStatus::Running)
+        {
+            self.active.remove(url);
+        }
+    }
+
+    fn url_of(&self, request_id: &LiveId) -> Option<String> {
+        self.active
+            .iter()
+            .find(|(_, download)| download.request_id == *request_id)
+            .map(|(url, _)| url.clone())
+    }
+
+    fn write_chunk(&mut self, cx: &mut Cx, url: &str, status_code: u16, chunk: &[u8]) {
+        let Some(download) = self
+            .active
+            .get_mut(url)
+            .filter(|download| download.status == DownloadStatus::Running)
+        else {
+            return;
+        };
+        let result = if status_code != 200 {
+            Err(format!("HTTP {}", status_code))
+        } else {
+            match &mut download.file {
+                Some(file) => file.write_all(chunk).map_err(|err| err.to_string()),
+                None => Err("file closed".to_string()),
+            }
+        };
+        match result {
+            Ok(()) => download.received += chunk.len() as u64,
+            Err(err) => {
+                cx.cancel_http_request(download.request_id);
+                self.fail(url, err);
+            }
+        }
+    }
+
+    fn fail(&mut self, url: &str, reason: String) {
+        if let Some(download) = self.active.get_mut(url) {
+            error!("Download of {} failed: {}", download.filename, reason);
+            download.file = None;
+            let _ = std::fs::remove_file(&download.part_path);
+            download.status = DownloadStatus::Failed(reason);
+        }
+    }
+
+    /// Checks the size, moves the file into place and records it. Returns
+    /// whether the history changed.
+    fn complete(&mut self, url: &str) -> bool {
+        let Some(download) = self.active.get_mut(url) else {
+            return false;
+        };
+        if download.status != DownloadStatus::Running {
+            return false;
+        }
+        let flushed = download.file.take().map_or(Ok(()), |mut file| file.flush());
+        if let Err(err) = flushed {
+            self.fail(url, err.to_string());
+            return false;
+        }
+        if download.expected > 0 && download.received != download.expected as u64 {
+            let reason = format!(
+                "size mismatch: got {} of {} bytes",
+                download.received, download.expected
+            );
+            self.fail(url, reason);
+            return false;
+        }
+        if let Err(err) = std::fs::rename(&download.part_path, &download.target) {
+            self.fail(url, err.to_string());
+            return false;
+        }
+        let Some(download) = self.active.remove(url) else {
+            return false;
+        };
+        self.finished.push(FinishedDownload {
+            url: url.to_string(),
+            filename: download.filename,
+            path: download.target.to_string_lossy().into_owned(),
+            size_bytes: download.received as i64,
+            finished_at: Timestamp::now(),
+        });
+        if self.finished.len() > MAX_HISTORY {
+            self.finished.remove(0);
+        }
+        true
+    }
+
+    /// Returns whether a download finished, so the history needs saving.
+    pub fn handle_network_responses(
+        &mut self,
+        cx: &mut Cx,
+        responses: &NetworkResponsesEvent,
+    ) -> bool {
+        let mut finished = false;
+        for event in responses {
+            match event {
+                NetworkResponse::HttpStreamResponse {
+                    request_id,
+                    response,
+                } => {
+                    if let Some(url) = self.url_of(request_id) {
+                        let chunk = response.get_body().map_or(&[][..], |body| body.as_slice());
+                        self.write_chunk(cx, &url, response.status_code, chunk);
+                        cx.redraw_all();
+                    }
+                }
+                NetworkResponse::HttpStreamComplete { request_id, .. } => {
+                    if let Some(url) = self.url_of(request_id) {
+                        finished |= self.complete(&url);
+                        cx.redraw_all();
+                    }
+                }
+                // Platforms without streaming deliver the whole file at once.
+                NetworkResponse::HttpResponse {
+                    request_id,
+                    response,
+                } => {
+                    if let Some(url) = self.url_of(request_id) {
+                        let chunk = response.get_body().map_or(&[][..], |body| body.as_slice());
+                        self.write_chunk(cx, &url, response.status_code, chunk);
+                        finished |= self.complete(&url);
+                        cx.redraw_all();
+                    }
+                }
+                NetworkResponse::HttpError { request_id, error } => {
+                    if let Some(url) = self.url_of(request_id) {
+                        self.fail(&url, format!("{:?}", error.message));
+                        cx.redraw_all();
+                    }
+                }
+                _ => {}
+            }
+        }
+        finished
+    }
+
+    /// Stops everything in flight, e.g. on logout.
+    pub fn cancel_all(&mut self, cx: &mut Cx) {
+        let urls: Vec<String> = self.active.keys().cloned().collect();
+        for url in urls {
+            self.cancel(cx, &url);
+        }
+    }
+}
+
+/// `dir/name`, or `dir/name (2).ext` and so on if that is taken: by a file,
+/// by the partial file of another download, or by one of the targets of the
+/// downloads in `in_use`, which don't exist until they finish.
+fn unique_path(dir: &Path, filename: &str, in_use: &[&Path]) -> PathBuf {
+    // Only the last path component, so a crafted name can't escape `dir`.
+    let name = Path::new(filename)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .filter(|name| !name.is_empty() && name != "..")
+        .unwrap_or_else(|| "download".to_string());
+    let free = |candidate: &PathBuf| {
+        !candidate.exists()
+            && !candidate.with_extension(part_extension(candidate)).exists()
+            && !in_use.contains(&candidate.as_path())
+    };
+    let candidate = dir.join(&name);
+    if free(&candidate) {
+        return candidate;
+    }
+    let path = Path::new(&name);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    (2..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, extension)))
+        .find(free)
+        .expect("some numbered name is free")
+}
+
+/// Extension of the partial file next to `target`, e.g. `pdf.part`.
+fn part_extension(target: &Path) -> String {
+    match target.extension() {
+        Some(ext) => format!("{}.part", ext.to_string_lossy()),
+        None => "part".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh empty directory for one test.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "shindensen-downloads-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn unique_path_numbers_taken_names() {
+        let dir = scratch_dir("taken");
+        assert_eq!(unique_path(&dir, "a.pdf", &[]), dir.join("a.pdf"));
+        std::fs::write(dir.join("a.pdf"), b"").unwrap();
+        assert_eq!(unique_path(&dir, "a.pdf", &[]), dir.join("a (2).pdf"));
+        std::fs::write(dir.join("a (2).pdf"), b"").unwrap();
+        assert_eq!(unique_path(&dir, "a.pdf", &[]), dir.join("a (3).pdf"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unique_path_skips_partial_files_and_running_downloads() {
+        let dir = scratch_dir("partial");
+        std::fs::write(dir.join("a.pdf.part"), b"").unwrap();
+        assert_eq!(unique_path(&dir, "a.pdf", &[]), dir.join("a (2).pdf"));
+        let running = dir.join("b");
+        assert_eq!(
+            unique_path(&dir, "b", &[running.as_path()]),
+            dir.join("b (2)")
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unique_path_stays_in_the_directory() {
+        let dir = scratch_dir("escape");
+        assert_eq!(
+            unique_path(&dir, "../../etc/passwd", &[]),
+            dir.join("passwd")
+        );
+        assert_eq!(unique_path(&dir, "..", &[]), dir.join("download"));
+        assert_eq!(unique_path(&dir, "", &[]), dir.join("download"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn part_extension_keeps_the_original_one() {
+        assert_eq!(part_extension(Path::new("a.pdf")), "pdf.part");
+        assert_eq!(part_extension(Path::new("a")), "part");
+    }
+}
@@ -0,0 +1,364 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmojiCategory {
+    Smileys,
+    People,
+    Nature,
+    Food,
+    Activities,
+    Travel,
+    Objects,
+    Symbols,
+}
+
+impl EmojiCategory {
+    pub const ALL: [EmojiCategory; 8] = [
+        EmojiCategory::Smileys,
+        EmojiCategory::People,
+        EmojiCategory::Nature,
+        EmojiCategory::Food,
+        EmojiCategory::Activities,
+        EmojiCategory::Travel,
+        EmojiCategory::Objects,
+        EmojiCategory::Symbols,
+    ];
+
+    /// Emoji standing for the category on its tab.
+    pub fn icon(self) -> &'static str {
+        match self {
+            EmojiCategory::Smileys => "😀",
+            EmojiCategory::People => "👋",
+            EmojiCategory::Nature => "🌿",
+            EmojiCategory::Food => "🍕",
+            EmojiCategory::Activities => "⚽",
+            EmojiCategory::Travel => "✈️",
+            EmojiCategory::Objects => "💡",
+            EmojiCategory::Symbols => "❤️",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Emoji {
+    pub glyph: &'static str,
+    pub shortcode: &'static str,
+    pub category: EmojiCategory,
+}
+
+const fn emoji(glyph: &'static str, shortcode: &'static str, category: EmojiCategory) -> Emoji {
+    Emoji {
+        glyph,
+        shortcode,
+        category,
+    }
+}
+
+use EmojiCategory::*;
+
+pub static EMOJI: &[Emoji] = &[
+    emoji("😀", "grinning", Smileys),
+    emoji("😃", "smiley", Smileys),
+    emoji("😄", "smile", Smileys),
+    emoji("😁", "grin", Smileys),
+    emoji("😆", "laughing", Smileys),
+    emoji("😅", "sweat_smile", Smileys),
+    emoji("🤣", "rofl", Smileys),
+    emoji("😂", "joy", Smileys),
+    emoji("🙂", "slightly_smiling_face", Smileys),
+    emoji("🙃", "upside_down_face", Smileys),
+    emoji("😉", "wink", Smileys),
+    emoji("😊", "blush", Smileys),
+    emoji("😇", "innocent", Smileys),
+    emoji("🥰", "smiling_face_with_hearts", Smileys),
+    emoji("😍", "heart_eyes", Smileys),
+    emoji("😘", "kissing_heart", Smileys),
+    emoji("😋", "yum", Smileys),
+    emoji("😛", "stuck_out_tongue", Smileys),
+    emoji("😜", "stuck_out_tongue_winking_eye", Smileys),
+    emoji("🤪", "zany_face", Smileys),
+    emoji("🤔", "thinking", Smileys),
+    emoji("🤨", "raised_eyebrow", Smileys),
+    emoji("😐", "neutral_face", Smileys),
+    emoji("😑", "expressionless", Smileys),
+    emoji("😶", "no_mouth", Smileys),
+    emoji("🙄", "roll_eyes", Smileys),
+    emoji("😏", "smirk", Smileys),
+    emoji("😬", "grimacing", Smileys),
+    emoji("😌", "relieved", Smileys),
+    emoji("😔", "pensive", Smileys),
+    emoji("😴", "sleeping", Smileys),
+    emoji("😷", "mask", Smileys),
+    emoji("🤒", "face_with_thermometer", Smileys),
+    emoji("🤯", "exploding_head", Smileys),
+    emoji("🥳", "partying_face", Smileys),
+    emoji("😎", "sunglasses", Smileys),
+    emoji("🤓", "nerd_face", Smileys),
+    emoji("😕", "confused", Smileys),
+    emoji("😟", "worried", Smileys),
+    emoji("😮", "open_mouth", Smileys),
+    emoji("😲", "astonished", Smileys),
+    emoji("😳", "flushed", Smileys),
+    emoji("🥺", "pleading_face", Smileys),
+    emoji("😢", "cry", Smileys),
+    emoji("😭", "sob", Smileys),
+    emoji("😱", "scream", Smileys),
+    emoji("😤", "triumph", Smileys),
+    emoji("😡", "rage", Smileys),
+    emoji("😠", "angry", Smileys),
+    emoji("💀", "skull", Smileys),
+    emoji("💩", "poop", Smileys),
+    emoji("🤡", "clown_face", Smileys),
+    emoji("👻", "ghost", Smileys),
+    emoji("👽", "alien", Smileys),
+    emoji("🤖", "robot", Smileys),
+    emoji("👋", "wave", People),
+    emoji("🤚", "raised_back_of_hand", People),
+    emoji("✋", "hand", People),
+    emoji("👌", "ok_hand", People),
+    emoji("🤌", "pinched_fingers", People),
+    emoji("✌️", "v", People),
+    emoji("🤞", "crossed_fingers", People),
+    emoji("🤘", "metal", People),
+    emoji("👈", "point_left", People),
+    emoji("👉", "point_right", People),
+    emoji("👆", "point_up_2", People),
+    emoji("👇", "point_down", People),
+    emoji("👍", "thumbsup", People),
+    emoji("👎", "thumbsdown", People),
+    emoji("✊", "fist", People),
+    emoji("👊", "punch", People),
+    emoji("👏", "clap", People),
+    emoji("🙌", "raised_hands", People),
+    emoji("🙏", "pray", People),
+    emoji("🤝", "handshake", People),
+    emoji("💪", "muscle", People),
+    emoji("👀", "eyes", People),
+    emoji("🧠", "brain", People),
+    emoji("🤷", "shrug", People),
+    emoji("🤦", "facepalm", People),
+    emoji("🙋", "raising_hand", People),
+    emoji("🐶", "dog", Nature),
+    emoji("🐱", "cat", Nature),
+    emoji("🐭", "mouse", Nature),
+    emoji("🦊", "fox_face", Nature),
+    emoji("🐻", "bear", Nature),
+    emoji("🐼", "panda_face", Nature),
+    emoji("🐸", "frog", Nature),
+    emoji("🐵", "monkey_face", Nature),
+    emoji("🐔", "chicken", Nature),
+    emoji("🐧", "penguin", Nature),
+    emoji("🦀", "crab", Nature),
+    emoji("🐍", "snake", Nature),
+    emoji("🐢", "turtle", Nature),
+    emoji("🐝", "bee", Nature),
+    emoji("🦋", "butterfly", Nature),
+    emoji("🌸", "cherry_blossom", Nature),
+    emoji("🌹", "rose", Nature),
+    emoji("🌻", "sunflower", Nature),
+    emoji("🌲", "evergreen_tree", Nature),
+    emoji("🌵", "cactus", Nature),
+    emoji("🍀", "four_leaf_clover", Nature),
+    emoji("🌞", "sun_with_face", Nature),
+    emoji("🌙", "crescent_moon", Nature),
+    emoji("⭐", "star", Nature),
+    emoji("🌈", "rainbow", Nature),
+    emoji("❄️", "snowflake", Nature),
+    emoji("🔥", "fire", Nature),
+    emoji("🌊", "ocean", Nature),
+    emoji("🍎", "apple", Food),
+    emoji("🍌", "banana", Food),
+    emoji("🍉", "watermelon", Food),
+    emoji("🍓", "strawberry", Food),
+    emoji("🍒", "cherries", Food),
+    emoji("🥑", "avocado", Food),
+    emoji("🥕", "carrot", Food),
+    emoji("🍞", "bread", Food),
+    emoji("🧀", "cheese", Food),
+    emoji("🍔", "hamburger", Food),
+    emoji("🍟", "fries", Food),
+    emoji("🍕", "pizza", Food),
+    emoji("🌮", "taco", Food),
+    emoji("🍣", "sushi", Food),
+    emoji("🍜", "ramen", Food),
+    emoji("🍙", "rice_ball", Food),
+    emoji("🍩", "doughnut", Food),
+    emoji("🍪", "cookie", Food),
+    emoji("🎂", "birthday", Food),
+    emoji("🍫", "chocolate_bar", Food),
+    emoji("☕", "coffee", Food),
+    emoji("🍵", "tea", Food),
+    emoji("🍺", "beer", Food),
+    emoji("🍷", "wine_glass", Food),
+    emoji("⚽", "soccer", Activities),
+    emoji("🏀", "basketball", Activities),
+    emoji("🏈", "football", Activities),
+    emoji("🎾", "tennis", Activities),
+    emoji("🏓", "ping_pong", Activities),
+    emoji("🎯", "dart", Activities),
+    emoji("🎮", "video_game", Activities),
+    emoji("🎲", "game_die", Activities),
+    emoji("🧩", "jigsaw", Activities),
+    emoji("🎨", "art", Activities),
+    emoji("🎸", "guitar", Activities),
+    emoji("🎧", "headphones", Activities),
+    emoji("🏆", "trophy", Activities),
+    emoji("🥇", "first_place_medal", Activities),
+    emoji("🎉", "tada", Activities),
+    emoji("🎊", "confetti_ball", Activities),
+    emoji("🎁", "gift", Activities),
+    emoji("🚗", "car", Travel),
+    emoji("🚕", "taxi", Travel),
+    emoji("🚌", "bus", Travel),
+    emoji("🚲", "bike", Travel),
+    emoji("🚀", "rocket", Travel),
+    emoji("✈️", "airplane", Travel),
+    emoji("🚢", "ship", Travel),
+    emoji("🚂", "steam_locomotive", Travel),
+    emoji("🏠", "house", Travel),
+    emoji("🏢", "office", Travel),
+    emoji("🏖️", "beach_umbrella", Travel),
+    emoji("🗻", "mount_fuji", Travel),
+    emoji("🗼", "tokyo_tower", Travel),
+    emoji("🌍", "earth_africa", Travel),
+    emoji("🗺️", "world_map", Travel),
+    emoji("💡", "bulb", Objects),
+    emoji("💻", "computer", Objects),
+    emoji("⌨️", "keyboard", Objects),
+    emoji("🖥️", "desktop_computer", Objects),
+    emoji("📱", "iphone", Objects),
+    emoji("📷", "camera", Objects),
+    emoji("🔋", "battery", Objects),
+    emoji("🔌", "electric_plug", Objects),
+    emoji("📚", "books", Objects),
+    emoji("📝", "memo", Objects),
+    emoji("📎", "paperclip", Objects),
+    emoji("📌", "pushpin", Objects),
+    emoji("✂️", "scissors", Objects),
+    emoji("🔒", "lock", Objects),
+    emoji("🔑", "key", Objects),
+    emoji("🔨", "hammer", Objects),
+    emoji("🔧", "wrench", Objects),
+    emoji("⚙️", "gear", Objects),
+    emoji("🧪", "test_tube", Objects),
+    emoji("📦", "package", Objects),
+    emoji("📅", "date", Objects),
+    emoji("⏰", "alarm_clock", Objects),
+    emoji("💰", "moneybag", Objects),
+    emoji("❤️", "heart", Symbols),
+    emoji("🧡", "orange_heart", Symbols),
+    emoji("💛", "yellow_heart", Symbols),
+    emoji("💚", "green_heart", Symbols),
+    emoji("💙", "blue_heart", Symbols),
+    emoji("💜", "purple_heart", Symbols),
+    emoji("🖤", "black_heart", Symbols),
+    emoji("💔", "broken_heart", Symbols),
+    emoji("💯", "100", Symbols),
+    emoji("✅", "white_check_mark", Symbols),
+    emoji("✔️", "heavy_check_mark", Symbols),
+    emoji("❌", "x", Symbols),
+    emoji("❗", "exclamation", Symbols),
+    emoji("❓", "question", Symbols),
+    emoji("⚠️", "warning", Symbols),
+    emoji("🚫", "no_entry_sign", Symbols),
+    emoji("➕", "heavy_plus_sign", Symbols),
+    emoji("➖", "heavy_minus_sign", Symbols),
+    emoji("🔴", "red_circle", Symbols),
+    emoji("🟢", "green_circle", Symbols),
+    emoji("✨", "sparkles", Symbols),
+    emoji("💤", "zzz", Symbols),
+    emoji("💬", "speech_balloon", Symbols),
+    emoji("🆗", "ok", Symbols),
+    emoji("🆕", "new", Symbols),
+];
+
+pub fn by_shortcode(shortcode: &str) -> Option<&'static Emoji> {
+    EMOJI.iter().find(|emoji| emoji.shortcode == shortcode)
+}
+
+pub fn in_category(category: EmojiCategory) -> impl Iterator<Item = &'static Emoji> {
+    EMOJI.iter().filter(move |emoji| emoji.category == category)
+}
+
+/// Emoji whose shortcode contains `query`, those starting with it first.
+pub fn search(query: &str) -> Vec<&'static Emoji> {
+    let query = query.trim().trim_matches(':').to_lowercase();
+    let mut found: Vec<&Emoji> = EMOJI
+        .iter()
+        .filter(|emoji| emoji.shortcode.contains(&query))
+        .collect();
+    found.sort_by_key(|emoji| !emoji.shortcode.starts_with(&query));
+    found
+}
+
+fn is_shortcode_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '+' || c == '-'
+}
+
+/// Replaces a known `:shortcode:` whose closing colon was just typed, i.e.
+/// sits right before byte offset `cursor`, with its emoji. Returns the new
+/// text and cursor, or `None` to leave the text alone. The opening colon has
+/// to start a word, so `Foo::new:` or `1:100:` stay as they are.
+pub fn expand_shortcode_at(text: &str, cursor: usize) -> Option<(String, usize)> {
+    let before = text.get(..cursor)?.strip_suffix(':')?;
+    let code_start = before
+        .rfind(|c: char| !is_shortcode_char(c))
+        .map_or(0, |index| index + 1);
+    let open = before[..code_start].strip_suffix(':')?;
+    if open
+        .chars()
+        .next_back()
+        .is_some_and(|c| !c.is_whitespace() && !"([{\"'".contains(c))
+    {
+        return None;
+    }
+    let emoji = by_shortcode(&before[code_start..])?;
+    let mut out = String::with_capacity(text.len());
+    out.push_str(open);
+    out.push_str(emoji.glyph);
+    let cursor = out.len();
+    out.push_str(&text[before.len() + 1..]);
+    Some((out, cursor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(text: &str) -> Option<(String, usize)> {
+        expand_shortcode_at(text, text.len())
+    }
+
+    #[test]
+    fn expands_the_shortcode_just_closed() {
+        assert_eq!(expand(":smile:"), Some(("😄".into(), "😄".len())));
+        assert_eq!(expand("hi :joy:"), Some(("hi 😂".into(), "hi 😂".len())));
+        assert_eq!(expand("(:100:"), Some(("(💯".into(), "(💯".len())));
+    }
+
+    #[test]
+    fn keeps_the_text_after_the_cursor() {
+        let text = "a :heart: b";
+        assert_eq!(
+            expand_shortcode_at(text, "a :heart:".len()),
+            Some(("a ❤️ b".into(), "a ❤️".len()))
+        );
+    }
+
+    #[test]
+    fn leaves_code_and_numbers_alone() {
+        assert_eq!(expand("Foo::new:"), None);
+        assert_eq!(expand("Foo::smile:"), None);
+        assert_eq!(expand("1:100:"), None);
+        assert_eq!(expand("a:smile:"), None);
+        assert_eq!(expand(":unknown:"), None);
+        assert_eq!(expand("::"), None);
+        assert_eq!(expand(":smile"), None);
+    }
+
+    #[test]
+    fn only_the_shortcode_before_the_cursor_counts() {
+        // An earlier complete shortcode is not touched when typing elsewhere.
+        assert_eq!(expand_shortcode_at(":smile: x", 9), None);
+        assert_eq!(expand_shortcode_at(":smile:", 3), None);
+        assert_eq!(expand_shortcode_at(":smile:", 99), None);
+    }
+}
@@ -0,0 +1,266 @@
+use crate::emoji::{self, EmojiCategory};
+use crate::state::*;
+use crate::theme::{StyledRows, Theme, ThemeStamp};
+use makepad_widgets::*;
+
+const EMOJI_PER_ROW: usize = 8;
+
+script_mod! {
+    use mod.prelude.widgets.*
+    use mod.widgets.*
+
+    let EmojiButton = SDButton {
+        width: 40.0
+        padding: Inset { top: 4.0, right: 4.0, bottom: 4.0, left: 4.0 }
+        text: ""
+        draw_text +: { text_style +: { font_size: 16.0 } }
+    }
+
+    let TabButton = SDButton {
+        padding: Inset { top: 4.0, right: 6.0, bottom: 4.0, left: 6.0 }
+        draw_text +: { text_style +: { font_size: 12.0 } }
+    }
+
+    mod.widgets.EmojiPicker = #(EmojiPicker::register_widget(vm)) {
+        visible: false
+        width: 360.0
+        height: 300.0
+        panel := View {
+            width: Fill
+            height: Fill
+            flow: Down
+            spacing: 6.0
+            padding: Inset { top: 8.0, right: 8.0, bottom: 8.0, left: 8.0 }
+            show_bg: true
+            draw_bg +: {
+                color: mod.sd_theme.panel
+            }
+            query := SDTextInput{
+                width: Fill
+                empty_text: "Search emoji..."
+            }
+            tabs := View {
+                width: Fill
+                height: Fit
+                flow: Right
+                recent := TabButton{text: "🕘"}
+                tab0 := TabButton{}
+                tab1 := TabButton{}
+                tab2 := TabButton{}
+                tab3 := TabButton{}
+                tab4 := TabButton{}
+                tab5 := TabButton{}
+                tab6 := TabButton{}
+                tab7 := TabButton{}
+            }
+            empty := Label {
+                visible: false
+                width: Fill
+                text: "No emoji found."
+                draw_text +: {
+                    color: mod.sd_theme.text_muted
+                    text_style +: { font_size: 10.0 }
+                }
+            }
+            grid := PortalList{
+                height: Fill
+                scroll_bar: ScrollBar{}
+                row := View {
+                    width: Fill
+                    height: Fit
+                    flow: Right
+                    e0 := EmojiButton{}
+                    e1 := EmojiButton{}
+                    e2 := EmojiButton{}
+                    e3 := EmojiButton{}
+                    e4 := EmojiButton{}
+                    e5 := EmojiButton{}
+                    e6 := EmojiButton{}
+                    e7 := EmojiButton{}
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub enum EmojiPickerAction {
+    /// An emoji was chosen in the picker with the given uid, so several
+    /// pickers can live side by side.
+    Picked { picker: WidgetUid, glyph: String },
+    #[default]
+    None,
+}
+
+/// Emoji grid with category tabs and a search field. Anything that wants
+/// emoji input places one in its layout, toggles its visibility and listens
+/// for `EmojiPickerAction::Picked` with the picker's uid. The host keeps the
+/// recently used emoji: it records picks and hands them in with `set_recent`.
+#[derive(Script, ScriptHook, Widget)]
+pub struct EmojiPicker {
+    #[deref]
+    view: View,
+    /// Shown category; `None` is the recently used tab.
+    #[rust]
+    category: Option<EmojiCategory>,
+    /// Emoji of the grid as of the last draw, in reading order.
+    #[rust]
+    shown: Vec<String>,
+    /// Recently used emoji, most recent first, as given by the host.
+    #[rust]
+    recent: Vec<String>,
+    #[rust]
+    theme: ThemeStamp,
+    #[rust]
+    rows: StyledRows,
+}
+
+impl EmojiPicker {
+    /// Shows `recent` in the recently used tab.
+    pub fn set_recent(&mut self, cx: &mut Cx, recent: &[String]) {
+        if self.recent != recent {
+            self.recent = recent.to_vec();
+            self.redraw(cx);
+        }
+    }
+
+    fn apply_theme(&self, cx: &mut Cx, theme: &Theme) {
+        theme.style_background(cx, &self.widget(cx, ids!(panel)), theme.panel);
+        theme.style_input(cx, &self.widget(cx, ids!(query)));
+        theme.style_label(cx, &self.widget(cx, ids!(empty)), theme.text_muted);
+        theme.style_button(cx, &self.widget(cx, ids!(tabs.recent)));
+        for index in 0..EmojiCategory::ALL.len() {
+            theme.style_button(cx, &self.tab(cx, index));
+        }
+    }
+
+    fn tab(&self, cx: &Cx, index: usize) -> ButtonRef {
+        match index {
+            0 => self.button(cx, ids!(tabs.tab0)),
+            1 => self.button(cx, ids!(tabs.tab1)),
+            2 => self.button(cx, ids!(tabs.tab2)),
+            3 => self.button(cx, ids!(tabs.tab3)),
+            4 => self.button(cx, ids!(tabs.tab4)),
+            5 => self.button(cx, ids!(tabs.tab5)),
+            6 => self.button(cx, ids!(tabs.tab6)),
+            _ => self.button(cx, ids!(tabs.tab7)),
+        }
+    }
+
+    /// What the grid shows: search results while a query is typed, otherwise
+    /// the selected tab.
+    fn glyphs(&self, cx: &Cx) -> Vec<String> {
+        let query = self.text_input(cx, ids!(query)).text();
+        if !query.trim().is_empty() {
+            return emoji::search(&query)
+                .into_iter()
+                .map(|emoji| emoji.glyph.to_string())
+                .collect();
+        }
+        match self.category {
+            Some(category) => emoji::in_category(category)
+                .map(|emoji| emoji.glyph.to_string())
+                .collect(),
+            None => self.recent.clone(),
+        }
+    }
+}
+
+fn cell(cx: &Cx, row: &WidgetRef, column: usize) -> ButtonRef {
+    match column {
+        0 => row.button(cx, ids!(e0)),
+        1 => row.button(cx, ids!(e1)),
+        2 => row.button(cx, ids!(e2)),
+        3 => row.button(cx, ids!(e3)),
+        4 => row.button(cx, ids!(e4)),
+        5 => row.button(cx, ids!(e5)),
+        6 => row.button(cx, ids!(e6)),
+        _ => row.button(cx, ids!(e7)),
+    }
+}
+
+impl Widget for EmojiPicker {
+    fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        if self.category.is_none() && self.recent.is_empty() {
+            // Nothing used yet, start on the first category instead.
+            self.category = Some(EmojiCategory::ALL[0]);
+        }
+        // Without the app's state the picker keeps the look of its layout.
+        if let Some(state) = scope.data.get::<State>()
+            && self.theme.update(state.theme_version)
+        {
+            self.apply_theme(cx, &state.theme);
+        }
+        for (index, category) in EmojiCategory::ALL.into_iter().enumerate() {
+            self.tab(cx, index).set_text(cx, category.icon());
+        }
+        self.shown = self.glyphs(cx);
+        self.label(cx, ids!(empty))
+            .set_visible(cx, self.shown.is_empty());
+        let rows = self.shown.len().div_ceil(EMOJI_PER_ROW);
+        while let Some(item) = self.view.draw_walk(cx, scope, walk).step() {
+            if let Some(mut list) = item.as_portal_list().borrow_mut() {
+                let state = scope.data.get::<State>();
+                list.set_item_range(cx, 0, rows);
+                while let Some(item_id) = list.next_visible_item(cx) {
+                    if item_id >= rows {
+                        continue;
+                    }
+                    let row = list.item(cx, item_id, id!(row));
+                    let theme = state
+                        .filter(|state| self.rows.needs_style(state.theme_version, &row))
+                        .map(|state| &state.theme);
+                    for column in 0..EMOJI_PER_ROW {
+                        let glyph = self.shown.get(item_id * EMOJI_PER_ROW + column);
+                        let button = cell(cx, &row, column);
+                        if let Some(theme) = theme {
+                            theme.style_button(cx, &button);
+                        }
+                        button.set_visible(cx, glyph.is_some());
+                        if let Some(glyph) = glyph {
+                            button.set_text(cx, glyph);
+                        }
+                    }
+                    row.draw_all_unscoped(cx);
+                }
+            }
+        }
+        DrawStep::done()
+    }
+
+    fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
+        let actions = cx.capture_actions(|cx| {
+            self.view.handle_event(cx, event, scope);
+        });
+        if self.text_input(cx, ids!(query)).changed(&actions).is_some() {
+            self.redraw(cx);
+        }
+        if self.button(cx, ids!(tabs.recent)).clicked(&actions) {
+            self.category = None;
+            self.text_input(cx, ids!(query)).set_text(cx, "");
+            self.redraw(cx);
+        }
+        for (index, category) in EmojiCategory::ALL.into_iter().enumerate() {
+            if self.tab(cx, index).clicked(&actions) {
+                self.category = Some(category);
+                self.text_input(cx, ids!(query)).set_text(cx, "");
+                self.redraw(cx);
+            }
+        }
+        let portal_list = self.view.portal_list(cx, ids!(grid));
+        for (item_id, _) in portal_list.items_with_actions(&actions) {
+            let row = portal_list.item(cx, item_id, id!(row));
+            for column in 0..EMOJI_PER_ROW {
+                if cell(cx, &row, column).clicked(&actions)
+                    && let Some(glyph) = self.shown.get(item_id * EMOJI_PER_ROW + column).cloned()
+                {
+                    cx.action(EmojiPickerAction::Picked {
+                        picker: self.widget_uid(),
+                        glyph,
+                    });
+                }
+            }
+        }
+        cx.extend_actions(actions);
+    }
+}
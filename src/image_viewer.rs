@@ -0,0 +1,195 @@
+use crate::images::{ImageState, fit_size};
+use crate::state::*;
+use crate::theme::{Theme, ThemeStamp};
+use makepad_widgets::*;
+
+/// Zoom levels the +/- buttons step through.
+const ZOOM_STEPS: [f64; 9] = [0.1, 0.25, 0.5, 0.75, 1.0, 1.5, 2.0, 3.0, 4.0];
+const CANVAS_PADDING: f64 = 20.0;
+
+script_mod! {
+    use mod.prelude.widgets.*
+    use mod.widgets.*
+
+    mod.widgets.ImageViewer = #(ImageViewer::register_widget(vm)) {
+        width: Fill
+        height: Fill
+        flow: Down
+        show_bg: true
+        draw_bg +: {
+            color: #000000d8
+        }
+        toolbar := View {
+            width: Fill
+            height: Fit
+            flow: Right
+            align: Align { y: 0.5 }
+            spacing: 6.0
+            padding: Inset { top: 10.0, right: 10.0, bottom: 10.0, left: 10.0 }
+            name := H4 { width: Fill, text: "" }
+            zoom_out := SDButton{text: "-"}
+            zoom_label := Label {
+                width: 48.0
+                align: Align { x: 0.5 }
+                text: ""
+                draw_text +: {
+                    // On the dark backdrop whatever the theme, like the name.
+                    color: #ffffff
+                    text_style +: { font_size: 10.0 }
+                }
+            }
+            zoom_in := SDButton{text: "+"}
+            fit := SDButton{text: "Fit"}
+            close := SDButton{text: "Close"}
+        }
+        canvas := ScrollXYView {
+            width: Fill
+            height: Fill
+            align: Align { x: 0.5, y: 0.5 }
+            frame := View {
+                width: 100.0
+                height: 100.0
+                image := Image {
+                    width: Fill
+                    height: Fill
+                }
+            }
+        }
+    }
+}
+
+/// Full-size view of an image attachment over the whole window. Opens at a
+/// size that fits, then zooms with the buttons or Ctrl+Plus/Minus/0.
+#[derive(Script, ScriptHook, Widget)]
+struct ImageViewer {
+    #[deref]
+    view: View,
+    /// Chosen zoom factor; `None` fits the image to the window.
+    #[rust]
+    zoom: Option<f64>,
+    /// Zoom the image was drawn at, to step from when fitted.
+    #[rust]
+    shown_scale: f64,
+    /// URL shown on the last draw, to reset the zoom for a new image.
+    #[rust]
+    shown_url: Option<String>,
+    #[rust]
+    theme: ThemeStamp,
+}
+
+impl ImageViewer {
+    fn apply_theme(&self, cx: &mut Cx, theme: &Theme) {
+        for button in [
+            ids!(toolbar.zoom_out),
+            ids!(toolbar.zoom_in),
+            ids!(toolbar.fit),
+            ids!(toolbar.close),
+        ] {
+            theme.style_button(cx, &self.widget(cx, button));
+        }
+    }
+
+    fn step_zoom(&mut self, cx: &mut Cx, up: bool) {
+        let current = self.zoom.unwrap_or(self.shown_scale);
+        let next = if up {
+            ZOOM_STEPS.iter().copied().find(|&z| z > current + 0.001)
+        } else {
+            ZOOM_STEPS
+                .iter()
+                .rev()
+                .copied()
+                .find(|&z| z < current - 0.001)
+        };
+        if let Some(next) = next {
+            self.zoom = Some(next);
+            self.redraw(cx);
+        }
+    }
+}
+
+impl Widget for ImageViewer {
+    fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        let state = scope.data.get::<State>().expect("State not found.");
+        let Some(file) = &state.lightbox else {
+            self.shown_url = None;
+            return DrawStep::done();
+        };
+        let Some(ImageState::Ready {
+            texture,
+            width,
+            height,
+        }) = state.images.get(&file.url)
+        else {
+            return DrawStep::done();
+        };
+        if self.theme.update(state.theme_version) {
+            self.apply_theme(cx, &state.theme);
+        }
+        if self.shown_url.as_ref() != Some(&file.url) {
+            self.shown_url = Some(file.url.clone());
+            self.zoom = None;
+        }
+        let canvas = self.view(cx, ids!(canvas)).area().rect(cx).size;
+        let (max_width, max_height) = if canvas.x > 0.0 && canvas.y > 0.0 {
+            (canvas.x - CANVAS_PADDING, canvas.y - CANVAS_PADDING)
+        } else {
+            (800.0, 600.0)
+        };
+        let (draw_width, draw_height) = match self.zoom {
+            Some(zoom) => (*width as f64 * zoom, *height as f64 * zoom),
+            None => fit_size(*width, *height, max_width, max_height),
+        };
+        self.shown_scale = draw_width / (*width).max(1) as f64;
+        let frame = self.view(cx, ids!(canvas.frame));
+        script_apply_eval!(cx, frame, {
+            width: #(draw_width)
+            height: #(draw_height)
+        });
+        self.image(cx, ids!(canvas.frame.image))
+            .set_texture(cx, Some(texture.clone()));
+        self.label(cx, ids!(toolbar.name))
+            .set_text(cx, &file.filename);
+        self.label(cx, ids!(toolbar.zoom_label))
+            .set_text(cx, &format!("{:.0}%", self.shown_scale * 100.0));
+        self.view.draw_walk(cx, scope, walk)
+    }
+
+    fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
+        let state = scope.data.get::<State>().expect("State not found.");
+        if state.lightbox.is_none() {
+            return;
+        }
+        if let Event::KeyDown(ke) = event
+            && (ke.modifiers.control || ke.modifiers.logo)
+        {
+            match ke.key_code {
+                KeyCode::Equals => self.step_zoom(cx, true),
+                KeyCode::Minus => self.step_zoom(cx, false),
+                KeyCode::Key0 => {
+                    self.zoom = None;
+                    self.redraw(cx);
+                }
+                _ => {}
+            }
+        }
+        let actions = cx.capture_actions(|cx| {
+            self.view.handle_event(cx, event, scope);
+        });
+        let state = scope.data.get_mut::<State>().expect("State not found.");
+        if self.button(cx, ids!(toolbar.zoom_in)).clicked(&actions) {
+            self.step_zoom(cx, true);
+        }
+        if self.button(cx, ids!(toolbar.zoom_out)).clicked(&actions) {
+            self.step_zoom(cx, false);
+        }
+        if self.button(cx, ids!(toolbar.fit)).clicked(&actions) {
+            self.zoom = None;
+            self.redraw(cx);
+        }
+        if self.button(cx, ids!(toolbar.close)).clicked(&actions) {
+            state.lightbox = None;
+            cx.redraw_all();
+        }
+        cx.extend_actions(actions);
+    }
+}
@@ -0,0 +1,403 @@
+use crate::shindensen_client::{FileMetadata, ShinDensenClient};
+use crate::storage::Store;
+use makepad_widgets::image_cache::ImageBuffer;
+use makepad_widgets::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const CACHE_DIR: &str = "image_cache";
+/// Size the disk cache is trimmed back to, least recently used files first.
+const MAX_CACHE_BYTES: u64 = 200 * 1024 * 1024;
+/// Images larger than this are not previewed, only listed.
+pub const MAX_PREVIEW_BYTES: i64 = 10 * 1024 * 1024;
+/// Guards against tiny files that claim enormous dimensions.
+const MAX_PREVIEW_SIDE: u32 = 8192;
+pub const THUMB_MAX_WIDTH: f64 = 320.0;
+pub const THUMB_MAX_HEIGHT: f64 = 240.0;
+
+#[derive(Clone, Debug)]
+pub enum ImageState {
+    Loading,
+    Ready {
+        texture: Texture,
+        width: u32,
+        height: u32,
+    },
+    Failed(String),
+}
+
+/// Result of the worker thread that stores and decodes an image.
+type Prepared = (String, Result<ImageBuffer, String>);
+
+/// Previews of image attachments, keyed by URL, and of local files about to
+/// be sent. Files are fetched once and kept in a cache directory of the
+/// account, as fetches are signed with its session. Decoding happens off the UI thread, which only uploads the
+/// finished pixels as a texture.
+#[derive(Default)]
+pub struct ImagePreviews {
+    images: HashMap<String, ImageState>,
+    /// Fetches in flight: URL key and where the file goes in the cache.
+    requests: HashMap<LiveId, (String, PathBuf)>,
+    prepared: ToUIReceiver<Prepared>,
+}
+
+impl ImagePreviews {
+    pub fn get(&self, url: &str) -> Option<&ImageState> {
+        self.images.get(url)
+    }
+
+    /// Preview of a local file, see `request_local`.
+    pub fn get_local(&self, path: &str) -> Option<&ImageState> {
+        self.images.get(&local_key(path))
+    }
+
+    /// Starts decoding the local image at `path` unless it is known already.
+    pub fn request_local(&mut self, path: &str) {
+        let key = local_key(path);
+        if self.images.contains_key(&key) {
+            return;
+        }
+        self.images.insert(key.clone(), ImageState::Loading);
+        self.prepare(key, PathBuf::from(path), None, None);
+    }
+
+    /// Starts loading the preview of `file` unless it is known already.
+    pub fn request(
+        &mut self,
+        cx: &mut Cx,
+        client: &ShinDensenClient,
+        store: Option<&Store>,
+        file: &FileMetadata,
+    ) {
+        if self.images.contains_key(&file.url) {
+            return;
+        }
+        if file.size_bytes > MAX_PREVIEW_BYTES {
+            self.images.insert(
+                file.url.clone(),
+                ImageState::Failed("too large to preview".to_string()),
+            );
+            return;
+        }
+        // Keyed by the absolute URL so servers with the same paths don't collide.
+        let url = client.resolve_url(&file.url);
+        let Some(cache) = store.map(|store| store.path(CACHE_DIR)) else {
+            self.fail(file.url.clone(), "no cache directory".to_string());
+            return;
+        };
+        let path = cache.join(cache_key(&url));
+        self.images.insert(file.url.clone(), ImageState::Loading);
+        if path.exists() {
+            self.prepare(file.url.clone(), path, Some(cache), None);
+            return;
+        }
+        let mut request = HttpRequest::new(url, HttpMethod::GET);
+        client.sign_request(&mut request);
+        let request_id = LiveId::unique();
+        self.requests.insert(request_id, (file.url.clone(), path));
+        cx.http_request(request_id, request);
+    }
+
+    /// Writes fetched bytes to the `cache` directory, if any, and decodes the
+    /// image on a worker thread. Local files have no `cache`.
+    fn prepare(&self, key: String, path: PathBuf, cache: Option<PathBuf>, data: Option<Vec<u8>>) {
+        let sender = self.prepared.sender();
+        std::thread::spawn(move || {
+            let result = prepare_image(&path, cache.as_deref(), data);
+            let _ = sender.send((key, result));
+        });
+    }
+
+    fn fail(&mut self, url: String, reason: String) {
+        error!("Image preview of {} failed: {}", url, reason);
+        self.images.insert(url, ImageState::Failed(reason));
+    }
+
+    pub fn handle_network_responses(&mut self, cx: &mut Cx, responses: &NetworkResponsesEvent) {
+        for event in responses {
+            match event {
+                NetworkResponse::HttpResponse {
+                    request_id,
+                    response,
+                } => {
+                    let Some((url, path)) = self.requests.remove(request_id) else {
+                        continue;
+                    };
+                    let data = response.get_body().cloned().unwrap_or_default();
+                    if response.status_code != 200 {
+                        self.fail(url, format!("HTTP {}", response.status_code));
+                    } else if data.len() as i64 > MAX_PREVIEW_BYTES {
+                        self.fail(url, "too large to preview".to_string());
+                    } else {
+                        let cache = path.parent().map(Path::to_path_buf);
+                        self.prepare(url, path, cache, Some(data));
+                    }
+                    cx.redraw_all();
+                }
+                NetworkResponse::HttpError { request_id, error } => {
+                    if let Some((url, _)) = self.requests.remove(request_id) {
+                        self.fail(url, format!("{:?}", error.message));
+                        cx.redraw_all();
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Forgets the previews of the session; the disk cache stays.
+    pub fn clear(&mut self) {
+        self.images.clear();
+        self.requests.clear();
+    }
+
+    /// Turns images the worker decoded into textures. Returns whether any
+    /// changed.
+    pub fn handle_signal(&mut self, cx: &mut Cx) -> bool {
+        let mut changed = false;
+        while let Ok((url, result)) = self.prepared.try_recv() {
+            let state = match result {
+                Ok(buffer) => ImageState::Ready {
+                    width: buffer.width as u32,
+                    height: buffer.height as u32,
+                    texture: buffer.into_new_texture(cx),
+                },
+                Err(reason) => {
+                    error!("Image preview of {} failed: {}", url, reason);
+                    ImageState::Failed(reason)
+                }
+            };
+            self.images.insert(url, state);
+            changed = true;
+        }
+        changed
+    }
+}
+
+/// Deletes the cache all accounts used to share, in the background.
+pub fn remove_shared_cache() {
+    let Some(dir) = Store::root().map(|root| root.join(CACHE_DIR)) else {
+        return;
+    };
+    if dir.exists() {
+        std::thread::spawn(move || {
+            if let Err(err) = std::fs::remove_dir_all(&dir) {
+                error!("Failed to remove {}: {}", dir.display(), err);
+            }
+        });
+    }
+}
+
+/// File name of `url` in the cache. FNV-1a rather than the std hasher,
+/// whose output may change between Rust releases and orphan the cache.
+fn cache_key(url: &str) -> String {
+    let hash = url.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+fn local_key(path: &str) -> String {
+    format!("local:{}", path)
+}
+
+/// Stores fetched bytes in the `cache` directory, or reads the file at
+/// `path`, and decodes it after checking the header.
+fn prepare_image(
+    path: &Path,
+    cache: Option<&Path>,
+    data: Option<Vec<u8>>,
+) -> Result<ImageBuffer, String> {
+    let data = match data {
+        Some(data) => {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+            }
+            std::fs::write(path, &data).map_err(|err| err.to_string())?;
+            if let Some(cache) = cache {
+                trim_cache(cache);
+            }
+            data
+        }
+        None => {
+            let size = std::fs::metadata(path)
+                .map_err(|err| err.to_string())?
+                .len();
+            if size > MAX_PREVIEW_BYTES as u64 {
+                return Err("too large to preview".to_string());
+            }
+            // Marks a cache hit as recently used. Local files are left alone.
+            if cache.is_some()
+                && let Ok(file) = std::fs::File::options().append(true).open(path)
+            {
+                let _ = file.set_modified(SystemTime::now());
+            }
+            std::fs::read(path).map_err(|err| err.to_string())?
+        }
+    };
+    let (width, height) = image_size(&data).ok_or("not a PNG or JPEG image")?;
+    if width == 0 || height == 0 || width > MAX_PREVIEW_SIDE || height > MAX_PREVIEW_SIDE {
+        return Err(format!("unsupported dimensions {}x{}", width, height));
+    }
+    let buffer = if data.starts_with(b"\x89PNG") {
+        ImageBuffer::from_png(&data)
+    } else {
+        ImageBuffer::from_jpg(&data)
+    };
+    buffer.map_err(|err| format!("{:?}", err))
+}
+
+/// Deletes the least recently used cache files while the cache is larger
+/// than `MAX_CACHE_BYTES`.
+fn trim_cache(cache: &Path) {
+    let Ok(entries) = std::fs::read_dir(cache) else {
+        return;
+    };
+    let files = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            let used = metadata.modified().ok()?;
+            metadata
+                .is_file()
+                .then(|| (entry.path(), metadata.len(), used))
+        })
+        .collect();
+    for path in evicted(files, MAX_CACHE_BYTES) {
+        if let Err(err) = std::fs::remove_file(&path) {
+            error!("Failed to evict {}: {}", path.display(), err);
+        }
+    }
+}
+
+/// Files to delete so the rest fits in `max_bytes`: the least recently
+/// used ones, given as path, size and time of last use.
+fn evicted(mut files: Vec<(PathBuf, u64, SystemTime)>, max_bytes: u64) -> Vec<PathBuf> {
+    let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+    files.sort_by_key(|(_, _, used)| *used);
+    files
+        .into_iter()
+        .take_while(|(_, size, _)| {
+            let over = total > max_bytes;
+            total -= size;
+            over
+        })
+        .map(|(path, _, _)| path)
+        .collect()
+}
+
+/// Width and height from the header of a PNG or JPEG file.
+pub fn image_size(data: &[u8]) -> Option<(u32, u32)> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") && data.get(12..16) == Some(b"IHDR") {
+        let width = u32::from_be_bytes(data.get(16..20)?.try_into().ok()?);
+        let height = u32::from_be_bytes(data.get(20..24)?.try_into().ok()?);
+        return Some((width, height));
+    }
+    if data.starts_with(&[0xFF, 0xD8]) {
+        let mut pos = 2;
+        while pos + 4 <= data.len() {
+            if data[pos] != 0xFF {
+                return None;
+            }
+            let marker = data[pos + 1];
+            if marker == 0xFF {
+                // Fill byte before the marker.
+                pos += 1;
+                continue;
+            }
+            let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+            // Start-of-frame markers carry the size; C4, C8 and CC are tables.
+            if matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+                let frame = data.get(pos + 5..pos + 9)?;
+                let height = u16::from_be_bytes([frame[0], frame[1]]) as u32;
+                let width = u16::from_be_bytes([frame[2], frame[3]]) as u32;
+                return Some((width, height));
+            }
+            pos += 2 + length;
+        }
+    }
+    None
+}
+
+/// Largest size with the aspect ratio of `width` x `height` that fits in the
+/// bounds, never scaling up.
+pub fn fit_size(width: u32, height: u32, max_width: f64, max_height: f64) -> (f64, f64) {
+    let (width, height) = (width.max(1) as f64, height.max(1) as f64);
+    let scale = (max_width / width).min(max_height / height).min(1.0);
+    (width * scale, height * scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn png_header(width: u32, height: u32) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        data.extend(width.to_be_bytes());
+        data.extend(height.to_be_bytes());
+        data
+    }
+
+    fn jpeg_header(width: u16, height: u16) -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8];
+        // An APP0 segment to skip, then a fill byte and the baseline frame.
+        data.extend([0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00]);
+        data.extend([0xFF, 0xFF, 0xC0, 0x00, 0x11, 0x08]);
+        data.extend(height.to_be_bytes());
+        data.extend(width.to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn cache_keys_are_stable() {
+        assert_eq!(cache_key(""), "cbf29ce484222325");
+        assert_eq!(cache_key("a"), "af63dc4c8601ec8c");
+        assert_ne!(
+            cache_key("https://a.example/files/1"),
+            cache_key("https://b.example/files/1")
+        );
+    }
+
+    #[test]
+    fn image_size_reads_png_and_jpeg_headers() {
+        assert_eq!(image_size(&png_header(640, 480)), Some((640, 480)));
+        assert_eq!(image_size(&jpeg_header(1024, 768)), Some((1024, 768)));
+    }
+
+    #[test]
+    fn image_size_rejects_other_data() {
+        assert_eq!(image_size(b"GIF89a"), None);
+        assert_eq!(image_size(&png_header(1, 1)[..20]), None);
+        assert_eq!(image_size(&[0xFF, 0xD8, 0x00, 0x00, 0x00]), None);
+        assert_eq!(image_size(&[]), None);
+    }
+
+    #[test]
+    fn fit_size_keeps_the_aspect_ratio() {
+        assert_eq!(fit_size(640, 480, 320.0, 240.0), (320.0, 240.0));
+        assert_eq!(fit_size(1000, 100, 320.0, 240.0), (320.0, 32.0));
+        assert_eq!(fit_size(100, 1000, 320.0, 240.0), (24.0, 240.0));
+        // Small images are not scaled up.
+        assert_eq!(fit_size(50, 40, 320.0, 240.0), (50.0, 40.0));
+        assert_eq!(fit_size(0, 0, 320.0, 240.0), (1.0, 1.0));
+    }
+
+    #[test]
+    fn eviction_drops_least_recently_used_files() {
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let files = vec![
+            (PathBuf::from("new"), 40, at(30)),
+            (PathBuf::from("old"), 40, at(10)),
+            (PathBuf::from("mid"), 40, at(20)),
+        ];
+        assert!(evicted(files.clone(), 120).is_empty());
+        assert_eq!(evicted(files.clone(), 100), vec![PathBuf::from("old")]);
+        assert_eq!(
+            evicted(files.clone(), 40),
+            vec![PathBuf::from("old"), PathBuf::from("mid")]
+        );
+        assert_eq!(evicted(files, 0).len(), 3);
+    }
+}
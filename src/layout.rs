@@ -1,5 +1,16 @@
 use makepad_widgets::*;
 
+/// Width of the chat list at a scale of 1, and the limits of resizing it.
+pub const CONTACTS_WIDTH: f64 = 350.0;
+pub const MIN_CONTACTS_WIDTH: f64 = 220.0;
+pub const MAX_CONTACTS_WIDTH: f64 = 600.0;
+/// Dragging the splitter left of this collapses the chat list to avatars.
+pub const COLLAPSE_CONTACTS_BELOW: f64 = 160.0;
+pub const COLLAPSED_CONTACTS_WIDTH: f64 = 80.0;
+/// Windows narrower than this, at a scale of 1, show the chat list and the
+/// open chat one at a time.
+pub const SINGLE_PANE_WIDTH: f64 = 720.0;
+
 script_mod! {
     use mod.prelude.widgets.*
 
@@ -15,7 +26,7 @@ script_mod! {
             height: Fill
             show_bg: true
             draw_bg +: {
-                color: instance(#3f497e)
+                color: instance(mod.sd_theme.surface)
                 border_radius: 5.0
             }
             padding: Inset{ top: 0.0, right: 10.0, bottom: 0.0, left: 0.0 }
@@ -26,12 +37,23 @@ script_mod! {
             scroll_bars := ScrollBars{show_scroll_x: false, show_scroll_y: true}
         }
 
+        // Drag to resize the chat list, double-click to reset it.
+        splitter := View {
+            width: 6.0
+            height: Fill
+            show_bg: true
+            cursor: MouseCursor.ColResize
+            draw_bg +: {
+                color: mod.sd_theme.border
+            }
+        }
+
         dialog := SolidView {
             width: Fill
             height: Fill
             show_bg: true
             draw_bg +: {
-                color: #26242b
+                color: mod.sd_theme.background
                 border_radius: 5.0
             }
             flow: Down
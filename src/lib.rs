@@ -1,10 +1,38 @@
 pub use makepad_widgets;
 pub mod app;
+#[cfg(feature = "ai")]
+pub mod assistant;
+pub mod attachment_strip;
 pub mod autho;
+pub mod clipboard;
+pub mod composer;
 pub mod dialog;
 pub mod dialog_list;
+pub mod downloads;
+pub mod emoji;
+pub mod emoji_picker;
+pub mod image_viewer;
+pub mod images;
 pub mod layout;
+pub mod markdown;
+pub mod mentions;
+pub mod mentions_inbox;
 pub mod new_chat;
+pub mod notification_center;
+pub mod notifications;
+pub mod outbox;
+pub mod quick_switcher;
+pub mod router;
+pub mod screens;
+pub mod search;
+pub mod search_panel;
+pub mod settings_panel;
 pub mod shindensen_client;
+pub mod shortcuts;
 pub mod state;
+pub mod storage;
+pub mod summary;
+pub mod summary_panel;
+pub mod theme;
+pub mod time;
 pub mod ui;
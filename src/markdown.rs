@@ -0,0 +1,209 @@
+/// Longest message rendered as markdown; anything bigger is shown raw.
+pub const MAX_MARKDOWN_CHARS: usize = 20_000;
+const MAX_QUOTE_DEPTH: usize = 8;
+
+/// Prepares untrusted message text for the markdown widget: drops control
+/// characters, escapes raw HTML outside of code, flattens absurdly deep
+/// quotes and closes a code fence left open, so one malformed message can't
+/// swallow the rest of the layout. Returns `None` if the text is too large to
+/// render as markdown at all.
+pub fn sanitize_markdown(text: &str) -> Option<String> {
+    if text.chars().count() > MAX_MARKDOWN_CHARS {
+        return None;
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut fence: Option<String> = None;
+    for line in text.lines() {
+        let line: String = line
+            .chars()
+            .filter(|&c| !c.is_control() || c == '\t')
+            .collect();
+        let trimmed = line.trim_start();
+        if let Some(open) = &fence {
+            if trimmed.starts_with(open.as_str()) {
+                fence = None;
+            }
+            out.push_str(&line);
+        } else if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(trimmed[..3].to_string());
+            out.push_str(&line);
+        } else {
+            out.push_str(&escape_html(&limit_quote_depth(&line)));
+        }
+        out.push('\n');
+    }
+    if let Some(open) = fence {
+        out.push_str(&open);
+        out.push('\n');
+    }
+    Some(out)
+}
+
+fn limit_quote_depth(line: &str) -> String {
+    let depth = line
+        .chars()
+        .take_while(|&c| c == '>' || c == ' ')
+        .filter(|&c| c == '>')
+        .count();
+    if depth <= MAX_QUOTE_DEPTH {
+        return line.to_string();
+    }
+    let rest = line.trim_start_matches(|c| c == '>' || c == ' ');
+    format!("{} {}", ">".repeat(MAX_QUOTE_DEPTH), rest)
+}
+
+/// Escapes `<` outside of inline code spans.
+fn escape_html(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut in_code = false;
+    for c in line.chars() {
+        match c {
+            '`' => {
+                in_code = !in_code;
+                out.push(c);
+            }
+            '<' if !in_code => out.push_str("\\<"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// A run of message text, split at fenced code blocks.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Segment {
+    Text(String),
+    Code { lang: String, code: String },
+}
+
+/// Splits message text into prose and fenced code blocks, in order. An
+/// unterminated fence runs to the end of the message.
+pub fn split_code_blocks(text: &str) -> Vec<Segment> {
+    let mut segments = vec![];
+    let mut prose = String::new();
+    let mut code: Option<(String, String, String)> = None;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        match &mut code {
+            Some((fence, _, body)) => {
+                if trimmed.starts_with(fence.as_str()) {
+                    let (_, lang, body) = code.take().unwrap();
+                    segments.push(Segment::Code { lang, code: body });
+                } else {
+                    if !body.is_empty() {
+                        body.push('\n');
+                    }
+                    body.push_str(line);
+                }
+            }
+            None if trimmed.starts_with("```") || trimmed.starts_with("~~~") => {
+                if !prose.trim().is_empty() {
+                    segments.push(Segment::Text(prose.clone()));
+                }
+                prose.clear();
+                let lang = trimmed[3..].trim().to_string();
+                code = Some((trimmed[..3].to_string(), lang, String::new()));
+            }
+            None => {
+                prose.push_str(line);
+                prose.push('\n');
+            }
+        }
+    }
+    if let Some((_, lang, code)) = code {
+        segments.push(Segment::Code { lang, code });
+    }
+    if !prose.trim().is_empty() {
+        segments.push(Segment::Text(prose));
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Segment {
+        Segment::Text(s.to_string())
+    }
+
+    fn code(lang: &str, code: &str) -> Segment {
+        Segment::Code {
+            lang: lang.to_string(),
+            code: code.to_string(),
+        }
+    }
+
+    #[test]
+    fn sanitize_escapes_html_outside_code() {
+        assert_eq!(
+            sanitize_markdown("<b>hi</b> `<i>`").unwrap(),
+            "\\<b>hi\\</b> `<i>`\n"
+        );
+        assert_eq!(
+            sanitize_markdown("```\n<b>\n```").unwrap(),
+            "```\n<b>\n```\n"
+        );
+    }
+
+    #[test]
+    fn sanitize_drops_control_characters() {
+        assert_eq!(sanitize_markdown("a\u{0}b\u{1b}c\td").unwrap(), "abc\td\n");
+    }
+
+    #[test]
+    fn sanitize_closes_open_fences() {
+        assert_eq!(
+            sanitize_markdown("~~~rust\nfn main() {}").unwrap(),
+            "~~~rust\nfn main() {}\n~~~\n"
+        );
+    }
+
+    #[test]
+    fn sanitize_flattens_deep_quotes() {
+        let deep = format!("{} deep", ">".repeat(20));
+        let expected = format!("{} deep\n", ">".repeat(MAX_QUOTE_DEPTH));
+        assert_eq!(sanitize_markdown(&deep).unwrap(), expected);
+        assert_eq!(sanitize_markdown("> > ok").unwrap(), "> > ok\n");
+    }
+
+    #[test]
+    fn sanitize_gives_up_on_huge_text() {
+        assert!(sanitize_markdown(&"a".repeat(MAX_MARKDOWN_CHARS)).is_some());
+        assert!(sanitize_markdown(&"a".repeat(MAX_MARKDOWN_CHARS + 1)).is_none());
+    }
+
+    #[test]
+    fn split_keeps_prose_and_code_in_order() {
+        let segments = split_code_blocks("before\n```rust\nlet a = 1;\nlet b = 2;\n```\nafter");
+        assert_eq!(
+            segments,
+            vec![
+                text("before\n"),
+                code("rust", "let a = 1;\nlet b = 2;"),
+                text("after\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn split_matches_the_opening_fence() {
+        let segments = split_code_blocks("~~~\n```\n~~~");
+        assert_eq!(segments, vec![code("", "```")]);
+    }
+
+    #[test]
+    fn split_runs_open_fences_to_the_end() {
+        assert_eq!(
+            split_code_blocks("```py\nprint(1)\n\nprint(2)"),
+            vec![code("py", "print(1)\n\nprint(2)")]
+        );
+    }
+
+    #[test]
+    fn split_skips_blank_prose() {
+        assert_eq!(split_code_blocks("\n```\nx\n```\n\n"), vec![code("", "x")]);
+        assert_eq!(split_code_blocks("plain"), vec![text("plain\n")]);
+        assert!(split_code_blocks("").is_empty());
+    }
+}
@@ -0,0 +1,105 @@
+use crate::composer::insert_at;
+
+/// Characters a username can be made of; anything else ends a mention.
+fn is_username_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+/// The partial username after an `@` at the end of `text`, the composer text
+/// before the cursor, if the user is typing a mention.
+pub fn mention_query(text: &str) -> Option<&str> {
+    let at = text.rfind('@')?;
+    if text[..at]
+        .chars()
+        .next_back()
+        .is_some_and(|c| !c.is_whitespace())
+    {
+        // Part of an email address or similar, not a mention.
+        return None;
+    }
+    let query = &text[at + 1..];
+    query.chars().all(is_username_char).then_some(query)
+}
+
+/// Replaces the mention being typed right before `cursor` with a finished
+/// token for `username`. Returns the new text and where the cursor goes.
+pub fn complete_mention(text: &str, cursor: usize, username: &str) -> (String, usize) {
+    let mut cursor = cursor.min(text.len());
+    while !text.is_char_boundary(cursor) {
+        cursor -= 1;
+    }
+    let start = match mention_query(&text[..cursor]) {
+        Some(query) => cursor - query.len() - 1,
+        None => cursor,
+    };
+    let space = if text[cursor..].starts_with(char::is_whitespace) {
+        ""
+    } else {
+        " "
+    };
+    insert_at(text, start..cursor, &format!("@{}{}", username, space))
+}
+
+/// Whether `content` contains an `@username` token, ignoring case.
+pub fn mentions_user(content: &str, username: &str) -> bool {
+    if username.is_empty() {
+        return false;
+    }
+    let content = content.to_lowercase();
+    let token = format!("@{}", username.to_lowercase());
+    content.match_indices(&token).any(|(index, _)| {
+        let before = content[..index].chars().next_back();
+        let mut after = content[index + token.len()..].chars();
+        let ends = match after.next() {
+            None => true,
+            // A full stop ending the sentence, not part of a longer name.
+            Some('.') => after.next().is_none_or(char::is_whitespace),
+            Some(c) => !is_username_char(c),
+        };
+        before.is_none_or(|c| !is_username_char(c)) && ends
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mention_query_finds_the_name_being_typed() {
+        assert_eq!(mention_query("hi @al"), Some("al"));
+        assert_eq!(mention_query("@"), Some(""));
+        assert_eq!(mention_query("@john.doe_2"), Some("john.doe_2"));
+        assert_eq!(mention_query("mail me@host"), None);
+        assert_eq!(mention_query("@al done"), None);
+        assert_eq!(mention_query("no mention"), None);
+    }
+
+    #[test]
+    fn complete_mention_replaces_the_query_at_the_cursor() {
+        assert_eq!(
+            complete_mention("hi @al", 6, "alice"),
+            ("hi @alice ".into(), 10)
+        );
+        assert_eq!(
+            complete_mention("hi @al and bob", 6, "alice"),
+            ("hi @alice and bob".into(), 9)
+        );
+        assert_eq!(
+            complete_mention("@b, hello", 2, "bob"),
+            ("@bob , hello".into(), 5)
+        );
+        assert_eq!(complete_mention("hey ", 4, "ann"), ("hey @ann ".into(), 9));
+        assert_eq!(complete_mention("ä @", 99, "x"), ("ä @x ".into(), 6));
+    }
+
+    #[test]
+    fn mentions_user_matches_whole_tokens() {
+        assert!(mentions_user("hey @Alice!", "alice"));
+        assert!(mentions_user("@alice", "alice"));
+        assert!(mentions_user("thanks @alice.", "alice"));
+        assert!(!mentions_user("@alice.smith", "alice"));
+        assert!(!mentions_user("@alicex", "alice"));
+        assert!(!mentions_user("bob@alice", "alice"));
+        assert!(!mentions_user("@alice", ""));
+    }
+}
@@ -0,0 +1,158 @@
+use crate::search::SearchHit;
+use crate::state::*;
+use crate::theme::{StyledRows, Theme, ThemeStamp};
+use crate::time::Timestamp;
+use makepad_widgets::*;
+
+const MAX_MENTIONS: usize = 200;
+
+script_mod! {
+    use mod.prelude.widgets.*
+    use mod.widgets.*
+
+    mod.widgets.MentionsInbox = #(MentionsInbox::register_widget(vm)) {
+        width: Fill
+        height: Fill
+        align: Align { x: 1.0, y: 0.0 }
+        panel := View {
+            width: 360.0
+            height: Fill
+            flow: Down
+            show_bg: true
+            draw_bg +: {
+                color: mod.sd_theme.panel
+            }
+            padding: Inset { top: 10.0, right: 10.0, bottom: 10.0, left: 10.0 }
+            spacing: 6.0
+            header := View {
+                width: Fill
+                height: Fit
+                flow: Right
+                align: Align { y: 0.5 }
+                title := H4 { width: Fill, text: "Mentions" }
+                close := SDButton { text: "Close" }
+            }
+            empty := Label {
+                width: Fill
+                text: "Nobody mentioned you yet."
+                draw_text +: {
+                    color: mod.sd_theme.text_muted
+                    text_style +: { font_size: 10.0 }
+                }
+            }
+            entries := PortalList{
+                height: Fill
+                scroll_bar: ScrollBar{}
+                entry := CachedView{
+                    entry_item := ChatItem{}
+                }
+            }
+        }
+    }
+}
+
+/// Every loaded message that mentions the current user, newest first.
+/// Clicking one jumps to it in its chat.
+#[derive(Script, ScriptHook, Widget)]
+struct MentionsInbox {
+    #[deref]
+    view: View,
+    /// Mentions as of the last draw, so clicks resolve against what is on screen.
+    #[rust]
+    hits: Vec<SearchHit>,
+    #[rust]
+    theme: ThemeStamp,
+    #[rust]
+    rows: StyledRows,
+}
+
+impl MentionsInbox {
+    fn apply_theme(&self, cx: &mut Cx, theme: &Theme) {
+        theme.style_background(cx, &self.widget(cx, ids!(panel)), theme.panel);
+        theme.style_label(cx, &self.widget(cx, ids!(header.title)), theme.text);
+        theme.style_label(cx, &self.widget(cx, ids!(panel.empty)), theme.text_muted);
+        theme.style_button(cx, &self.widget(cx, ids!(header.close)));
+    }
+}
+
+impl Widget for MentionsInbox {
+    fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        let state = scope.data.get::<State>().expect("State not found.");
+        if !state.show_mentions {
+            return DrawStep::done();
+        }
+        if self.theme.update(state.theme_version) {
+            self.apply_theme(cx, &state.theme);
+        }
+        self.hits = state.mentions(MAX_MENTIONS);
+        self.label(cx, ids!(panel.empty))
+            .set_visible(cx, self.hits.is_empty());
+        let now = Timestamp::now();
+        while let Some(item) = self.view.draw_walk(cx, scope, walk).step() {
+            if let Some(mut list) = item.as_portal_list().borrow_mut() {
+                let state = scope.data.get::<State>().expect("State not found.");
+                list.set_item_range(cx, 0, self.hits.len());
+                while let Some(item_id) = list.next_visible_item(cx) {
+                    let Some(hit) = self.hits.get(item_id) else {
+                        continue;
+                    };
+                    let Some(msg) = state.find_message(hit.chat_id, hit.msg_id) else {
+                        continue;
+                    };
+                    let item = list.item(cx, item_id, id!(entry));
+                    if self.rows.needs_style(state.theme_version, &item) {
+                        let entry_item = item.widget(cx, ids!(entry_item));
+                        state.theme.style_chat_item(cx, &entry_item);
+                    }
+                    item.label(cx, ids!(entry_item.body.target_usr.text))
+                        .set_text(cx, &state.get_chat_name(hit.chat_id));
+                    item.label(cx, ids!(entry_item.body.target_usr.time))
+                        .set_text(cx, &msg.timestamp.relative_label(now));
+                    item.label(cx, ids!(entry_item.body.last_msg.text))
+                        .set_text(
+                            cx,
+                            &format!(
+                                "{}: {}",
+                                state.user_name(msg.sender_id),
+                                msg.content.as_deref().unwrap_or("")
+                            ),
+                        );
+                    item.draw_all_unscoped(cx);
+                }
+            }
+        }
+        DrawStep::done()
+    }
+
+    fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
+        let state = scope.data.get::<State>().expect("State not found.");
+        if !state.show_mentions {
+            return;
+        }
+        let actions = cx.capture_actions(|cx| {
+            self.view.handle_event(cx, event, scope);
+        });
+        let state = scope.data.get_mut::<State>().expect("State not found.");
+        if self.button(cx, ids!(header.close)).clicked(&actions) {
+            state.show_mentions = false;
+            cx.redraw_all();
+        }
+        let portal_list = self.view.portal_list(cx, ids!(entries));
+        for (item_id, _) in portal_list.items_with_actions(&actions) {
+            let item_widget = portal_list.item(cx, item_id, id!(entry));
+            let body_view = item_widget.view(cx, ids!(body));
+            for action in &actions {
+                if let ViewAction::FingerUp(fe) = action.as_widget_action().cast()
+                    && body_view.area().rect(cx).contains(fe.abs)
+                    && let Some(hit) = self.hits.get(item_id)
+                {
+                    state.show_mentions = false;
+                    state.scroll_to_message = Some(hit.msg_id);
+                    state.go_to_chat(cx, hit.chat_id);
+                    cx.redraw_all();
+                }
+            }
+        }
+        cx.extend_actions(actions);
+    }
+}
@@ -1,4 +1,6 @@
-use crate::{app::AppAction, state::*};
+use crate::router::{Route, RouterAction, Screen};
+use crate::state::*;
+use crate::theme::{Theme, ThemeStamp};
 use makepad_widgets::*;
 
 script_mod! {
@@ -6,15 +8,15 @@ script_mod! {
     use mod.widgets.*
 
     mod.widgets.NewChat = #(NewChat::register_widget(vm)) {
-        SolidView {
+        background := SolidView {
             width: Fill
             height: Fill
             flow: Down
             draw_bg +: {
-                color: #26242b
+                color: mod.sd_theme.background
             }
             align: Align { x: 0.5, y: 0.5 }
-            SDLabel {
+            prompt := SDLabel {
                 text: "Enter new chat name:"
                 draw_text +: {
                     text_style +: {
@@ -47,13 +49,52 @@ script_mod! {
     }
 }
 
+/// Id of the screen for starting a chat.
+pub const NEW_CHAT_SCREEN: LiveId = live_id!(new_chat);
+
+/// Starting a chat with a user by name.
+pub struct NewChatScreen;
+
+impl Screen for NewChatScreen {
+    fn id(&self) -> LiveId {
+        NEW_CHAT_SCREEN
+    }
+
+    fn page(&self) -> &'static [LiveId] {
+        ids!(new_chat)
+    }
+
+    fn enter(&self, cx: &mut Cx, page: &WidgetRef, _state: &mut State, _route: &Route) {
+        let input = page.text_input(cx, ids!(chat_name));
+        input.set_text(cx, "");
+        input.set_key_focus(cx);
+    }
+
+    fn leave(&self, cx: &mut Cx, page: &WidgetRef, _state: &mut State) {
+        page.text_input(cx, ids!(chat_name)).set_text(cx, "");
+        page.widget(cx, ids!(error_label)).set_visible(cx, false);
+    }
+}
+
 #[derive(Script, ScriptHook, Widget)]
 struct NewChat {
     #[deref]
     view: View,
+    #[rust]
+    theme: ThemeStamp,
 }
 
 impl NewChat {
+    fn apply_theme(&self, cx: &mut Cx, theme: &Theme) {
+        theme.style_background(cx, &self.widget(cx, ids!(background)), theme.background);
+        theme.style_label(cx, &self.widget(cx, ids!(prompt)), theme.text);
+        theme.style_input(cx, &self.widget(cx, ids!(chat_name)));
+        theme.style_alert(cx, &self.widget(cx, ids!(error_label)));
+        for button in [ids!(back), ids!(create)] {
+            theme.style_button(cx, &self.widget(cx, button));
+        }
+    }
+
     fn user_search(&mut self, cx: &mut Cx, state: &mut State) {
         let user = self.text_input(cx, ids!(chat_name)).text();
         if !user.is_empty() {
@@ -64,6 +105,10 @@ impl NewChat {
 
 impl Widget for NewChat {
     fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        let state = scope.data.get::<State>().expect("State not found.");
+        if self.theme.update(state.theme_version) {
+            self.apply_theme(cx, &state.theme);
+        }
         self.view.draw_walk(cx, scope, walk)
     }
 
@@ -83,9 +128,7 @@ impl Widget for NewChat {
         }
 
         if btn_back.clicked(&actions) {
-            cx.action(AppAction::SwitchWindow(Screen::Dialog));
-            input.set_text(cx, "");
-            self.widget(cx, ids!(error_label)).set_visible(cx, false);
+            cx.action(RouterAction::Pop);
         }
         cx.extend_actions(actions);
     }
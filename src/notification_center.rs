@@ -0,0 +1,288 @@
+use crate::notifications::{MAX_TOASTS, Notification};
+use crate::state::*;
+use crate::theme::{StyledRows, Theme, ThemeStamp};
+use crate::time::Timestamp;
+use makepad_widgets::*;
+
+script_mod! {
+    use mod.prelude.widgets.*
+    use mod.widgets.*
+
+    let Toast = RoundedView {
+        visible: false
+        width: 320.0
+        height: Fit
+        flow: Down
+        cursor: MouseCursor.Hand
+        padding: Inset { top: 8.0, right: 12.0, bottom: 8.0, left: 12.0 }
+        spacing: 2.0
+        draw_bg +: {
+            color: mod.sd_theme.panel
+            border_radius: 6.0
+            border_size: 1.5
+            border_color: mod.sd_theme.surface
+        }
+        title := H4 {
+            width: Fill
+            text: ""
+        }
+        text := Label {
+            width: Fill
+            text: ""
+            draw_text +: {
+                color: mod.sd_theme.text_input
+                text_style +: { font_size: 10.0 }
+            }
+        }
+    }
+
+    mod.widgets.ToastOverlay = #(ToastOverlay::register_widget(vm)) {
+        width: Fill
+        height: Fill
+        flow: Down
+        align: Align { x: 1.0, y: 0.0 }
+        padding: Inset { top: 10.0, right: 10.0, bottom: 10.0, left: 10.0 }
+        spacing: 6.0
+        toast0 := Toast{}
+        toast1 := Toast{}
+        toast2 := Toast{}
+    }
+
+    mod.widgets.NotificationCenter = #(NotificationCenter::register_widget(vm)) {
+        width: Fill
+        height: Fill
+        align: Align { x: 1.0, y: 0.0 }
+        panel := View {
+            width: 360.0
+            height: Fill
+            flow: Down
+            show_bg: true
+            draw_bg +: {
+                color: mod.sd_theme.panel
+            }
+            padding: Inset { top: 10.0, right: 10.0, bottom: 10.0, left: 10.0 }
+            spacing: 6.0
+            header := View {
+                width: Fill
+                height: Fit
+                flow: Right
+                align: Align { y: 0.5 }
+                spacing: 6.0
+                title := H4 { width: Fill, text: "Notifications" }
+                clear := SDButton { text: "Clear" }
+                close := SDButton { text: "Close" }
+            }
+            empty := Label {
+                width: Fill
+                text: "Nothing here yet."
+                draw_text +: {
+                    color: mod.sd_theme.text_muted
+                    text_style +: { font_size: 10.0 }
+                }
+            }
+            entries := PortalList{
+                height: Fill
+                scroll_bar: ScrollBar{}
+                entry := CachedView{
+                    entry_item := ChatItem{}
+                }
+            }
+        }
+    }
+}
+
+fn open_notification(cx: &mut Cx, state: &mut State, notification: &Notification) {
+    if let Some(chat_id) = notification.chat_id {
+        state.go_to_chat(cx, chat_id);
+        cx.redraw_all();
+    }
+}
+
+/// The latest notifications stacked in the top right corner. Each disappears
+/// after a few seconds; clicking one opens its chat.
+#[derive(Script, ScriptHook, Widget)]
+struct ToastOverlay {
+    #[deref]
+    view: View,
+    /// Toasts as of the last draw, by slot.
+    #[rust]
+    shown: Vec<Notification>,
+    #[rust]
+    timer: Timer,
+    #[rust]
+    theme: ThemeStamp,
+}
+
+impl ToastOverlay {
+    fn slot(&self, cx: &mut Cx, slot: usize) -> ViewRef {
+        match slot {
+            0 => self.view(cx, ids!(toast0)),
+            1 => self.view(cx, ids!(toast1)),
+            _ => self.view(cx, ids!(toast2)),
+        }
+    }
+
+    fn apply_theme(&self, cx: &mut Cx, theme: &Theme) {
+        for slot in 0..MAX_TOASTS {
+            let toast = self.slot(cx, slot);
+            theme.style_card(cx, &toast);
+            theme.style_label(cx, &toast.widget(cx, ids!(title)), theme.text);
+            theme.style_label(cx, &toast.widget(cx, ids!(text)), theme.text_input);
+        }
+    }
+}
+
+impl Widget for ToastOverlay {
+    fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        let state = scope.data.get::<State>().expect("State not found.");
+        if self.theme.update(state.theme_version) {
+            self.apply_theme(cx, &state.theme);
+        }
+        self.shown = state.notifications.toasts().cloned().collect();
+        if !self.shown.is_empty() && self.timer.is_empty() {
+            self.timer = cx.start_interval(1.0);
+        }
+        for slot in 0..MAX_TOASTS {
+            let toast = self.slot(cx, slot);
+            let Some(notification) = self.shown.get(slot) else {
+                toast.set_visible(cx, false);
+                continue;
+            };
+            toast.set_visible(cx, true);
+            toast
+                .label(cx, ids!(title))
+                .set_text(cx, &notification.title);
+            toast.label(cx, ids!(text)).set_text(cx, &notification.text);
+        }
+        self.view.draw_walk(cx, scope, walk)
+    }
+
+    fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
+        let actions = cx.capture_actions(|cx| {
+            self.view.handle_event(cx, event, scope);
+        });
+        let state = scope.data.get_mut::<State>().expect("State not found.");
+        if self.timer.is_event(event).is_some() {
+            if state.notifications.expire_toasts() {
+                self.redraw(cx);
+            }
+            if !state.notifications.has_toasts() {
+                cx.stop_timer(self.timer);
+                self.timer = Timer::empty();
+            }
+        }
+        for action in &actions {
+            let ViewAction::FingerUp(fe) = action.as_widget_action().cast() else {
+                continue;
+            };
+            for slot in 0..MAX_TOASTS {
+                if let Some(notification) = self.shown.get(slot).cloned()
+                    && self.slot(cx, slot).area().rect(cx).contains(fe.abs)
+                {
+                    state.notifications.dismiss_toast(notification.id);
+                    open_notification(cx, state, &notification);
+                    self.redraw(cx);
+                }
+            }
+        }
+        cx.extend_actions(actions);
+    }
+}
+
+/// History of everything the app notified about, opened from the chat list.
+#[derive(Script, ScriptHook, Widget)]
+struct NotificationCenter {
+    #[deref]
+    view: View,
+    /// Entries as of the last draw, so clicks resolve against what is on screen.
+    #[rust]
+    entries: Vec<Notification>,
+    #[rust]
+    theme: ThemeStamp,
+    #[rust]
+    rows: StyledRows,
+}
+
+impl NotificationCenter {
+    fn apply_theme(&self, cx: &mut Cx, theme: &Theme) {
+        theme.style_background(cx, &self.widget(cx, ids!(panel)), theme.panel);
+        theme.style_label(cx, &self.widget(cx, ids!(header.title)), theme.text);
+        theme.style_label(cx, &self.widget(cx, ids!(panel.empty)), theme.text_muted);
+        for button in [ids!(header.clear), ids!(header.close)] {
+            theme.style_button(cx, &self.widget(cx, button));
+        }
+    }
+}
+
+impl Widget for NotificationCenter {
+    fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        let state = scope.data.get::<State>().expect("State not found.");
+        if !state.show_notifications {
+            return DrawStep::done();
+        }
+        if self.theme.update(state.theme_version) {
+            self.apply_theme(cx, &state.theme);
+        }
+        self.entries = state.notifications.history().iter().cloned().collect();
+        self.label(cx, ids!(panel.empty))
+            .set_visible(cx, self.entries.is_empty());
+        let now = Timestamp::now();
+        while let Some(item) = self.view.draw_walk(cx, scope, walk).step() {
+            if let Some(mut list) = item.as_portal_list().borrow_mut() {
+                let state = scope.data.get::<State>().expect("State not found.");
+                list.set_item_range(cx, 0, self.entries.len());
+                while let Some(item_id) = list.next_visible_item(cx) {
+                    if let Some(notification) = self.entries.get(item_id) {
+                        let item = list.item(cx, item_id, id!(entry));
+                        if self.rows.needs_style(state.theme_version, &item) {
+                            let entry_item = item.widget(cx, ids!(entry_item));
+                            state.theme.style_chat_item(cx, &entry_item);
+                        }
+                        item.label(cx, ids!(entry_item.body.target_usr.text))
+                            .set_text(cx, &notification.title);
+                        item.label(cx, ids!(entry_item.body.target_usr.time))
+                            .set_text(cx, &notification.time.relative_label(now));
+                        item.label(cx, ids!(entry_item.body.last_msg.text))
+                            .set_text(cx, &notification.text);
+                        item.draw_all_unscoped(cx);
+                    }
+                }
+            }
+        }
+        DrawStep::done()
+    }
+
+    fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
+        let state = scope.data.get::<State>().expect("State not found.");
+        if !state.show_notifications {
+            return;
+        }
+        let actions = cx.capture_actions(|cx| {
+            self.view.handle_event(cx, event, scope);
+        });
+        let state = scope.data.get_mut::<State>().expect("State not found.");
+        if self.button(cx, ids!(header.close)).clicked(&actions) {
+            state.show_notifications = false;
+            cx.redraw_all();
+        }
+        if self.button(cx, ids!(header.clear)).clicked(&actions) {
+            state.notifications.clear();
+            cx.redraw_all();
+        }
+        let portal_list = self.view.portal_list(cx, ids!(entries));
+        for (item_id, _) in portal_list.items_with_actions(&actions) {
+            let item_widget = portal_list.item(cx, item_id, id!(entry));
+            let body_view = item_widget.view(cx, ids!(body));
+            for action in &actions {
+                if let ViewAction::FingerUp(fe) = action.as_widget_action().cast()
+                    && body_view.area().rect(cx).contains(fe.abs)
+                    && let Some(notification) = self.entries.get(item_id)
+                {
+                    state.show_notifications = false;
+                    open_notification(cx, state, notification);
+                }
+            }
+        }
+        cx.extend_actions(actions);
+    }
+}
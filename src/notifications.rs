@@ -0,0 +1,117 @@
+use crate::time::Timestamp;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const HISTORY_LIMIT: usize = 200;
+/// Toasts shown at once; older ones are pushed out.
+pub const MAX_TOASTS: usize = 3;
+/// How long a toast stays on screen.
+pub const TOAST_DURATION: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NotificationKind {
+    #[default]
+    Message,
+    Error,
+    Connection,
+    Success,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Notification {
+    pub id: u64,
+    pub kind: NotificationKind,
+    pub title: String,
+    pub text: String,
+    /// Chat opened when the notification is clicked.
+    pub chat_id: Option<i64>,
+    pub time: Timestamp,
+}
+
+impl Notification {
+    pub fn new(kind: NotificationKind, title: impl Into<String>, text: impl Into<String>) -> Self {
+        Self {
+            kind,
+            title: title.into(),
+            text: text.into(),
+            time: Timestamp::now(),
+            ..Default::default()
+        }
+    }
+
+    pub fn message(chat_id: i64, title: impl Into<String>, text: impl Into<String>) -> Self {
+        Self {
+            chat_id: Some(chat_id),
+            ..Self::new(NotificationKind::Message, title, text)
+        }
+    }
+
+    pub fn error(text: impl Into<String>) -> Self {
+        Self::new(NotificationKind::Error, "Error", text)
+    }
+
+    pub fn connection(text: impl Into<String>) -> Self {
+        Self::new(NotificationKind::Connection, "Connection", text)
+    }
+
+    pub fn success(text: impl Into<String>) -> Self {
+        Self::new(NotificationKind::Success, "Done", text)
+    }
+}
+
+/// Everything the app told the user, newest first, plus the few currently
+/// shown as toasts.
+#[derive(Default)]
+pub struct Notifications {
+    history: VecDeque<Notification>,
+    /// Ids of notifications on screen with the time they were shown at.
+    toasts: Vec<(u64, Instant)>,
+    next_id: u64,
+}
+
+impl Notifications {
+    pub fn push(&mut self, mut notification: Notification) {
+        self.next_id += 1;
+        notification.id = self.next_id;
+        self.toasts.push((notification.id, Instant::now()));
+        if self.toasts.len() > MAX_TOASTS {
+            self.toasts.remove(0);
+        }
+        self.history.push_front(notification);
+        self.history.truncate(HISTORY_LIMIT);
+    }
+
+    pub fn history(&self) -> &VecDeque<Notification> {
+        &self.history
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Notification> {
+        self.history.iter().find(|n| n.id == id)
+    }
+
+    /// Toasts on screen, oldest first.
+    pub fn toasts(&self) -> impl Iterator<Item = &Notification> {
+        self.toasts.iter().filter_map(|(id, _)| self.get(*id))
+    }
+
+    pub fn has_toasts(&self) -> bool {
+        !self.toasts.is_empty()
+    }
+
+    /// Drops toasts shown longer than `TOAST_DURATION`. Returns whether any went away.
+    pub fn expire_toasts(&mut self) -> bool {
+        let before = self.toasts.len();
+        self.toasts
+            .retain(|(_, shown)| shown.elapsed() < TOAST_DURATION);
+        self.toasts.len() != before
+    }
+
+    pub fn dismiss_toast(&mut self, id: u64) {
+        self.toasts.retain(|(toast, _)| *toast != id);
+    }
+
+    pub fn clear(&mut self) {
+        self.history.clear();
+        self.toasts.clear();
+    }
+}
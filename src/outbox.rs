@@ -0,0 +1,292 @@
+use crate::shindensen_client::{FileMetadata, FilePayload, ShinDensenClient};
+use crate::storage::Store;
+use makepad_micro_serde::*;
+use makepad_widgets::*;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const OUTBOX_FILE: &str = "outbox.json";
+/// Uploads are sent in one request, so huge files are refused up front.
+pub const MAX_ATTACHMENT_BYTES: u64 = 50 * 1024 * 1024;
+/// Wait before retrying a failed upload, doubled after each further failure.
+const UPLOAD_RETRY_MIN_DELAY: f64 = 2.0;
+const UPLOAD_RETRY_MAX_DELAY: f64 = 60.0;
+/// Failed attempts after which an attachment is given up on.
+const MAX_UPLOAD_ATTEMPTS: u32 = 5;
+
+/// A message composed by the user that hasn't reached the socket yet.
+#[derive(Clone, Debug, Default, DeJson, SerJson, PartialEq)]
+pub struct OutboxEntry {
+    pub chat_id: i64,
+    /// Sent along with the message so the server can drop duplicates of a resend.
+    pub nonce: String,
+    pub content: String,
+    /// Files uploaded one by one before the message goes out.
+    #[nserde(default)]
+    pub attachments: Vec<OutboxAttachment>,
+}
+
+/// A file queued with a message. The local copy is read when its upload starts.
+#[derive(Clone, Debug, Default, DeJson, SerJson, PartialEq)]
+pub struct OutboxAttachment {
+    pub filename: String,
+    pub mime_type: String,
+    pub path: String,
+    /// `path` is a copy the app made, deleted once the message is sent.
+    pub temporary: bool,
+    pub uploaded: Option<FileMetadata>,
+}
+
+impl OutboxAttachment {
+    /// Attachment for a file the user picked; it is read when the upload starts.
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        let metadata =
+            std::fs::metadata(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        if !metadata.is_file() {
+            return Err(format!("{} is not a file", path.display()));
+        }
+        if metadata.len() > MAX_ATTACHMENT_BYTES {
+            return Err(format!(
+                "{} is larger than {} MB",
+                path.display(),
+                MAX_ATTACHMENT_BYTES / (1024 * 1024)
+            ));
+        }
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "file".to_string());
+        Ok(Self {
+            mime_type: mime_type_for(&filename).to_string(),
+            filename,
+            path: path.to_string_lossy().into_owned(),
+            temporary: false,
+            uploaded: None,
+        })
+    }
+
+    pub fn is_image(&self) -> bool {
+        self.mime_type.starts_with("image/")
+    }
+}
+
+/// MIME type guessed from the file extension.
+pub fn mime_type_for(filename: &str) -> &'static str {
+    let extension = Path::new(filename)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "txt" | "log" | "md" => "text/plain; charset=utf-8",
+        "json" => "application/json",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+/// What became of a failed upload of the outbox.
+#[derive(Clone, Debug, PartialEq)]
+pub enum UploadFailure {
+    /// Tried again after this many seconds.
+    Retrying(f64),
+    /// Failed too often; the message goes out without the named file.
+    GaveUp(String),
+}
+
+/// Messages waiting for a READY socket, kept in the order they were written.
+#[derive(Default)]
+pub struct Outbox {
+    entries: Vec<OutboxEntry>,
+    /// Tags of the uploads in flight.
+    uploading: HashSet<String>,
+    /// Failed attempts per upload tag, until it succeeds or is given up on.
+    upload_attempts: HashMap<String, u32>,
+    retry_timer: Timer,
+}
+
+/// Identifies an attachment upload across the client round trip.
+fn upload_tag(nonce: &str, index: usize) -> String {
+    format!("{}/{}", nonce, index)
+}
+
+impl Outbox {
+    pub fn load(store: &Store) -> Self {
+        Self {
+            entries: store.load(OUTBOX_FILE).unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    pub fn save(&self, store: &Store) {
+        if self.entries.is_empty() {
+            store.remove(OUTBOX_FILE);
+        } else {
+            store.save(OUTBOX_FILE, &self.entries);
+        }
+    }
+
+    pub fn push(&mut self, chat_id: i64, content: String, attachments: Vec<OutboxAttachment>) {
+        self.entries.push(OutboxEntry {
+            chat_id,
+            nonce: new_nonce(),
+            content,
+            attachments,
+        });
+    }
+
+    /// Records a finished upload. Returns whether it belonged to the outbox.
+    pub fn upload_finished(&mut self, tag: &str, file: FileMetadata) -> bool {
+        if !self.uploading.remove(tag) {
+            return false;
+        }
+        self.upload_attempts.remove(tag);
+        for entry in &mut self.entries {
+            for (index, attachment) in entry.attachments.iter_mut().enumerate() {
+                if upload_tag(&entry.nonce, index) == tag {
+                    attachment.uploaded = Some(file);
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Plans another attempt at a failed upload, waiting longer after each
+    /// failure, or drops the attachment once it failed `MAX_UPLOAD_ATTEMPTS`
+    /// times so the messages behind it aren't held up forever. `None` if the
+    /// upload wasn't the outbox's.
+    pub fn upload_failed(&mut self, cx: &mut Cx, tag: &str) -> Option<UploadFailure> {
+        if !self.uploading.remove(tag) {
+            return None;
+        }
+        let attempts = self.upload_attempts.entry(tag.to_string()).or_default();
+        *attempts += 1;
+        if *attempts < MAX_UPLOAD_ATTEMPTS {
+            let delay = (UPLOAD_RETRY_MIN_DELAY * 2f64.powi(*attempts as i32 - 1))
+                .min(UPLOAD_RETRY_MAX_DELAY);
+            cx.stop_timer(self.retry_timer);
+            self.retry_timer = cx.start_timeout(delay);
+            return Some(UploadFailure::Retrying(delay));
+        }
+        self.upload_attempts.remove(tag);
+        for entry in &mut self.entries {
+            let Some(index) =
+                (0..entry.attachments.len()).find(|&index| upload_tag(&entry.nonce, index) == tag)
+            else {
+                continue;
+            };
+            let attachment = entry.attachments.remove(index);
+            if attachment.temporary {
+                let _ = std::fs::remove_file(&attachment.path);
+            }
+            return Some(UploadFailure::GaveUp(attachment.filename));
+        }
+        None
+    }
+
+    /// Whether the retry timer fired, so it's time to flush again.
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        if self.retry_timer.is_event(event).is_some() {
+            self.retry_timer = Timer::empty();
+            return true;
+        }
+        false
+    }
+
+    /// Whether `entry` waits for another attempt at a failed upload.
+    pub fn is_retrying(&self, entry: &OutboxEntry) -> bool {
+        (0..entry.attachments.len()).any(|index| {
+            self.upload_attempts
+                .contains_key(&upload_tag(&entry.nonce, index))
+        })
+    }
+
+    pub fn queued_for(&self, chat_id: i64) -> impl Iterator<Item = &OutboxEntry> {
+        self.entries.iter().filter(move |e| e.chat_id == chat_id)
+    }
+
+    /// Sends queued messages in order, stopping at the first one the socket
+    /// refuses or that still waits for an upload, so nothing overtakes it.
+    /// Returns how many were sent.
+    pub fn flush(&mut self, cx: &mut Cx, client: &mut ShinDensenClient) -> usize {
+        let mut sent = 0;
+        for entry in &mut self.entries {
+            if !client.is_ready() {
+                break;
+            }
+            let waiting = loop {
+                let Some(index) = entry.attachments.iter().position(|a| a.uploaded.is_none())
+                else {
+                    break false;
+                };
+                let tag = upload_tag(&entry.nonce, index);
+                let retry_pending =
+                    !self.retry_timer.is_empty() && self.upload_attempts.contains_key(&tag);
+                if self.uploading.contains(&tag) || retry_pending {
+                    break true;
+                }
+                let attachment = &entry.attachments[index];
+                match std::fs::read(&attachment.path) {
+                    Ok(data) => {
+                        client.upload_file(
+                            cx,
+                            tag.clone(),
+                            &attachment.filename,
+                            &attachment.mime_type,
+                            data,
+                        );
+                        self.uploading.insert(tag);
+                        break true;
+                    }
+                    Err(err) => {
+                        // Sending without it beats blocking the outbox forever.
+                        error!("Dropping attachment {}: {}", attachment.path, err);
+                        entry.attachments.remove(index);
+                    }
+                }
+            };
+            if waiting {
+                break;
+            }
+            let files = entry
+                .attachments
+                .iter()
+                .filter_map(|a| a.uploaded.as_ref().map(FilePayload::from))
+                .collect();
+            if let Err(err) = client.send_message(
+                cx,
+                entry.chat_id,
+                entry.content.clone(),
+                files,
+                entry.nonce.clone(),
+            ) {
+                error!("Outbox flush stopped: {}", err);
+                break;
+            }
+            sent += 1;
+        }
+        for entry in self.entries.drain(..sent) {
+            for attachment in entry.attachments.iter().filter(|a| a.temporary) {
+                let _ = std::fs::remove_file(&attachment.path);
+            }
+        }
+        sent
+    }
+}
+
+fn new_nonce() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!("{:x}-{:x}", nanos, LiveId::unique().0)
+}
@@ -0,0 +1,173 @@
+use crate::state::*;
+use crate::theme::{StyledRows, Theme, ThemeStamp};
+use makepad_widgets::*;
+
+script_mod! {
+    use mod.prelude.widgets.*
+    use mod.widgets.*
+
+    mod.widgets.QuickSwitcher = #(QuickSwitcher::register_widget(vm)) {
+        width: Fill
+        height: Fill
+        align: Align { x: 0.5, y: 0.0 }
+        padding: Inset { top: 80.0 }
+        show_bg: true
+        draw_bg +: {
+            color: #00000080
+        }
+        panel := RoundedView {
+            width: 440.0
+            height: 420.0
+            flow: Down
+            padding: Inset { top: 10.0, right: 10.0, bottom: 10.0, left: 10.0 }
+            draw_bg +: {
+                color: mod.sd_theme.panel
+                border_radius: 6.0
+                border_size: 1.5
+                border_color: mod.sd_theme.surface
+            }
+            query := SDTextInput{
+                width: Fill
+                empty_text: "Jump to chat..."
+            }
+            matches := PortalList{
+                height: Fill
+                scroll_bar: ScrollBar{}
+                entry := CachedView{
+                    entry_item := ChatItem{}
+                }
+            }
+        }
+    }
+}
+
+/// Ctrl+K popup that opens a chat by typing part of its name. Up/Down pick
+/// a match, Enter opens it.
+#[derive(Script, ScriptHook, Widget)]
+struct QuickSwitcher {
+    #[deref]
+    view: View,
+    /// Matches as of the last draw, best first.
+    #[rust]
+    matches: Vec<i64>,
+    #[rust]
+    selected: usize,
+    /// Whether the switcher was shown on the last draw, to reset it on opening.
+    #[rust]
+    open: bool,
+    #[rust]
+    theme: ThemeStamp,
+    #[rust]
+    rows: StyledRows,
+}
+
+impl QuickSwitcher {
+    fn apply_theme(&self, cx: &mut Cx, theme: &Theme) {
+        theme.style_card(cx, &self.widget(cx, ids!(panel)));
+        theme.style_input(cx, &self.widget(cx, ids!(panel.query)));
+    }
+
+    fn choose(&mut self, cx: &mut Cx, state: &mut State, chat_id: i64) {
+        state.show_switcher = false;
+        state.go_to_chat(cx, chat_id);
+        cx.redraw_all();
+    }
+}
+
+impl Widget for QuickSwitcher {
+    fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        let state = scope.data.get::<State>().expect("State not found.");
+        if !state.show_switcher {
+            self.open = false;
+            return DrawStep::done();
+        }
+        if self.theme.update(state.theme_version) {
+            self.apply_theme(cx, &state.theme);
+        }
+        let input = self.text_input(cx, ids!(panel.query));
+        if !self.open {
+            self.open = true;
+            self.selected = 0;
+            input.set_text(cx, "");
+            input.set_key_focus(cx);
+        }
+        self.matches = state.switcher_matches(&input.text());
+        self.selected = self.selected.min(self.matches.len().saturating_sub(1));
+        while let Some(item) = self.view.draw_walk(cx, scope, walk).step() {
+            if let Some(mut list) = item.as_portal_list().borrow_mut() {
+                let state = scope.data.get::<State>().expect("State not found.");
+                list.set_item_range(cx, 0, self.matches.len());
+                while let Some(item_id) = list.next_visible_item(cx) {
+                    if let Some(&chat_id) = self.matches.get(item_id) {
+                        let item = list.item(cx, item_id, id!(entry));
+                        if self.rows.needs_style(state.theme_version, &item) {
+                            let entry_item = item.widget(cx, ids!(entry_item));
+                            state.theme.style_chat_item(cx, &entry_item);
+                        }
+                        let marker = if item_id == self.selected { "> " } else { "" };
+                        item.label(cx, ids!(entry_item.body.target_usr.text))
+                            .set_text(cx, &format!("{}{}", marker, state.get_chat_name(chat_id)));
+                        let preview = state
+                            .msg_history
+                            .get(&chat_id)
+                            .and_then(|msgs| msgs.last())
+                            .and_then(|msg| msg.content.clone())
+                            .unwrap_or_default();
+                        item.label(cx, ids!(entry_item.body.last_msg.text))
+                            .set_text(cx, &preview);
+                        item.draw_all_unscoped(cx);
+                    }
+                }
+            }
+        }
+        DrawStep::done()
+    }
+
+    fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
+        let state = scope.data.get::<State>().expect("State not found.");
+        if !state.show_switcher {
+            return;
+        }
+        if let Event::KeyDown(ke) = event {
+            match ke.key_code {
+                KeyCode::ArrowDown if self.selected + 1 < self.matches.len() => {
+                    self.selected += 1;
+                    self.redraw(cx);
+                }
+                KeyCode::ArrowUp if self.selected > 0 => {
+                    self.selected -= 1;
+                    self.redraw(cx);
+                }
+                _ => {}
+            }
+        }
+        let actions = cx.capture_actions(|cx| {
+            self.view.handle_event(cx, event, scope);
+        });
+        let state = scope.data.get_mut::<State>().expect("State not found.");
+        let input = self.text_input(cx, ids!(panel.query));
+        if input.changed(&actions).is_some() {
+            self.selected = 0;
+            self.redraw(cx);
+        }
+        if input.returned(&actions).is_some()
+            && let Some(&chat_id) = self.matches.get(self.selected)
+        {
+            self.choose(cx, state, chat_id);
+        }
+        let portal_list = self.view.portal_list(cx, ids!(matches));
+        for (item_id, _) in portal_list.items_with_actions(&actions) {
+            let item_widget = portal_list.item(cx, item_id, id!(entry));
+            let body_view = item_widget.view(cx, ids!(body));
+            for action in &actions {
+                if let ViewAction::FingerUp(fe) = action.as_widget_action().cast()
+                    && body_view.area().rect(cx).contains(fe.abs)
+                    && let Some(&chat_id) = self.matches.get(item_id)
+                {
+                    self.choose(cx, state, chat_id);
+                }
+            }
+        }
+        cx.extend_actions(actions);
+    }
+}
@@ -0,0 +1,307 @@
+use crate::screens;
+use crate::state::*;
+use makepad_widgets::*;
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// A screen of the app: the page that shows it and what it does as it is
+/// entered and left. Screens are made known with `Router::register`; the app
+/// registers every screen listed in `screens`.
+pub trait Screen {
+    /// Id that routes to this screen are made with.
+    fn id(&self) -> LiveId;
+
+    /// Page in `Pages` that shows this screen.
+    fn page(&self) -> &'static [LiveId];
+
+    /// Runs when a route to this screen becomes the current one, including
+    /// when it is uncovered by going back.
+    fn enter(&self, _cx: &mut Cx, _page: &WidgetRef, _state: &mut State, _route: &Route) {}
+
+    /// Runs when a route to this screen stops being the current one, whether
+    /// it is left for good or covered by another.
+    fn leave(&self, _cx: &mut Cx, _page: &WidgetRef, _state: &mut State) {}
+}
+
+/// Parameters of a route, e.g. the chat to open. Each screen defines its own
+/// type and reads it back with `Route::params`. Routes travel in actions,
+/// hence `Send + Sync`.
+pub trait RouteParams: Debug + Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+
+    fn eq_params(&self, other: &dyn RouteParams) -> bool;
+}
+
+impl<T: Any + Debug + PartialEq + Send + Sync> RouteParams for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn eq_params(&self, other: &dyn RouteParams) -> bool {
+        other.as_any().downcast_ref::<T>() == Some(self)
+    }
+}
+
+/// A screen together with what it needs to show.
+#[derive(Clone, Debug)]
+pub struct Route {
+    /// Id of the registered screen.
+    pub screen: LiveId,
+    params: Option<Arc<dyn RouteParams>>,
+}
+
+impl PartialEq for Route {
+    fn eq(&self, other: &Self) -> bool {
+        self.screen == other.screen
+            && match (&self.params, &other.params) {
+                (None, None) => true,
+                (Some(params), Some(other)) => params.eq_params(&**other),
+                _ => false,
+            }
+    }
+}
+
+impl Route {
+    pub fn new(screen: LiveId) -> Self {
+        Route {
+            screen,
+            params: None,
+        }
+    }
+
+    pub fn with_params(screen: LiveId, params: impl RouteParams + 'static) -> Self {
+        Route {
+            screen,
+            params: Some(Arc::new(params)),
+        }
+    }
+
+    /// The parameters of the route if they are a `T`.
+    pub fn params<T: Any>(&self) -> Option<&T> {
+        self.params.as_ref()?.as_any().downcast_ref()
+    }
+}
+
+/// Requests to move between screens, emitted by any widget and carried out
+/// by `navigate`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum RouterAction {
+    /// Opens a route on top of the current one.
+    Push(Route),
+    /// Swaps the current route for another.
+    Replace(Route),
+    /// Goes back to the previous route; the first one is never popped.
+    Pop,
+    /// Starts over from a single route, e.g. after logging in or out.
+    Reset(Route),
+    #[default]
+    None,
+}
+
+/// The routes visited so far, the current one last, and the screens they
+/// can lead to.
+#[derive(Clone)]
+pub struct Router {
+    stack: Vec<Route>,
+    screens: HashMap<LiveId, Rc<dyn Screen>>,
+}
+
+impl Default for Router {
+    /// Starts at the first screen with every screen of `screens` registered.
+    fn default() -> Self {
+        let mut router = Router::new(Route::new(screens::START));
+        screens::register(&mut router);
+        router
+    }
+}
+
+impl Router {
+    /// A router at `start` that knows no screens yet.
+    pub fn new(start: Route) -> Self {
+        Router {
+            stack: vec![start],
+            screens: HashMap::new(),
+        }
+    }
+
+    /// Makes `screen` reachable by routes with its id, replacing any screen
+    /// registered under the same id.
+    pub fn register(&mut self, screen: impl Screen + 'static) {
+        self.screens.insert(screen.id(), Rc::new(screen));
+    }
+
+    pub fn current(&self) -> &Route {
+        self.stack.last().expect("the route stack is never empty")
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        self.stack.len() > 1
+    }
+
+    /// Applies `action` to the stack. Returns whether the current route
+    /// changed; routes to screens that were never registered are refused.
+    fn apply(&mut self, action: RouterAction) -> bool {
+        let before = self.current().clone();
+        match action {
+            RouterAction::Push(route)
+            | RouterAction::Replace(route)
+            | RouterAction::Reset(route)
+                if !self.screens.contains_key(&route.screen) =>
+            {
+                error!("No screen registered for {:?}", route);
+                return false;
+            }
+            RouterAction::Push(route) => self.stack.push(route),
+            RouterAction::Replace(route) => {
+                self.stack.pop();
+                self.stack.push(route);
+            }
+            RouterAction::Pop => {
+                if !self.can_go_back() {
+                    return false;
+                }
+                self.stack.pop();
+            }
+            RouterAction::Reset(route) => {
+                self.stack.clear();
+                self.stack.push(route);
+            }
+            RouterAction::None => return false,
+        }
+        *self.current() != before
+    }
+
+    fn screen(&self, route: &Route) -> Option<Rc<dyn Screen>> {
+        self.screens.get(&route.screen).cloned()
+    }
+}
+
+/// Carries out `action`: runs the leave hook of the current screen, shows the
+/// page of the new one and runs its enter hook.
+pub fn navigate(cx: &mut Cx, ui: &WidgetRef, state: &mut State, action: RouterAction) {
+    let from = state.router.current().clone();
+    if !state.router.apply(action) {
+        return;
+    }
+    let to = state.router.current().clone();
+    // The screens are cloned out so their hooks can change the state.
+    let (Some(from_screen), Some(to_screen)) =
+        (state.router.screen(&from), state.router.screen(&to))
+    else {
+        return;
+    };
+    let from_page = ui.widget(cx, from_screen.page());
+    let to_page = ui.widget(cx, to_screen.page());
+    from_screen.leave(cx, &from_page, state);
+    if from_screen.page() != to_screen.page() {
+        from_page.set_visible(cx, false);
+        to_page.set_visible(cx, true);
+    }
+    to_screen.enter(cx, &to_page, state, &to);
+    log!("Navigated from {:?} to {:?}", from, to);
+    cx.redraw_all();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestScreen(LiveId);
+
+    impl Screen for TestScreen {
+        fn id(&self) -> LiveId {
+            self.0
+        }
+
+        fn page(&self) -> &'static [LiveId] {
+            ids!(test_page)
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Item(i64);
+
+    const HOME: LiveId = live_id!(home);
+    const LIST: LiveId = live_id!(list);
+    const DETAIL: LiveId = live_id!(detail);
+
+    fn router() -> Router {
+        let mut router = Router::new(Route::new(HOME));
+        for screen in [HOME, LIST, DETAIL] {
+            router.register(TestScreen(screen));
+        }
+        router
+    }
+
+    #[test]
+    fn push_and_pop_walk_the_stack() {
+        let mut router = router();
+        assert!(!router.can_go_back());
+        assert!(router.apply(RouterAction::Push(Route::new(LIST))));
+        assert!(router.apply(RouterAction::Push(Route::with_params(DETAIL, Item(7)))));
+        assert_eq!(router.current().params::<Item>(), Some(&Item(7)));
+        assert!(router.apply(RouterAction::Pop));
+        assert_eq!(*router.current(), Route::new(LIST));
+        assert!(router.apply(RouterAction::Pop));
+        assert_eq!(*router.current(), Route::new(HOME));
+        // The first route is never popped.
+        assert!(!router.apply(RouterAction::Pop));
+        assert_eq!(*router.current(), Route::new(HOME));
+    }
+
+    #[test]
+    fn replace_swaps_only_the_current_route() {
+        let mut router = router();
+        router.apply(RouterAction::Push(Route::new(LIST)));
+        assert!(router.apply(RouterAction::Replace(Route::with_params(LIST, Item(1)))));
+        assert!(router.apply(RouterAction::Replace(Route::with_params(LIST, Item(2)))));
+        assert_eq!(router.current().params::<Item>(), Some(&Item(2)));
+        // Replacing a route with an equal one changes nothing.
+        assert!(!router.apply(RouterAction::Replace(Route::with_params(LIST, Item(2)))));
+        router.apply(RouterAction::Pop);
+        assert_eq!(*router.current(), Route::new(HOME));
+    }
+
+    #[test]
+    fn reset_starts_over() {
+        let mut router = router();
+        router.apply(RouterAction::Push(Route::new(LIST)));
+        router.apply(RouterAction::Push(Route::new(DETAIL)));
+        assert!(router.apply(RouterAction::Reset(Route::with_params(LIST, Item(3)))));
+        assert_eq!(*router.current(), Route::with_params(LIST, Item(3)));
+        assert!(!router.can_go_back());
+    }
+
+    #[test]
+    fn refuses_unregistered_screens() {
+        let mut router = router();
+        let unknown = Route::new(live_id!(unknown));
+        assert!(!router.apply(RouterAction::Push(unknown.clone())));
+        assert!(!router.apply(RouterAction::Replace(unknown.clone())));
+        assert!(!router.apply(RouterAction::Reset(unknown)));
+        assert!(!router.apply(RouterAction::None));
+        assert_eq!(*router.current(), Route::new(HOME));
+        assert!(!router.can_go_back());
+    }
+
+    #[test]
+    fn params_compare_by_type_and_value() {
+        assert_eq!(
+            Route::with_params(LIST, Item(1)),
+            Route::with_params(LIST, Item(1))
+        );
+        assert_ne!(
+            Route::with_params(LIST, Item(1)),
+            Route::with_params(LIST, Item(2))
+        );
+        assert_ne!(
+            Route::with_params(LIST, Item(1)),
+            Route::with_params(LIST, 1_i64)
+        );
+        assert_ne!(Route::with_params(LIST, Item(1)), Route::new(LIST));
+        assert_eq!(Route::with_params(LIST, 1_i64).params::<Item>(), None);
+    }
+}
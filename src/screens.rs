@@ -0,0 +1,53 @@
+use crate::autho::{AUTH_SCREEN, LoginScreen};
+use crate::dialog::ChatsScreen;
+use crate::new_chat::NewChatScreen;
+use crate::router::Router;
+use crate::settings_panel::SettingsScreen;
+use makepad_widgets::*;
+
+script_mod! {
+    use mod.prelude.widgets.*
+    use mod.widgets.*
+
+    // One page per screen; the router shows the one of the current route.
+    mod.widgets.Pages = View{
+        width: Fill
+        height: Fill
+        flow: Down
+        spacing: 0.0
+        auth_page := LoginForm {
+            visible: true
+        }
+        dialog_page := DialogPage {
+            visible: false
+        }
+        new_chat := NewChat {
+            visible: false
+        }
+        settings_page := SettingsPanel {
+            visible: false
+        }
+    }
+}
+
+/// Screen the app starts at.
+pub const START: LiveId = AUTH_SCREEN;
+
+/// Defines the widgets of every screen, then the `Pages` holding them.
+pub fn script_mods(vm: &mut ScriptVm) {
+    crate::settings_panel::script_mod(vm);
+    crate::dialog::script_mod(vm);
+    crate::autho::script_mod(vm);
+    crate::new_chat::script_mod(vm);
+    script_mod(vm);
+}
+
+/// Makes every screen reachable. A new screen defines its widget and its
+/// `Screen` in its own module and is listed here, in `script_mods` and as a
+/// page in `Pages`; neither `App` nor the router change.
+pub fn register(router: &mut Router) {
+    router.register(LoginScreen);
+    router.register(ChatsScreen);
+    router.register(NewChatScreen);
+    router.register(SettingsScreen);
+}
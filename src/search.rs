@@ -0,0 +1,295 @@
+use crate::shindensen_client::ChatMessage;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+const SNIPPET_CHARS: usize = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SearchHit {
+    pub chat_id: i64,
+    pub msg_id: i64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchQuery {
+    pub words: Vec<String>,
+    pub chat_id: Option<i64>,
+    pub sender_id: Option<i64>,
+}
+
+impl SearchQuery {
+    pub fn new(text: &str) -> Self {
+        Self {
+            words: tokenize(text),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty() && self.chat_id.is_none() && self.sender_id.is_none()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct IndexedMessage {
+    chat_id: i64,
+    sender_id: i64,
+}
+
+/// Inverted index over message words. Words are kept sorted so a prefix
+/// lookup is a range scan.
+#[derive(Default)]
+pub struct SearchIndex {
+    words: BTreeMap<String, BTreeSet<i64>>,
+    messages: HashMap<i64, IndexedMessage>,
+}
+
+impl SearchIndex {
+    pub fn insert(&mut self, msg: &ChatMessage) {
+        if self.messages.contains_key(&msg.id) {
+            return;
+        }
+        self.messages.insert(
+            msg.id,
+            IndexedMessage {
+                chat_id: msg.chat_id,
+                sender_id: msg.sender_id,
+            },
+        );
+        for word in tokenize(msg.content.as_deref().unwrap_or("")) {
+            self.words.entry(word).or_default().insert(msg.id);
+        }
+    }
+
+    /// Forgets every message of `chat_id`.
+    pub fn remove_chat(&mut self, chat_id: i64) {
+        self.messages.retain(|_, msg| msg.chat_id != chat_id);
+        let messages = &self.messages;
+        self.words.retain(|_, ids| {
+            ids.retain(|id| messages.contains_key(id));
+            !ids.is_empty()
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.words.clear();
+        self.messages.clear();
+    }
+
+    /// Messages containing a word starting with every query word, newest first.
+    pub fn search(&self, query: &SearchQuery, limit: usize) -> Vec<SearchHit> {
+        if query.is_empty() {
+            return vec![];
+        }
+        let mut matches: Option<BTreeSet<i64>> = None;
+        for word in &query.words {
+            let ids: BTreeSet<i64> = self
+                .words
+                .range(word.clone()..)
+                .take_while(|(w, _)| w.starts_with(word.as_str()))
+                .flat_map(|(_, ids)| ids.iter().copied())
+                .collect();
+            matches = Some(match matches {
+                Some(prev) => prev.intersection(&ids).copied().collect(),
+                None => ids,
+            });
+        }
+        let candidates: Box<dyn Iterator<Item = i64>> = match matches {
+            Some(ids) => Box::new(ids.into_iter().rev()),
+            None => {
+                let mut ids: Vec<i64> = self.messages.keys().copied().collect();
+                ids.sort_unstable_by(|a, b| b.cmp(a));
+                Box::new(ids.into_iter())
+            }
+        };
+        candidates
+            .filter_map(|msg_id| {
+                let msg = self.messages.get(&msg_id)?;
+                if query.chat_id.is_some_and(|id| id != msg.chat_id)
+                    || query.sender_id.is_some_and(|id| id != msg.sender_id)
+                {
+                    return None;
+                }
+                Some(SearchHit {
+                    chat_id: msg.chat_id,
+                    msg_id,
+                })
+            })
+            .take(limit)
+            .collect()
+    }
+}
+
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+/// Id of the candidate whose name best matches `name`, ignoring case: an
+/// exact match, else the shortest name starting with it. Ties go to the
+/// lowest id so the same query always picks the same candidate.
+pub fn match_name<S: AsRef<str>>(
+    name: &str,
+    candidates: impl IntoIterator<Item = (i64, S)>,
+) -> Option<i64> {
+    let name = name.to_lowercase();
+    candidates
+        .into_iter()
+        .filter_map(|(id, candidate)| {
+            let candidate = candidate.as_ref().to_lowercase();
+            candidate
+                .starts_with(&name)
+                .then(|| (candidate != name, candidate.len(), id))
+        })
+        .min()
+        .map(|(_, _, id)| id)
+}
+
+/// A short excerpt of `content` around the first word matching the query.
+pub fn snippet(content: &str, query: &SearchQuery) -> String {
+    let lower = content.to_lowercase();
+    let start = query
+        .words
+        .iter()
+        .filter_map(|word| lower.find(word.as_str()))
+        .min()
+        .unwrap_or(0);
+    // `lower` may differ in length from `content`, so work in chars.
+    let chars: Vec<char> = content.chars().collect();
+    let start_char = lower[..start].chars().count().min(chars.len());
+    let from = start_char.saturating_sub(SNIPPET_CHARS / 3);
+    let to = (from + SNIPPET_CHARS).min(chars.len());
+    let mut out = String::new();
+    if from > 0 {
+        out.push('…');
+    }
+    out.extend(
+        chars[from..to]
+            .iter()
+            .map(|&c| if c == '\n' { ' ' } else { c }),
+    );
+    if to < chars.len() {
+        out.push('…');
+    }
+    out
+}
+
+/// Scores how well `candidate` matches `query` typed as a subsequence,
+/// ignoring case. Consecutive characters and word starts score higher.
+/// `None` when some query character isn't found in order.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i32> {
+    let query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+    let mut next = 0;
+    let mut score = 0;
+    let mut prev: Option<char> = None;
+    let mut prev_matched = false;
+    for c in candidate.chars().flat_map(char::to_lowercase) {
+        if next < query.len() && c == query[next] {
+            score += 1;
+            if prev_matched {
+                score += 5;
+            }
+            if prev.is_none_or(|p| !p.is_alphanumeric()) {
+                score += 10;
+            }
+            next += 1;
+            prev_matched = true;
+        } else {
+            prev_matched = false;
+        }
+        prev = Some(c);
+    }
+    (next == query.len()).then_some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: i64, chat_id: i64, sender_id: i64, content: &str) -> ChatMessage {
+        ChatMessage {
+            id,
+            chat_id,
+            sender_id,
+            content: Some(content.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn match_name_prefers_exact_then_shortest() {
+        let names = [(1, "Annabel"), (2, "Ann"), (3, "Anna")];
+        assert_eq!(match_name("ann", names), Some(2));
+        assert_eq!(match_name("anna", names), Some(3));
+        assert_eq!(match_name("ANNAB", names), Some(1));
+        assert_eq!(match_name("bob", names), None);
+    }
+
+    #[test]
+    fn match_name_breaks_ties_by_id() {
+        assert_eq!(match_name("al", [(7, "Alex"), (4, "Alan")]), Some(4));
+        assert_eq!(match_name("al", [(4, "Alan"), (7, "Alex")]), Some(4));
+    }
+
+    #[test]
+    fn fuzzy_score_needs_every_char_in_order() {
+        assert!(fuzzy_score("gnl", "general").is_some());
+        assert_eq!(fuzzy_score("lg", "general"), None);
+        assert_eq!(fuzzy_score("x", "general"), None);
+        assert_eq!(fuzzy_score("", "general"), Some(0));
+    }
+
+    #[test]
+    fn fuzzy_score_favors_runs_and_word_starts() {
+        let run = fuzzy_score("gen", "general").unwrap();
+        let spread = fuzzy_score("gnl", "general").unwrap();
+        assert!(run > spread);
+        let word_start = fuzzy_score("t", "team talk").unwrap();
+        let inner = fuzzy_score("l", "team talk").unwrap();
+        assert!(word_start > inner);
+        assert_eq!(fuzzy_score("GEN", "general"), Some(run));
+    }
+
+    #[test]
+    fn tokenize_splits_and_lowercases() {
+        assert_eq!(tokenize("Hello, World!  x2"), vec!["hello", "world", "x2"]);
+    }
+
+    #[test]
+    fn index_matches_word_prefixes_newest_first() {
+        let mut index = SearchIndex::default();
+        index.insert(&message(1, 10, 100, "Deploy tonight"));
+        index.insert(&message(2, 10, 101, "deployment done"));
+        index.insert(&message(3, 11, 100, "lunch?"));
+        let ids: Vec<i64> = index
+            .search(&SearchQuery::new("depl"), 10)
+            .iter()
+            .map(|hit| hit.msg_id)
+            .collect();
+        assert_eq!(ids, vec![2, 1]);
+        let query = SearchQuery {
+            sender_id: Some(100),
+            ..SearchQuery::new("deploy")
+        };
+        assert_eq!(index.search(&query, 10).len(), 1);
+        assert!(index.search(&SearchQuery::new(""), 10).is_empty());
+    }
+
+    #[test]
+    fn remove_chat_forgets_its_messages() {
+        let mut index = SearchIndex::default();
+        index.insert(&message(1, 10, 100, "hello"));
+        index.insert(&message(2, 11, 100, "hello there"));
+        index.remove_chat(11);
+        let hits = index.search(&SearchQuery::new("hello"), 10);
+        assert_eq!(
+            hits,
+            vec![SearchHit {
+                chat_id: 10,
+                msg_id: 1
+            }]
+        );
+        assert!(index.search(&SearchQuery::new("there"), 10).is_empty());
+    }
+}
@@ -0,0 +1,119 @@
+use crate::search::{SearchHit, SearchQuery, snippet};
+use crate::state::*;
+use crate::theme::{StyledRows, ThemeStamp};
+use makepad_widgets::*;
+
+const MAX_HITS: usize = 200;
+
+script_mod! {
+    use mod.prelude.widgets.*
+    use mod.widgets.*
+
+    mod.widgets.SearchPanel = #(SearchPanel::register_widget(vm)) {
+        width: Fill
+        height: Fit
+        flow: Down
+        query := SDTextInput{
+            width: Fill
+            empty_text: "Search messages (from:user in:chat)"
+        }
+        hits := PortalList{
+            visible: false
+            height: Fill
+            scroll_bar: ScrollBar{}
+            hit := CachedView{
+                hit_item := ChatItem{}
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum SearchPanelAction {
+    /// Whether a query is entered and the hit list replaces the chat list.
+    Active(bool),
+    #[default]
+    None,
+}
+
+#[derive(Script, ScriptHook, Widget)]
+struct SearchPanel {
+    #[deref]
+    view: View,
+    /// Hits as of the last draw, so clicks resolve against what is on screen.
+    #[rust]
+    hits: Vec<SearchHit>,
+    #[rust]
+    theme: ThemeStamp,
+    #[rust]
+    rows: StyledRows,
+}
+
+impl Widget for SearchPanel {
+    fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        let state = scope.data.get::<State>().expect("State not found.");
+        if self.theme.update(state.theme_version) {
+            let query = self.widget(cx, ids!(query));
+            state.theme.style_input(cx, &query);
+        }
+        let text = self.text_input(cx, ids!(query)).text();
+        while let Some(item) = self.view.draw_walk(cx, scope, walk).step() {
+            if let Some(mut list) = item.as_portal_list().borrow_mut() {
+                let state = scope.data.get::<State>().expect("State not found.");
+                let query = state.parse_search(&text).unwrap_or_default();
+                self.hits = state.search_messages(&query, MAX_HITS);
+                list.set_item_range(cx, 0, self.hits.len());
+                while let Some(item_id) = list.next_visible_item(cx) {
+                    if let Some(hit) = self.hits.get(item_id) {
+                        let item = list.item(cx, item_id, id!(hit));
+                        if self.rows.needs_style(state.theme_version, &item) {
+                            let hit_item = item.widget(cx, ids!(hit_item));
+                            state.theme.style_chat_item(cx, &hit_item);
+                        }
+                        let content = state
+                            .find_message(hit.chat_id, hit.msg_id)
+                            .and_then(|msg| msg.content.as_deref())
+                            .unwrap_or("");
+                        item.label(cx, ids!(hit_item.body.target_usr.text))
+                            .set_text(cx, &state.get_chat_name(hit.chat_id));
+                        item.label(cx, ids!(hit_item.body.last_msg.text))
+                            .set_text(cx, &snippet(content, &query));
+                        item.draw_all_unscoped(cx);
+                    }
+                }
+            }
+        }
+        DrawStep::done()
+    }
+
+    fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
+        let actions = cx.capture_actions(|cx| {
+            self.view.handle_event(cx, event, scope);
+        });
+        if let Some(text) = self.text_input(cx, ids!(query)).changed(&actions) {
+            let active = !SearchQuery::new(&text).is_empty() || text.contains(':');
+            self.view
+                .portal_list(cx, ids!(hits))
+                .set_visible(cx, active);
+            cx.action(SearchPanelAction::Active(active));
+            self.redraw(cx);
+        }
+        let state = scope.data.get_mut::<State>().expect("State not found.");
+        let portal_list = self.view.portal_list(cx, ids!(hits));
+        for (item_id, _) in portal_list.items_with_actions(&actions) {
+            let item_widget = portal_list.item(cx, item_id, id!(hit));
+            let body_view = item_widget.view(cx, ids!(body));
+            for action in &actions {
+                if let ViewAction::FingerUp(fe) = action.as_widget_action().cast()
+                    && body_view.area().rect(cx).contains(fe.abs)
+                    && let Some(hit) = self.hits.get(item_id)
+                {
+                    state.scroll_to_message = Some(hit.msg_id);
+                    state.go_to_chat(cx, hit.chat_id);
+                    log!("Opened search hit {} in chat {}", hit.msg_id, hit.chat_id);
+                }
+            }
+        }
+        cx.extend_actions(actions);
+    }
+}
@@ -0,0 +1,306 @@
+use crate::router::{Route, RouterAction, Screen};
+use crate::state::*;
+use crate::theme::{StyledRows, Theme, ThemeStamp};
+use makepad_widgets::*;
+
+script_mod! {
+    use mod.prelude.widgets.*
+    use mod.widgets.*
+
+    let StepRow = View {
+        width: Fill
+        height: Fit
+        flow: Right
+        align: Align { y: 0.5 }
+        spacing: 6.0
+        label := Label {
+            width: Fill
+            text: ""
+            draw_text +: {
+                color: mod.sd_theme.text
+                text_style +: { font_size: 11.0 }
+            }
+        }
+        smaller := SDButton { text: "-" }
+        larger := SDButton { text: "+" }
+    }
+
+    mod.widgets.SettingsPanel = #(SettingsPanel::register_widget(vm)) {
+        width: Fill
+        height: Fill
+        page := View {
+            width: Fill
+            height: Fill
+            align: Align { x: 0.5, y: 0.0 }
+            show_bg: true
+            draw_bg +: {
+                color: mod.sd_theme.background
+            }
+            panel := View {
+                width: 480.0
+                height: Fill
+                flow: Down
+                show_bg: true
+                draw_bg +: {
+                    color: mod.sd_theme.panel
+                }
+                padding: Inset { top: 10.0, right: 10.0, bottom: 10.0, left: 10.0 }
+                spacing: 6.0
+                header := View {
+                    width: Fill
+                    height: Fit
+                    flow: Right
+                    align: Align { y: 0.5 }
+                    title := H4 { width: Fill, text: "Settings" }
+                    close := SDButton { text: "Close" }
+                }
+                scale_row := StepRow{}
+                font_row := StepRow{}
+                downloads_title := H4 { width: Fill, text: "Downloads folder" }
+                downloads_row := View {
+                    width: Fill
+                    height: Fit
+                    flow: Right
+                    align: Align { y: 0.5 }
+                    spacing: 6.0
+                    directory := SDTextInput {
+                        width: Fill
+                        margin: Inset { top: 0.0, right: 0.0, bottom: 0.0, left: 0.0 }
+                        empty_text: "~/Downloads"
+                    }
+                    save := SDButton { text: "Save" }
+                    reset := SDButton { text: "Default" }
+                }
+                downloads_status := Label {
+                    width: Fill
+                    text: ""
+                    draw_text +: {
+                        color: mod.sd_theme.text_muted
+                        text_style +: { font_size: 9.0 }
+                    }
+                }
+                theme_title := H4 { width: Fill, text: "Theme" }
+                themes := PortalList{
+                    height: Fill
+                    scroll_bar: ScrollBar{}
+                    entry := View {
+                        width: Fill
+                        height: Fit
+                        padding: Inset { bottom: 6.0 }
+                        choose := SDButton { width: Fill, text: "" }
+                    }
+                }
+                hint := Label {
+                    width: Fill
+                    text: "Custom themes are read from the themes folder in the data directory."
+                    draw_text +: {
+                        color: mod.sd_theme.text_muted
+                        text_style +: { font_size: 9.0 }
+                    }
+                }
+                reload := SDButton { text: "Reload themes" }
+            }
+        }
+    }
+}
+
+/// Id of the settings screen.
+pub const SETTINGS_SCREEN: LiveId = live_id!(settings);
+
+/// The user's preferences, opened from the chat list header.
+pub struct SettingsScreen;
+
+impl Screen for SettingsScreen {
+    fn id(&self) -> LiveId {
+        SETTINGS_SCREEN
+    }
+
+    fn page(&self) -> &'static [LiveId] {
+        ids!(settings_page)
+    }
+
+    fn enter(&self, cx: &mut Cx, page: &WidgetRef, state: &mut State, _route: &Route) {
+        if let Some(mut panel) = page.borrow_mut::<SettingsPanel>() {
+            panel.open(cx, state);
+        }
+    }
+}
+
+/// Page with the user's preferences.
+#[derive(Script, ScriptHook, Widget)]
+struct SettingsPanel {
+    #[deref]
+    view: View,
+    /// Theme names as of the last draw, in row order.
+    #[rust]
+    themes: Vec<String>,
+    /// Outcome of the last change of the downloads folder.
+    #[rust]
+    downloads_status: String,
+    #[rust]
+    theme: ThemeStamp,
+    #[rust]
+    rows: StyledRows,
+}
+
+impl SettingsPanel {
+    /// Starts over with the current downloads folder and no status.
+    fn open(&mut self, cx: &mut Cx, state: &State) {
+        self.downloads_status.clear();
+        self.show_download_directory(cx, state);
+    }
+
+    fn apply_theme(&self, cx: &mut Cx, theme: &Theme) {
+        theme.style_background(cx, &self.widget(cx, ids!(page)), theme.background);
+        theme.style_background(cx, &self.widget(cx, ids!(panel)), theme.panel);
+        theme.style_input(cx, &self.widget(cx, ids!(downloads_row.directory)));
+        for label in [
+            ids!(header.title),
+            ids!(scale_row.label),
+            ids!(font_row.label),
+            ids!(downloads_title),
+            ids!(theme_title),
+        ] {
+            theme.style_label(cx, &self.widget(cx, label), theme.text);
+        }
+        for label in [ids!(downloads_status), ids!(hint)] {
+            theme.style_label(cx, &self.widget(cx, label), theme.text_muted);
+        }
+        for button in [
+            ids!(header.close),
+            ids!(scale_row.smaller),
+            ids!(scale_row.larger),
+            ids!(font_row.smaller),
+            ids!(font_row.larger),
+            ids!(downloads_row.save),
+            ids!(downloads_row.reset),
+            ids!(reload),
+        ] {
+            theme.style_button(cx, &self.widget(cx, button));
+        }
+    }
+
+    /// Shows the folder new downloads go to in the input.
+    fn show_download_directory(&mut self, cx: &mut Cx, state: &State) {
+        let directory = state
+            .downloads
+            .directory()
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_default();
+        self.text_input(cx, ids!(downloads_row.directory))
+            .set_text(cx, &directory);
+    }
+
+    fn set_download_directory(
+        &mut self,
+        cx: &mut Cx,
+        state: &mut State,
+        directory: Option<String>,
+    ) {
+        self.downloads_status = match state.downloads.set_directory(directory) {
+            Ok(()) => {
+                self.show_download_directory(cx, state);
+                "New downloads are saved here.".to_string()
+            }
+            Err(err) => err,
+        };
+        self.redraw(cx);
+    }
+}
+
+impl Widget for SettingsPanel {
+    fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        let state = scope.data.get::<State>().expect("State not found.");
+        if self.theme.update(state.theme_version) {
+            self.apply_theme(cx, &state.theme);
+        }
+        self.label(cx, ids!(downloads_status))
+            .set_text(cx, &self.downloads_status);
+        self.themes = state
+            .themes
+            .iter()
+            .map(|theme| theme.name.clone())
+            .collect();
+        self.label(cx, ids!(scale_row.label)).set_text(
+            cx,
+            &format!("Interface scale: {:.0}%", state.theme.ui_scale * 100.0),
+        );
+        self.label(cx, ids!(font_row.label)).set_text(
+            cx,
+            &format!("Message text: {:.0} pt", state.theme.message_font_size),
+        );
+        while let Some(item) = self.view.draw_walk(cx, scope, walk).step() {
+            if let Some(mut list) = item.as_portal_list().borrow_mut() {
+                let state = scope.data.get::<State>().expect("State not found.");
+                list.set_item_range(cx, 0, self.themes.len());
+                while let Some(item_id) = list.next_visible_item(cx) {
+                    let Some(name) = self.themes.get(item_id) else {
+                        continue;
+                    };
+                    let item = list.item(cx, item_id, id!(entry));
+                    let button = item.button(cx, ids!(choose));
+                    if *name == state.theme.name {
+                        button.set_text(cx, &format!("✓ {}", name));
+                    } else {
+                        button.set_text(cx, name);
+                    }
+                    if self.rows.needs_style(state.theme_version, &item) {
+                        state.theme.style_button(cx, &button);
+                    }
+                    item.draw_all_unscoped(cx);
+                }
+            }
+        }
+        DrawStep::done()
+    }
+
+    fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
+        let actions = cx.capture_actions(|cx| {
+            self.view.handle_event(cx, event, scope);
+        });
+        let state = scope.data.get_mut::<State>().expect("State not found.");
+        if self.button(cx, ids!(header.close)).clicked(&actions) {
+            cx.action(RouterAction::Pop);
+        }
+        if self.button(cx, ids!(scale_row.smaller)).clicked(&actions) {
+            state.step_ui_scale(-1);
+        }
+        if self.button(cx, ids!(scale_row.larger)).clicked(&actions) {
+            state.step_ui_scale(1);
+        }
+        if self.button(cx, ids!(font_row.smaller)).clicked(&actions) {
+            state.step_message_font(-1);
+        }
+        if self.button(cx, ids!(font_row.larger)).clicked(&actions) {
+            state.step_message_font(1);
+        }
+        let directory = self.text_input(cx, ids!(downloads_row.directory));
+        if self.button(cx, ids!(downloads_row.save)).clicked(&actions)
+            || directory.returned(&actions).is_some()
+        {
+            let text = directory.text().trim().to_string();
+            let directory = (!text.is_empty()).then_some(text);
+            self.set_download_directory(cx, state, directory);
+        }
+        if self.button(cx, ids!(downloads_row.reset)).clicked(&actions) {
+            self.set_download_directory(cx, state, None);
+        }
+        if self.button(cx, ids!(reload)).clicked(&actions) {
+            state.reload_themes();
+            cx.redraw_all();
+        }
+        let portal_list = self.view.portal_list(cx, ids!(themes));
+        for (item_id, _) in portal_list.items_with_actions(&actions) {
+            if portal_list
+                .item(cx, item_id, id!(entry))
+                .button(cx, ids!(choose))
+                .clicked(&actions)
+                && let Some(name) = self.themes.get(item_id)
+            {
+                state.set_theme(name);
+                cx.redraw_all();
+            }
+        }
+        cx.extend_actions(actions);
+    }
+}
@@ -1,6 +1,18 @@
+use crate::time::Timestamp;
 use makepad_micro_serde::*;
 use makepad_widgets::makepad_platform::makepad_network::{WsMessage, WsSend};
 use makepad_widgets::*;
+use std::collections::HashMap;
+
+/// Message length assumed until the server advertises its own in READY.
+pub const DEFAULT_MAX_MESSAGE_LENGTH: usize = 4000;
+
+/// Socket ops the server uses to reject an IDENTIFY or drop an expired session.
+const WS_AUTH_FAILURE_OPS: &[&str] = &["INVALID_SESSION", "UNAUTHORIZED", "AUTH_FAILED"];
+
+/// Wait before the first reconnect attempt, doubled after each failed one.
+const RECONNECT_MIN_DELAY: f64 = 1.0;
+const RECONNECT_MAX_DELAY: f64 = 30.0;
 
 #[derive(Default)]
 pub struct ShinDensenClient {
@@ -8,6 +20,33 @@ pub struct ShinDensenClient {
     ws_url: String,
     socket: Option<LiveId>,
     token: Option<String>,
+    ready: bool,
+    refreshing: bool,
+    in_flight: HashMap<LiveId, PendingRequest>,
+    retry_queue: Vec<PendingRequest>,
+    max_message_length: Option<usize>,
+    /// Fires when the dropped socket should be opened again.
+    reconnect_timer: Timer,
+    /// Reconnect attempts since the socket was last READY.
+    reconnect_attempts: u32,
+    /// The socket already refreshed the token after a rejected IDENTIFY and
+    /// hasn't been READY since; another rejection ends the session.
+    socket_reauth_attempted: bool,
+}
+
+/// A REST request kept around until its response arrives, so it can be replayed
+/// after a token refresh.
+#[derive(Clone, Debug)]
+struct PendingRequest {
+    kind: LiveId,
+    suffix: String,
+    body: Option<Vec<u8>>,
+    /// Body type other than JSON, for uploads.
+    content_type: Option<String>,
+    /// Caller's handle echoed back with the result, for uploads.
+    tag: Option<String>,
+    /// Already replayed once after a refresh; another 401 ends the session.
+    retried: bool,
 }
 
 #[derive(SerJson, Debug)]
@@ -15,6 +54,11 @@ pub struct AuthRequestPayload {
     pub username: String,
 }
 
+#[derive(SerJson, Debug)]
+pub struct RefreshRequestPayload {
+    pub token: String,
+}
+
 #[derive(SerJson, Debug)]
 pub struct FilePayload {
     pub _type: String,
@@ -24,11 +68,24 @@ pub struct FilePayload {
     pub size_bytes: i64,
 }
 
+impl From<&FileMetadata> for FilePayload {
+    fn from(file: &FileMetadata) -> Self {
+        Self {
+            _type: file._type.clone(),
+            url: file.url.clone(),
+            filename: file.filename.clone(),
+            mime_type: file.mime_type.clone(),
+            size_bytes: file.size_bytes,
+        }
+    }
+}
+
 #[derive(SerJson, Debug)]
 pub struct ChatMessagePayload {
     pub chat_id: i64,
     pub content: Option<String>,
     pub files: Option<Vec<FilePayload>>,
+    pub nonce: Option<String>,
 }
 
 #[derive(SerJson, Debug)]
@@ -42,6 +99,11 @@ pub struct WsIdentifyData {
     pub token: String,
 }
 
+#[derive(DeJson, Debug)]
+pub struct WsOp {
+    pub op: String,
+}
+
 #[derive(DeJson, Debug)]
 pub struct WsReady {
     pub op: String,
@@ -51,6 +113,7 @@ pub struct WsReady {
 #[derive(DeJson, Debug)]
 pub struct WsReadyData {
     pub user_id: i64,
+    pub max_message_length: Option<usize>,
 }
 
 #[derive(SerJson, Debug)]
@@ -86,7 +149,26 @@ pub struct FileMetadata {
     pub filename: String,
     pub mime_type: Option<String>,
     pub size_bytes: i64,
-    pub created_at: String,
+    pub created_at: Timestamp,
+}
+
+impl FileMetadata {
+    pub fn is_image(&self) -> bool {
+        self.mime_type
+            .as_deref()
+            .is_some_and(|mime| mime.starts_with("image/"))
+    }
+
+    /// Size for display, e.g. `1.4 MB`.
+    pub fn size_label(&self) -> String {
+        let bytes = self.size_bytes.max(0) as f64;
+        match bytes {
+            b if b < 1024.0 => format!("{} B", self.size_bytes.max(0)),
+            b if b < 1024.0 * 1024.0 => format!("{:.1} KB", b / 1024.0),
+            b if b < 1024.0 * 1024.0 * 1024.0 => format!("{:.1} MB", b / (1024.0 * 1024.0)),
+            b => format!("{:.1} GB", b / (1024.0 * 1024.0 * 1024.0)),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default, DeJson, SerJson, PartialEq)]
@@ -95,7 +177,7 @@ pub struct ChatMessage {
     pub chat_id: i64,
     pub sender_id: i64,
     pub content: Option<String>,
-    pub timestamp: String,
+    pub timestamp: Timestamp,
     pub files: Vec<FileMetadata>,
 }
 
@@ -110,7 +192,7 @@ pub struct ChatInfo {
     pub id: i64,
     pub name: Option<String>,
     pub chat_type: String,
-    pub created_at: String,
+    pub created_at: Timestamp,
     pub participants: Vec<i64>,
 }
 
@@ -122,6 +204,12 @@ pub enum ShinDensenClientAction {
     Chats(Vec<ChatInfo>),
     History(GetHistoryResponse),
     Token(String),
+    SessionExpired,
+    /// The WebSocket went away without us closing it.
+    Disconnected,
+    /// An upload finished; carries the tag given to `upload_file`.
+    FileUploaded(String, FileMetadata),
+    UploadFailed(String, String),
     UserSearchResponse(Vec<UserInfoResponse>),
     UserInfo(UserInfoResponse),
     UserNotFound,
@@ -137,32 +225,126 @@ impl ShinDensenClient {
         Self {
             api_url,
             ws_url,
-            socket: None,
-            token: None,
+            ..Default::default()
+        }
+    }
+
+    pub fn api_url(&self) -> &str {
+        &self.api_url
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    /// Longest message text the server accepts.
+    pub fn max_message_length(&self) -> usize {
+        self.max_message_length
+            .unwrap_or(DEFAULT_MAX_MESSAGE_LENGTH)
+    }
+
+    /// Absolute form of a file URL, which the server may give relative to the API.
+    pub fn resolve_url(&self, url: &str) -> String {
+        if url.starts_with("http://") || url.starts_with("https://") {
+            url.to_string()
+        } else {
+            format!(
+                "{}/{}",
+                self.api_url.trim_end_matches('/'),
+                url.trim_start_matches('/')
+            )
+        }
+    }
+
+    /// Adds the session token to a request made outside the client, e.g. a
+    /// file fetch.
+    pub fn sign_request(&self, request: &mut HttpRequest) {
+        if let Some(token) = &self.token {
+            request.set_header("Authorization".to_string(), format!("Bearer {}", token));
         }
     }
 
     fn send_request<T: SerJson>(
-        &self,
+        &mut self,
         cx: &mut Cx,
         suffix: &str,
         payload: Option<T>,
         live_id: LiveId,
     ) {
-        let method = if payload.is_some() {
+        let pending = PendingRequest {
+            kind: live_id,
+            suffix: suffix.to_string(),
+            body: payload.map(|p| p.serialize_json().into_bytes()),
+            content_type: None,
+            tag: None,
+            retried: false,
+        };
+        self.dispatch(cx, pending);
+    }
+
+    fn dispatch(&mut self, cx: &mut Cx, pending: PendingRequest) {
+        let method = if pending.body.is_some() {
             HttpMethod::POST
         } else {
             HttpMethod::GET
         };
-        let mut request = HttpRequest::new(format!("{}/{suffix}", self.api_url), method);
-        if let Some(token) = &self.token {
-            request.set_header("Authorization".to_string(), format!("Bearer {}", token));
+        let mut request = HttpRequest::new(format!("{}/{}", self.api_url, pending.suffix), method);
+        self.sign_request(&mut request);
+        if let Some(body) = &pending.body {
+            let content_type = pending
+                .content_type
+                .as_deref()
+                .unwrap_or("application/json");
+            request.set_header("Content-Type".to_string(), content_type.to_string());
+            request.set_body(body.clone());
+        }
+        // Every request gets its own id so the response can be matched back to it.
+        let request_id = LiveId::unique();
+        self.in_flight.insert(request_id, pending);
+        cx.http_request(request_id, request);
+    }
+
+    /// Parks a request rejected with 401 and starts a token refresh if one
+    /// isn't already running. A request rejected again after its replay means
+    /// the fresh token doesn't help either.
+    fn handle_unauthorized(&mut self, cx: &mut Cx, pending: Option<PendingRequest>) {
+        let Some(token) = self.token.clone() else {
+            self.expire_session(cx);
+            return;
+        };
+        if let Some(pending) = pending {
+            if pending.retried {
+                log!("Request {} rejected again after a refresh", pending.kind);
+                self.expire_session(cx);
+                return;
+            }
+            self.retry_queue.push(pending);
         }
-        if let Some(payload) = payload {
-            request.set_header("Content-Type".to_string(), "application/json".to_string());
-            request.set_body(payload.serialize_json().as_bytes().to_vec());
+        if !self.refreshing {
+            log!("Token rejected, trying to refresh it");
+            self.refreshing = true;
+            let payload = RefreshRequestPayload { token };
+            self.send_request(cx, "auth/refresh", Some(payload), live_id!(RefreshToken));
         }
-        cx.http_request(live_id, request);
+    }
+
+    fn expire_session(&mut self, cx: &mut Cx) {
+        log!("Session expired");
+        self.end_session(cx);
+        cx.action(ShinDensenClientAction::SessionExpired);
+    }
+
+    /// Drops the token, the socket and every request still waiting for a response.
+    pub fn end_session(&mut self, cx: &mut Cx) {
+        self.close_socket(cx);
+        cx.stop_timer(self.reconnect_timer);
+        self.reconnect_timer = Timer::empty();
+        self.reconnect_attempts = 0;
+        self.socket_reauth_attempted = false;
+        self.token = None;
+        self.refreshing = false;
+        self.in_flight.clear();
+        self.retry_queue.clear();
     }
 
     fn open_socket(&mut self, cx: &mut Cx) {
@@ -170,13 +352,58 @@ impl ShinDensenClient {
         // Authorization is now done via the IDENTIFY message
         let socket_id = live_id!(ShinDensenWebSocket);
         if let Err(err) = cx.net.ws_open(socket_id, request) {
-            cx.action(ShinDensenClientAction::Error(format!(
-                "Failed to open WebSocket: {}",
-                err
-            )));
+            error!("Failed to open WebSocket: {}", err);
+            // Reconnect attempts already told the user the connection is gone.
+            if self.reconnect_attempts == 0 {
+                cx.action(ShinDensenClientAction::Error(format!(
+                    "Failed to open WebSocket: {}",
+                    err
+                )));
+            }
+            self.schedule_reconnect(cx);
         } else {
             self.socket = Some(socket_id);
+            self.ready = false;
+        }
+    }
+
+    /// Forgets a socket that went away on its own and plans a new one.
+    fn socket_lost(&mut self, cx: &mut Cx) {
+        self.socket = None;
+        self.ready = false;
+        cx.action(ShinDensenClientAction::Disconnected);
+        self.schedule_reconnect(cx);
+    }
+
+    /// Starts the reconnect timer, waiting longer after each failed attempt.
+    fn schedule_reconnect(&mut self, cx: &mut Cx) {
+        if self.token.is_none() || !self.reconnect_timer.is_empty() {
+            return;
+        }
+        let delay = (RECONNECT_MIN_DELAY * 2f64.powi(self.reconnect_attempts.min(16) as i32))
+            .min(RECONNECT_MAX_DELAY);
+        self.reconnect_attempts += 1;
+        log!("Reconnecting in {}s", delay);
+        self.reconnect_timer = cx.start_timeout(delay);
+    }
+
+    /// Reopens the socket once the reconnect timer fires.
+    pub fn handle_event(&mut self, cx: &mut Cx, event: &Event) {
+        if self.reconnect_timer.is_event(event).is_some() {
+            self.reconnect_timer = Timer::empty();
+            if self.socket.is_none() && self.token.is_some() {
+                self.open_socket(cx);
+            }
+        }
+    }
+
+    fn close_socket(&mut self, cx: &mut Cx) {
+        if let Some(socket_id) = self.socket.take()
+            && let Err(err) = cx.net.ws_close(socket_id)
+        {
+            error!("Failed to close WebSocket: {}", err);
         }
+        self.ready = false;
     }
 
     pub fn authorize(&mut self, cx: &mut Cx, user: String) {
@@ -184,11 +411,11 @@ impl ShinDensenClient {
         self.send_request(cx, "login", Some(payload), live_id!(AuthRequest));
     }
 
-    pub fn get_chats(&self, cx: &mut Cx) {
+    pub fn get_chats(&mut self, cx: &mut Cx) {
         self.send_request::<String>(cx, "chats", None, live_id!(GetChats));
     }
 
-    pub fn get_history(&self, cx: &mut Cx, chat_id: i64) {
+    pub fn get_history(&mut self, cx: &mut Cx, chat_id: i64) {
         self.send_request::<String>(
             cx,
             &format!("chats/{}/messages", chat_id),
@@ -197,7 +424,16 @@ impl ShinDensenClient {
         );
     }
 
-    pub fn user_search(&self, cx: &mut Cx, username: String) {
+    pub fn get_history_after(&mut self, cx: &mut Cx, chat_id: i64, after_id: i64) {
+        self.send_request::<String>(
+            cx,
+            &format!("chats/{}/messages?after={}", chat_id, after_id),
+            None,
+            live_id!(GetHistory),
+        );
+    }
+
+    pub fn user_search(&mut self, cx: &mut Cx, username: String) {
         self.send_request::<String>(
             cx,
             &format!("users?username={}", username),
@@ -206,7 +442,7 @@ impl ShinDensenClient {
         );
     }
 
-    pub fn user_get_by_id(&self, cx: &mut Cx, user_id: i64) {
+    pub fn user_get_by_id(&mut self, cx: &mut Cx, user_id: i64) {
         self.send_request::<String>(
             cx,
             &format!("users/{}", user_id),
@@ -215,32 +451,52 @@ impl ShinDensenClient {
         );
     }
 
-    pub fn initiate_chat(&self, cx: &mut Cx, target_id: i64) {
+    pub fn initiate_chat(&mut self, cx: &mut Cx, target_id: i64) {
         let payload = InitiateChatPayload { target_id };
         self.send_request(cx, "chats/initiate", Some(payload), live_id!(InitiateChat));
     }
 
-    pub fn send_message(&mut self, cx: &mut Cx, chat_id: i64, text: String) {
-        if let Some(socket_id) = self.socket {
-            let payload = ChatMessagePayload {
-                chat_id,
-                content: Some(text),
-                files: Some(vec![]),
-            };
-            if let Err(err) = cx
-                .net
-                .ws_send(socket_id, WsSend::Text(payload.serialize_json()))
-            {
-                cx.action(ShinDensenClientAction::Error(format!(
-                    "Failed to send WebSocket message: {}",
-                    err
-                )));
-            }
-        } else {
-            cx.action(ShinDensenClientAction::Error(
-                "Socket is NOT open, cannot send message!".to_string(),
-            ));
-        }
+    /// Uploads `data` as a file. The result comes back as `FileUploaded` or
+    /// `UploadFailed` carrying `tag`.
+    pub fn upload_file(
+        &mut self,
+        cx: &mut Cx,
+        tag: String,
+        filename: &str,
+        mime_type: &str,
+        data: Vec<u8>,
+    ) {
+        let pending = PendingRequest {
+            kind: live_id!(UploadFile),
+            suffix: format!("files?filename={}", encode_query(filename)),
+            body: Some(data),
+            content_type: Some(mime_type.to_string()),
+            tag: Some(tag),
+            retried: false,
+        };
+        self.dispatch(cx, pending);
+    }
+
+    pub fn send_message(
+        &mut self,
+        cx: &mut Cx,
+        chat_id: i64,
+        text: String,
+        files: Vec<FilePayload>,
+        nonce: String,
+    ) -> Result<(), String> {
+        let Some(socket_id) = self.socket.filter(|_| self.ready) else {
+            return Err("Socket is not ready, cannot send message".to_string());
+        };
+        let payload = ChatMessagePayload {
+            chat_id,
+            content: (!text.is_empty()).then_some(text),
+            files: Some(files),
+            nonce: Some(nonce),
+        };
+        cx.net
+            .ws_send(socket_id, WsSend::Text(payload.serialize_json()))
+            .map_err(|err| format!("Failed to send WebSocket message: {}", err))
     }
 
     pub fn handle_signal(&mut self, _cx: &mut Cx) {
@@ -254,14 +510,32 @@ impl ShinDensenClient {
                     request_id,
                     response,
                 } => {
+                    let Some(pending) = self.in_flight.remove(request_id) else {
+                        continue;
+                    };
                     let data = response.get_string_body().unwrap_or_default();
-                    self.handle_response(cx, *request_id, response.status_code, data);
+                    self.handle_response(cx, pending, response.status_code, data);
                 }
                 NetworkResponse::HttpError { request_id, error } => {
-                    cx.action(ShinDensenClientAction::NetworkError(format!(
-                        "HttpRequestError for {}: {:?}",
-                        request_id, error.message
-                    )));
+                    let Some(pending) = self.in_flight.remove(request_id) else {
+                        continue;
+                    };
+                    if pending.kind == live_id!(RefreshToken) {
+                        self.refreshing = false;
+                        self.retry_queue.clear();
+                    }
+                    // A failed upload is reported once, to the outbox entry it belongs to.
+                    let action = match pending.tag {
+                        Some(tag) => ShinDensenClientAction::UploadFailed(
+                            tag,
+                            format!("{:?}", error.message),
+                        ),
+                        None => ShinDensenClientAction::NetworkError(format!(
+                            "HttpRequestError for {}: {:?}",
+                            pending.kind, error.message
+                        )),
+                    };
+                    cx.action(action);
                 }
                 NetworkResponse::WsOpened { socket_id } => {
                     if self.socket == Some(*socket_id) {
@@ -291,12 +565,32 @@ impl ShinDensenClient {
                                     && ready.op == "READY"
                                 {
                                     log!("WebSocket READY: user_id = {}", ready.d.user_id);
+                                    self.ready = true;
+                                    self.reconnect_attempts = 0;
+                                    self.socket_reauth_attempted = false;
+                                    self.max_message_length = ready.d.max_message_length;
                                     cx.action(ShinDensenClientAction::Ready(ready.d.user_id));
                                     continue;
                                 }
 
-                                if let Ok(msg) = ChatMessage::deserialize_json(data) {
-                                    cx.action(ShinDensenClientAction::NewMessage(msg));
+                                if let Ok(op) = WsOp::deserialize_json(data)
+                                    && WS_AUTH_FAILURE_OPS.contains(&op.op.as_str())
+                                {
+                                    log!("WebSocket rejected the session: {}", op.op);
+                                    self.close_socket(cx);
+                                    if self.socket_reauth_attempted {
+                                        log!("WebSocket rejected again after a refresh");
+                                        self.expire_session(cx);
+                                    } else {
+                                        self.socket_reauth_attempted = true;
+                                        self.handle_unauthorized(cx, None);
+                                    }
+                                    continue;
+                                }
+
+                                match ChatMessage::deserialize_json(data) {
+                                    Ok(msg) => cx.action(ShinDensenClientAction::NewMessage(msg)),
+                                    Err(e) => log!("Skipping WebSocket message: {e:?}"),
                                 }
                             }
                             WsMessage::Binary(_) => {
@@ -308,13 +602,13 @@ impl ShinDensenClient {
                 NetworkResponse::WsClosed { socket_id } => {
                     if self.socket == Some(*socket_id) {
                         log!("WebSocket closed");
-                        self.socket = None;
+                        self.socket_lost(cx);
                     }
                 }
                 NetworkResponse::WsError { socket_id, message } => {
                     if self.socket == Some(*socket_id) {
                         error!("WebSocket error: {}", message);
-                        self.socket = None;
+                        self.socket_lost(cx);
                     }
                 }
                 _ => {}
@@ -322,7 +616,32 @@ impl ShinDensenClient {
         }
     }
 
-    pub fn handle_response(&mut self, cx: &mut Cx, request_id: LiveId, status: u16, data: String) {
+    fn handle_response(&mut self, cx: &mut Cx, pending: PendingRequest, status: u16, data: String) {
+        let request_id = pending.kind;
+        if request_id == live_id!(RefreshToken) {
+            self.handle_refresh_response(cx, status, data);
+            return;
+        }
+
+        if status == 401 && request_id != live_id!(AuthRequest) {
+            self.handle_unauthorized(cx, Some(pending));
+            return;
+        }
+
+        if request_id == live_id!(UploadFile) {
+            let tag = pending.tag.unwrap_or_default();
+            let result = match status {
+                200 | 201 => FileMetadata::deserialize_json(&data)
+                    .map_err(|e| format!("Parsing UploadFile: {e:?}")),
+                _ => Err(format!("Server returned error code {}", status)),
+            };
+            cx.action(match result {
+                Ok(file) => ShinDensenClientAction::FileUploaded(tag, file),
+                Err(err) => ShinDensenClientAction::UploadFailed(tag, err),
+            });
+            return;
+        }
+
         if request_id == live_id!(GetUserInfo) && status == 404 {
             cx.action(ShinDensenClientAction::UserNotFound);
             return;
@@ -402,4 +721,46 @@ impl ShinDensenClient {
             _ => {}
         }
     }
+
+    /// A missing refresh endpoint or a rejected token both end the session.
+    fn handle_refresh_response(&mut self, cx: &mut Cx, status: u16, data: String) {
+        self.refreshing = false;
+        if status != 200 {
+            log!("Token refresh failed with status {}", status);
+            self.expire_session(cx);
+            return;
+        }
+        match AuthResponse::deserialize_json(&data) {
+            Ok(data) => {
+                self.token = Some(data.token.clone());
+                for mut pending in std::mem::take(&mut self.retry_queue) {
+                    pending.retried = true;
+                    self.dispatch(cx, pending);
+                }
+                if self.socket.is_none() {
+                    self.open_socket(cx);
+                }
+                cx.action(ShinDensenClientAction::Token(data.token));
+            }
+            Err(e) => {
+                cx.action(ShinDensenClientAction::Error(format!(
+                    "Parsing RefreshToken: {e:?}"
+                )));
+                self.expire_session(cx);
+            }
+        }
+    }
+}
+
+/// Percent-encodes everything but unreserved characters, for query values.
+fn encode_query(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    out
 }
@@ -0,0 +1,310 @@
+use crate::storage::Store;
+use makepad_micro_serde::*;
+use makepad_widgets::*;
+use std::str::FromStr;
+
+const CONFIG_FILE: &str = "shortcuts.json";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShortcutCommand {
+    QuickSwitcher,
+    NextChat,
+    PreviousChat,
+    /// Closes the topmost overlay, or goes back a screen.
+    Close,
+    /// Goes back a screen.
+    Back,
+    FocusSearch,
+    /// Scales the whole interface.
+    ZoomIn,
+    ZoomOut,
+    ZoomReset,
+    /// Changes the message font size only.
+    LargerText,
+    SmallerText,
+}
+
+/// Key bindings as written in `shortcuts.json` in the data directory, e.g.
+/// `{"quick_switcher": "Ctrl+K"}`. Commands left out keep their default.
+#[derive(Clone, Debug, Default, DeJson, SerJson, PartialEq)]
+pub struct ShortcutConfig {
+    pub quick_switcher: Option<String>,
+    pub next_chat: Option<String>,
+    pub previous_chat: Option<String>,
+    pub close: Option<String>,
+    pub back: Option<String>,
+    pub focus_search: Option<String>,
+    pub zoom_in: Option<String>,
+    pub zoom_out: Option<String>,
+    pub zoom_reset: Option<String>,
+    pub larger_text: Option<String>,
+    pub smaller_text: Option<String>,
+}
+
+/// A key with the modifiers that have to be held, parsed from text like
+/// `Ctrl+Shift+K`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyBinding {
+    pub control: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub logo: bool,
+    pub key_code: KeyCode,
+}
+
+impl KeyBinding {
+    pub fn matches(&self, ke: &KeyEvent) -> bool {
+        ke.key_code == self.key_code
+            && ke.modifiers.control == self.control
+            && ke.modifiers.alt == self.alt
+            && ke.modifiers.shift == self.shift
+            && ke.modifiers.logo == self.logo
+    }
+}
+
+impl FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut binding = KeyBinding {
+            control: false,
+            alt: false,
+            shift: false,
+            logo: false,
+            key_code: KeyCode::Unknown,
+        };
+        let parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let Some((key, modifiers)) = parts.split_last() else {
+            return Err(format!("Empty key binding {:?}", s));
+        };
+        for modifier in modifiers {
+            match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => binding.control = true,
+                "alt" | "option" => binding.alt = true,
+                "shift" => binding.shift = true,
+                "cmd" | "super" | "logo" => binding.logo = true,
+                _ => return Err(format!("Unknown modifier {:?} in {:?}", modifier, s)),
+            }
+        }
+        binding.key_code =
+            parse_key(key).ok_or_else(|| format!("Unknown key {:?} in {:?}", key, s))?;
+        Ok(binding)
+    }
+}
+
+fn parse_key(name: &str) -> Option<KeyCode> {
+    let name = name.to_ascii_lowercase();
+    let key_code = match name.as_str() {
+        "esc" | "escape" => KeyCode::Escape,
+        "enter" | "return" => KeyCode::ReturnKey,
+        "tab" => KeyCode::Tab,
+        "space" => KeyCode::Space,
+        "backspace" => KeyCode::Backspace,
+        "delete" => KeyCode::Delete,
+        "up" => KeyCode::ArrowUp,
+        "down" => KeyCode::ArrowDown,
+        "left" => KeyCode::ArrowLeft,
+        "right" => KeyCode::ArrowRight,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        // `+` separates the parts, so the plus key goes by name.
+        "plus" | "equals" => KeyCode::Equals,
+        "minus" => KeyCode::Minus,
+        "slash" => KeyCode::Slash,
+        "comma" => KeyCode::Comma,
+        "period" => KeyCode::Period,
+        "a" => KeyCode::KeyA,
+        "b" => KeyCode::KeyB,
+        "c" => KeyCode::KeyC,
+        "d" => KeyCode::KeyD,
+        "e" => KeyCode::KeyE,
+        "f" => KeyCode::KeyF,
+        "g" => KeyCode::KeyG,
+        "h" => KeyCode::KeyH,
+        "i" => KeyCode::KeyI,
+        "j" => KeyCode::KeyJ,
+        "k" => KeyCode::KeyK,
+        "l" => KeyCode::KeyL,
+        "m" => KeyCode::KeyM,
+        "n" => KeyCode::KeyN,
+        "o" => KeyCode::KeyO,
+        "p" => KeyCode::KeyP,
+        "q" => KeyCode::KeyQ,
+        "r" => KeyCode::KeyR,
+        "s" => KeyCode::KeyS,
+        "t" => KeyCode::KeyT,
+        "u" => KeyCode::KeyU,
+        "v" => KeyCode::KeyV,
+        "w" => KeyCode::KeyW,
+        "x" => KeyCode::KeyX,
+        "y" => KeyCode::KeyY,
+        "z" => KeyCode::KeyZ,
+        "0" => KeyCode::Key0,
+        "1" => KeyCode::Key1,
+        "2" => KeyCode::Key2,
+        "3" => KeyCode::Key3,
+        "4" => KeyCode::Key4,
+        "5" => KeyCode::Key5,
+        "6" => KeyCode::Key6,
+        "7" => KeyCode::Key7,
+        "8" => KeyCode::Key8,
+        "9" => KeyCode::Key9,
+        "f1" => KeyCode::F1,
+        "f2" => KeyCode::F2,
+        "f3" => KeyCode::F3,
+        "f4" => KeyCode::F4,
+        "f5" => KeyCode::F5,
+        "f6" => KeyCode::F6,
+        "f7" => KeyCode::F7,
+        "f8" => KeyCode::F8,
+        "f9" => KeyCode::F9,
+        "f10" => KeyCode::F10,
+        "f11" => KeyCode::F11,
+        "f12" => KeyCode::F12,
+        _ => return None,
+    };
+    Some(key_code)
+}
+
+/// The active bindings, defaults overridden by the settings file.
+#[derive(Clone, Debug, Default)]
+pub struct Shortcuts {
+    bindings: Vec<(ShortcutCommand, KeyBinding)>,
+}
+
+impl Shortcuts {
+    pub fn load() -> Self {
+        let config: ShortcutConfig = Store::root()
+            .and_then(|root| std::fs::read_to_string(root.join(CONFIG_FILE)).ok())
+            .and_then(|data| ShortcutConfig::deserialize_json(&data).ok())
+            .unwrap_or_default();
+        Self::from_config(config)
+    }
+
+    /// The default bindings with the valid ones of `config` in their place.
+    pub fn from_config(config: ShortcutConfig) -> Self {
+        let mut shortcuts = Shortcuts::default();
+        let mut bind = |command, custom: Option<String>, default: &str| {
+            let binding = custom
+                .and_then(|text| {
+                    text.parse::<KeyBinding>()
+                        .map_err(|err| error!("Ignoring shortcut: {}", err))
+                        .ok()
+                })
+                .unwrap_or_else(|| default.parse().expect("valid default shortcut"));
+            shortcuts.bindings.push((command, binding));
+        };
+        bind(
+            ShortcutCommand::QuickSwitcher,
+            config.quick_switcher,
+            "Ctrl+K",
+        );
+        bind(ShortcutCommand::NextChat, config.next_chat, "Alt+Down");
+        bind(
+            ShortcutCommand::PreviousChat,
+            config.previous_chat,
+            "Alt+Up",
+        );
+        bind(ShortcutCommand::Close, config.close, "Esc");
+        bind(ShortcutCommand::Back, config.back, "Alt+Left");
+        bind(ShortcutCommand::FocusSearch, config.focus_search, "Ctrl+F");
+        bind(ShortcutCommand::ZoomIn, config.zoom_in, "Ctrl+Plus");
+        bind(ShortcutCommand::ZoomOut, config.zoom_out, "Ctrl+Minus");
+        bind(ShortcutCommand::ZoomReset, config.zoom_reset, "Ctrl+0");
+        bind(
+            ShortcutCommand::LargerText,
+            config.larger_text,
+            "Ctrl+Shift+Plus",
+        );
+        bind(
+            ShortcutCommand::SmallerText,
+            config.smaller_text,
+            "Ctrl+Shift+Minus",
+        );
+        shortcuts
+    }
+
+    pub fn command(&self, ke: &KeyEvent) -> Option<ShortcutCommand> {
+        self.bindings
+            .iter()
+            .find(|(_, binding)| binding.matches(ke))
+            .map(|(command, _)| *command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(control: bool, alt: bool, shift: bool, key_code: KeyCode) -> KeyBinding {
+        KeyBinding {
+            control,
+            alt,
+            shift,
+            logo: false,
+            key_code,
+        }
+    }
+
+    #[test]
+    fn parses_modifiers_and_keys() {
+        assert_eq!(
+            "Ctrl+Shift+K".parse(),
+            Ok(binding(true, false, true, KeyCode::KeyK))
+        );
+        assert_eq!(
+            " alt + Down ".parse(),
+            Ok(binding(false, true, false, KeyCode::ArrowDown))
+        );
+        assert_eq!(
+            "Esc".parse(),
+            Ok(binding(false, false, false, KeyCode::Escape))
+        );
+        assert_eq!(
+            "Control+Plus".parse(),
+            Ok(binding(true, false, false, KeyCode::Equals))
+        );
+        let logo: KeyBinding = "Cmd+F12".parse().unwrap();
+        assert!(logo.logo && !logo.control);
+        assert_eq!(logo.key_code, KeyCode::F12);
+    }
+
+    #[test]
+    fn rejects_unknown_parts() {
+        assert!("Hyper+K".parse::<KeyBinding>().is_err());
+        assert!("Ctrl+Banana".parse::<KeyBinding>().is_err());
+        assert!("Ctrl+".parse::<KeyBinding>().is_err());
+        assert!("".parse::<KeyBinding>().is_err());
+    }
+
+    #[test]
+    fn config_overrides_only_valid_bindings() {
+        let shortcuts = Shortcuts::from_config(ShortcutConfig {
+            quick_switcher: Some("Ctrl+P".to_string()),
+            next_chat: Some("Ctrl+Nonsense".to_string()),
+            ..Default::default()
+        });
+        let bound = |command| {
+            shortcuts
+                .bindings
+                .iter()
+                .find(|(c, _)| *c == command)
+                .map(|(_, binding)| *binding)
+        };
+        assert_eq!(
+            bound(ShortcutCommand::QuickSwitcher),
+            Some(binding(true, false, false, KeyCode::KeyP))
+        );
+        assert_eq!(
+            bound(ShortcutCommand::NextChat),
+            Some(binding(false, true, false, KeyCode::ArrowDown))
+        );
+        assert_eq!(
+            bound(ShortcutCommand::Back),
+            Some(binding(false, true, false, KeyCode::ArrowLeft))
+        );
+        assert_eq!(shortcuts.bindings.len(), 11);
+    }
+}
@@ -1,13 +1,85 @@
-use crate::shindensen_client::{ChatInfo, ChatMessage, ShinDensenClient, UserInfoResponse};
-use makepad_widgets::Cx;
-use std::collections::HashMap;
-
-#[derive(Default, Debug, PartialEq, Clone, Copy)]
-pub enum Screen {
-    #[default]
-    Auth,
-    Dialog,
-    NewChatInit,
+#[cfg(feature = "ai")]
+use crate::assistant::{Assistant, CompletionMessage, ReplyTarget, chat_prompt};
+use crate::clipboard;
+use crate::dialog::{CHATS_SCREEN, chat_route};
+use crate::downloads::Downloads;
+use crate::images::{self, ImagePreviews};
+use crate::layout::{
+    COLLAPSE_CONTACTS_BELOW, COLLAPSED_CONTACTS_WIDTH, CONTACTS_WIDTH, MAX_CONTACTS_WIDTH,
+    MIN_CONTACTS_WIDTH, SINGLE_PANE_WIDTH,
+};
+use crate::mentions::mentions_user;
+use crate::notifications::{Notification, Notifications};
+use crate::outbox::{MAX_ATTACHMENT_BYTES, Outbox, OutboxAttachment, UploadFailure};
+use crate::router::{Router, RouterAction};
+use crate::search::{SearchHit, SearchIndex, SearchQuery, fuzzy_score, match_name};
+use crate::shindensen_client::{
+    ChatInfo, ChatMessage, FileMetadata, ShinDensenClient, UserInfoResponse,
+};
+use crate::shortcuts::Shortcuts;
+use crate::storage::{LastSession, Store};
+use crate::summary::SummaryJob;
+#[cfg(feature = "ai")]
+use crate::summary::{chunk_lines, combine_prompt, link_citations, part_prompt, summary_prompt};
+use crate::theme::{
+    DEFAULT_MESSAGE_FONT_SIZE, DEFAULT_THEME, DEFAULT_UI_SCALE, MAX_MESSAGE_FONT_SIZE,
+    MAX_UI_SCALE, MIN_MESSAGE_FONT_SIZE, MIN_UI_SCALE, Theme, UI_SCALE_STEP,
+};
+use crate::time::Timestamp;
+use makepad_micro_serde::*;
+use makepad_widgets::{Cx, LiveId, ToUIReceiver, error, log};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+const CHATS_FILE: &str = "chats.json";
+const USERS_FILE: &str = "users.json";
+const CHAT_SETTINGS_FILE: &str = "chat_settings.json";
+const LAST_READ_FILE: &str = "last_read.json";
+const DRAFTS_FILE: &str = "drafts.json";
+const RECENT_EMOJI_FILE: &str = "recent_emoji.json";
+const PREFERENCES_FILE: &str = "preferences.json";
+const MAX_RECENT_EMOJI: usize = 32;
+const NOTIFICATION_PREVIEW_CHARS: usize = 120;
+
+/// Per-chat preferences of the user.
+#[derive(Clone, Debug, Default, DeJson, SerJson, PartialEq)]
+pub struct ChatSettings {
+    pub pinned: bool,
+    pub muted: bool,
+    /// End of a temporary mute; `None` with `muted` set means muted for good.
+    pub muted_until: Option<Timestamp>,
+    pub archived: bool,
+}
+
+/// Choices the user made in settings.
+#[derive(Clone, Debug, Default, DeJson, SerJson, PartialEq)]
+pub struct Preferences {
+    /// Name of the chosen theme; the default one when unset or unknown.
+    pub theme: Option<String>,
+    pub ui_scale: Option<f64>,
+    pub message_font_size: Option<f64>,
+    /// Width the chat list was resized to, at a scale of 1.
+    pub sidebar_width: Option<f64>,
+    /// Whether the chat list is collapsed to avatars.
+    pub sidebar_collapsed: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, DeJson, SerJson, PartialEq)]
+struct StoredChatSettings {
+    chat_id: i64,
+    settings: ChatSettings,
+}
+
+#[derive(Clone, Debug, Default, DeJson, SerJson, PartialEq)]
+struct StoredDraft {
+    chat_id: i64,
+    text: String,
+}
+
+#[derive(Clone, Debug, Default, DeJson, SerJson, PartialEq)]
+struct StoredLastRead {
+    chat_id: i64,
+    msg_id: i64,
 }
 
 #[derive(Default)]
@@ -15,22 +87,856 @@ pub struct State {
     pub username: String,
     pub chat_info: HashMap<i64, ChatInfo>,
     pub msg_history: HashMap<i64, Vec<ChatMessage>>,
+    /// Bumped whenever a history or the raw toggle of a message changes, so
+    /// the feed knows to rebuild its rows.
+    pub history_version: u64,
     pub user_info: HashMap<i64, UserInfoResponse>,
-    pub pending_user_fetches: std::collections::HashSet<i64>,
+    pub pending_user_fetches: HashSet<i64>,
     pub open_chat_id: Option<i64>,
     pub current_user_id: Option<i64>,
-    pub screen: Screen,
+    pub router: Router,
     pub client: ShinDensenClient,
+    pub store: Option<Store>,
+    pub outbox: Outbox,
+    pub search_index: SearchIndex,
+    /// Message the feed should scroll to once it is drawn, set by search.
+    pub scroll_to_message: Option<i64>,
+    pub chat_settings: HashMap<i64, ChatSettings>,
+    /// Chat ids as shown in the chat list. Drawing and clicks both index into
+    /// this, so it only changes through `refresh_chat_order`.
+    pub chat_order: Vec<i64>,
+    /// Id of the newest message the user has seen in each chat.
+    pub last_read: HashMap<i64, i64>,
+    /// Unsent composer text of each chat.
+    pub drafts: HashMap<i64, String>,
+    /// Drafts changed since they were last written to disk.
+    drafts_dirty: bool,
+    /// Messages the user switched to their source text.
+    pub raw_messages: HashSet<i64>,
+    /// Id and time of the messages mentioning the current user, per chat,
+    /// kept up to date as messages arrive.
+    mention_ids: HashMap<i64, Vec<(i64, Timestamp)>>,
+    /// How many of `mention_ids` are unread, per chat. Cleared by `mark_read`.
+    unread_mentions: HashMap<i64, usize>,
+    #[cfg(feature = "ai")]
+    pub assistant: Assistant,
+    /// Summary shown in the side panel, if one was asked for.
+    pub summary: Option<SummaryJob>,
+    pub notifications: Notifications,
+    /// Whether the notification history panel is open.
+    pub show_notifications: bool,
+    /// Whether archived chats are unfolded in the chat list.
+    pub show_archived: bool,
+    /// Whether the quick chat switcher is open.
+    pub show_switcher: bool,
+    /// Whether the mentions inbox is open.
+    pub show_mentions: bool,
+    pub shortcuts: Shortcuts,
+    /// Emoji the user picked, most recent first.
+    pub recent_emoji: Vec<String>,
+    pub images: ImagePreviews,
+    /// Image attachment open in the full-size viewer.
+    pub lightbox: Option<FileMetadata>,
+    pub downloads: Downloads,
+    /// Files added to the composer of each chat, uploaded once the message is sent.
+    pub pending_attachments: HashMap<i64, Vec<OutboxAttachment>>,
+    /// Clipboard images read on a worker thread, with the chat they go to.
+    pasted_images: ToUIReceiver<(i64, Result<Option<Vec<u8>>, String>)>,
+    /// Whether a failed clipboard read was reported already. Missing tools
+    /// would otherwise fail every paste.
+    clipboard_error_shown: bool,
+    pub preferences: Preferences,
+    /// Built-in and custom themes the user can pick from.
+    pub themes: Vec<Theme>,
+    pub theme: Theme,
+    /// Bumped whenever `theme` changes, so the UI knows to restyle.
+    pub theme_version: u64,
+    /// Inner width of the window, zero until the first resize event.
+    pub window_width: f64,
+    /// In the single-pane layout, whether the chat list is shown in place of
+    /// the open chat.
+    pub show_chat_list: bool,
 }
 
 impl State {
     pub fn new(api_url: String, ws_url: String) -> Self {
         State {
             client: ShinDensenClient::new(api_url, ws_url),
+            #[cfg(feature = "ai")]
+            assistant: Assistant::new(),
+            shortcuts: Shortcuts::load(),
+            themes: Theme::load_all(),
             ..Default::default()
         }
     }
 
+    /// Opens the on-disk cache of the current account and fills the state from it.
+    pub fn open_store(&mut self) {
+        self.store = Store::open(self.client.api_url(), &self.username);
+        let Some(store) = &self.store else {
+            return;
+        };
+        images::remove_shared_cache();
+        for chat in store.load::<Vec<ChatInfo>>(CHATS_FILE).unwrap_or_default() {
+            if let Some(messages) = store.load::<Vec<ChatMessage>>(&history_file(chat.id)) {
+                for msg in &messages {
+                    self.search_index.insert(msg);
+                }
+                self.msg_history.insert(chat.id, messages);
+            }
+            self.chat_info.insert(chat.id, chat);
+        }
+        for user in store
+            .load::<Vec<UserInfoResponse>>(USERS_FILE)
+            .unwrap_or_default()
+        {
+            self.user_info.insert(user.id, user);
+        }
+        self.outbox = Outbox::load(store);
+        self.downloads = Downloads::load(store);
+        for stored in store
+            .load::<Vec<StoredChatSettings>>(CHAT_SETTINGS_FILE)
+            .unwrap_or_default()
+        {
+            self.chat_settings.insert(stored.chat_id, stored.settings);
+        }
+        for stored in store
+            .load::<Vec<StoredLastRead>>(LAST_READ_FILE)
+            .unwrap_or_default()
+        {
+            self.last_read.insert(stored.chat_id, stored.msg_id);
+        }
+        for stored in store
+            .load::<Vec<StoredDraft>>(DRAFTS_FILE)
+            .unwrap_or_default()
+        {
+            self.drafts.insert(stored.chat_id, stored.text);
+        }
+        self.recent_emoji = store.load(RECENT_EMOJI_FILE).unwrap_or_default();
+        self.preferences = store.load(PREFERENCES_FILE).unwrap_or_default();
+        #[cfg(feature = "ai")]
+        self.assistant.load_replies(store);
+        self.select_theme();
+        self.rebuild_mentions();
+        self.refresh_chat_order();
+        self.history_version += 1;
+    }
+
+    pub fn remember_session(&self) {
+        Store::save_last_session(&LastSession {
+            server: self.client.api_url().to_string(),
+            username: self.username.clone(),
+        });
+    }
+
+    pub fn save_chats(&self) {
+        if let Some(store) = &self.store {
+            let chats: Vec<ChatInfo> = self.chat_info.values().cloned().collect();
+            store.save(CHATS_FILE, &chats);
+        }
+    }
+
+    pub fn save_users(&self) {
+        if let Some(store) = &self.store {
+            let users: Vec<UserInfoResponse> = self.user_info.values().cloned().collect();
+            store.save(USERS_FILE, &users);
+        }
+    }
+
+    pub fn save_chat_settings(&self) {
+        if let Some(store) = &self.store {
+            let settings: Vec<StoredChatSettings> = self
+                .chat_settings
+                .iter()
+                .filter(|(_, settings)| **settings != ChatSettings::default())
+                .map(|(&chat_id, settings)| StoredChatSettings {
+                    chat_id,
+                    settings: settings.clone(),
+                })
+                .collect();
+            store.save(CHAT_SETTINGS_FILE, &settings);
+        }
+    }
+
+    pub fn save_last_read(&self) {
+        if let Some(store) = &self.store {
+            let last_read: Vec<StoredLastRead> = self
+                .last_read
+                .iter()
+                .map(|(&chat_id, &msg_id)| StoredLastRead { chat_id, msg_id })
+                .collect();
+            store.save(LAST_READ_FILE, &last_read);
+        }
+    }
+
+    pub fn save_drafts(&self) {
+        if let Some(store) = &self.store {
+            let drafts: Vec<StoredDraft> = self
+                .drafts
+                .iter()
+                .map(|(&chat_id, text)| StoredDraft {
+                    chat_id,
+                    text: text.clone(),
+                })
+                .collect();
+            store.save(DRAFTS_FILE, &drafts);
+        }
+    }
+
+    pub fn download(&mut self, cx: &mut Cx, file: &FileMetadata) {
+        self.downloads.start(cx, &self.client, file);
+    }
+
+    pub fn save_downloads(&self) {
+        if let Some(store) = &self.store {
+            self.downloads.save(store);
+        }
+    }
+
+    #[cfg(feature = "ai")]
+    pub fn save_assistant_replies(&self) {
+        if let Some(store) = &self.store {
+            self.assistant.save_replies(store);
+        }
+    }
+
+    /// Starts loading the preview of an image attachment.
+    pub fn request_image(&mut self, cx: &mut Cx, file: &FileMetadata) {
+        self.images
+            .request(cx, &self.client, self.store.as_ref(), file);
+    }
+
+    /// Moves `glyph` to the front of the recently used emoji.
+    pub fn use_emoji(&mut self, glyph: &str) {
+        self.recent_emoji.retain(|recent| recent != glyph);
+        self.recent_emoji.insert(0, glyph.to_string());
+        self.recent_emoji.truncate(MAX_RECENT_EMOJI);
+        if let Some(store) = &self.store {
+            store.save(RECENT_EMOJI_FILE, &self.recent_emoji);
+        }
+    }
+
+    /// Switches to the theme called `name` and remembers the choice.
+    pub fn set_theme(&mut self, name: &str) {
+        self.preferences.theme = Some(name.to_string());
+        self.save_preferences();
+    }
+
+    /// Grows or shrinks the whole interface by `steps` increments; zero goes
+    /// back to the default size.
+    pub fn step_ui_scale(&mut self, steps: i32) {
+        let scale = if steps == 0 {
+            DEFAULT_UI_SCALE
+        } else {
+            self.theme.ui_scale + steps as f64 * UI_SCALE_STEP
+        };
+        // Rounded so repeated steps don't drift away from whole percents.
+        let scale = (scale * 100.0).round() / 100.0;
+        self.preferences.ui_scale = Some(scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE));
+        self.save_preferences();
+    }
+
+    /// Changes the message font size by `steps` points; zero resets it.
+    pub fn step_message_font(&mut self, steps: i32) {
+        let size = if steps == 0 {
+            DEFAULT_MESSAGE_FONT_SIZE
+        } else {
+            self.theme.message_font_size + steps as f64
+        };
+        self.preferences.message_font_size =
+            Some(size.clamp(MIN_MESSAGE_FONT_SIZE, MAX_MESSAGE_FONT_SIZE));
+        self.save_preferences();
+    }
+
+    /// Whether the chat list shows only avatars. A single-pane layout always
+    /// shows it in full.
+    pub fn sidebar_collapsed(&self) -> bool {
+        !self.single_pane() && self.preferences.sidebar_collapsed == Some(true)
+    }
+
+    /// Width of the chat list at a scale of 1.
+    pub fn sidebar_width(&self) -> f64 {
+        if self.sidebar_collapsed() {
+            return COLLAPSED_CONTACTS_WIDTH;
+        }
+        self.preferences
+            .sidebar_width
+            .unwrap_or(CONTACTS_WIDTH)
+            .clamp(MIN_CONTACTS_WIDTH, MAX_CONTACTS_WIDTH)
+    }
+
+    /// Follows a drag of the splitter to `width`, at a scale of 1. Narrow
+    /// enough collapses the list; `save_preferences` keeps the result.
+    pub fn resize_sidebar(&mut self, width: f64) {
+        if width < COLLAPSE_CONTACTS_BELOW {
+            self.preferences.sidebar_collapsed = Some(true);
+        } else {
+            self.preferences.sidebar_collapsed = None;
+            self.preferences.sidebar_width =
+                Some(width.clamp(MIN_CONTACTS_WIDTH, MAX_CONTACTS_WIDTH));
+        }
+    }
+
+    /// Collapses the chat list to avatars, or expands it to its last width.
+    pub fn set_sidebar_collapsed(&mut self, collapsed: bool) {
+        self.preferences.sidebar_collapsed = collapsed.then_some(true);
+        self.save_preferences();
+    }
+
+    /// Back to the default width, expanded.
+    pub fn reset_sidebar(&mut self) {
+        self.preferences.sidebar_width = None;
+        self.preferences.sidebar_collapsed = None;
+        self.save_preferences();
+    }
+
+    /// Applies the preferences and writes them to the account's store.
+    pub fn save_preferences(&mut self) {
+        self.select_theme();
+        if let Some(store) = &self.store {
+            store.save(PREFERENCES_FILE, &self.preferences);
+        }
+    }
+
+    /// Reads the custom themes again, e.g. after the user edited one.
+    pub fn reload_themes(&mut self) {
+        self.themes = Theme::load_all();
+        // Picks up new colors of the chosen theme too, not only a new name.
+        self.select_theme();
+    }
+
+    fn select_theme(&mut self) {
+        let name = self.preferences.theme.as_deref().unwrap_or(DEFAULT_THEME);
+        let mut theme = self
+            .themes
+            .iter()
+            .find(|theme| theme.name == name)
+            .cloned()
+            .unwrap_or_default();
+        theme.ui_scale = self
+            .preferences
+            .ui_scale
+            .unwrap_or(DEFAULT_UI_SCALE)
+            .clamp(MIN_UI_SCALE, MAX_UI_SCALE);
+        theme.message_font_size = self
+            .preferences
+            .message_font_size
+            .unwrap_or(DEFAULT_MESSAGE_FONT_SIZE)
+            .clamp(MIN_MESSAGE_FONT_SIZE, MAX_MESSAGE_FONT_SIZE);
+        if theme != self.theme {
+            self.theme = theme;
+            self.theme_version += 1;
+        }
+    }
+
+    pub fn draft(&self, chat_id: i64) -> &str {
+        self.drafts.get(&chat_id).map_or("", |text| text.as_str())
+    }
+
+    /// Stores the composer text of `chat_id`; an empty text drops the draft.
+    /// Drafts reach the disk through `flush_drafts`, not on every keystroke.
+    pub fn set_draft(&mut self, chat_id: i64, text: &str) {
+        let changed = if text.trim().is_empty() {
+            self.drafts.remove(&chat_id).is_some()
+        } else {
+            self.drafts.insert(chat_id, text.to_string()).as_deref() != Some(text)
+        };
+        self.drafts_dirty |= changed;
+    }
+
+    /// Writes the drafts if any changed. Called when switching chats, when
+    /// the session ends and when the app quits.
+    pub fn flush_drafts(&mut self) {
+        if self.drafts_dirty {
+            self.drafts_dirty = false;
+            self.save_drafts();
+        }
+    }
+
+    pub fn save_history(&self, chat_id: i64) {
+        if let Some(store) = &self.store
+            && let Some(messages) = self.msg_history.get(&chat_id)
+        {
+            store.save(&history_file(chat_id), messages);
+        }
+    }
+
+    /// Drops every chat the server no longer lists, along with its cached
+    /// history and per-chat settings.
+    pub fn forget_missing_chats(&mut self, chats: &[ChatInfo]) {
+        let gone: Vec<i64> = self
+            .chat_info
+            .keys()
+            .copied()
+            .filter(|id| !chats.iter().any(|chat| chat.id == *id))
+            .collect();
+        if gone.is_empty() {
+            return;
+        }
+        for chat_id in &gone {
+            log!("Forgetting chat {}", chat_id);
+            self.chat_info.remove(chat_id);
+            self.msg_history.remove(chat_id);
+            self.search_index.remove_chat(*chat_id);
+            self.chat_settings.remove(chat_id);
+            self.last_read.remove(chat_id);
+            self.mention_ids.remove(chat_id);
+            self.unread_mentions.remove(chat_id);
+            self.drafts.remove(chat_id);
+            discard_attachments(self.pending_attachments.remove(chat_id).unwrap_or_default());
+            #[cfg(feature = "ai")]
+            self.assistant.forget(ReplyTarget::Chat(*chat_id));
+            if self.open_chat_id == Some(*chat_id) {
+                self.open_chat_id = None;
+            }
+            if let Some(store) = &self.store {
+                store.remove(&history_file(*chat_id));
+            }
+        }
+        self.save_chat_settings();
+        self.save_last_read();
+        self.save_drafts();
+        #[cfg(feature = "ai")]
+        self.save_assistant_replies();
+        self.history_version += 1;
+    }
+
+    /// Queues a composed message and sends it right away if the socket is READY.
+    pub fn queue_message(&mut self, cx: &mut Cx, chat_id: i64, text: String) {
+        self.queue_with_attachments(cx, chat_id, text, vec![]);
+    }
+
+    /// Queues a message whose files get uploaded first.
+    pub fn queue_with_attachments(
+        &mut self,
+        cx: &mut Cx,
+        chat_id: i64,
+        text: String,
+        attachments: Vec<OutboxAttachment>,
+    ) {
+        self.outbox.push(chat_id, text, attachments);
+        self.outbox.flush(cx, &mut self.client);
+        self.save_outbox();
+    }
+
+    /// Adds a local file to the composer of `chat_id`.
+    pub fn attach_file(&mut self, chat_id: i64, path: &Path) -> Result<(), String> {
+        let attachment = OutboxAttachment::from_path(path)?;
+        let pending = self.pending_attachments.entry(chat_id).or_default();
+        if !pending.iter().any(|a| a.path == attachment.path) {
+            pending.push(attachment);
+        }
+        Ok(())
+    }
+
+    pub fn attachments(&self, chat_id: i64) -> &[OutboxAttachment] {
+        self.pending_attachments
+            .get(&chat_id)
+            .map_or(&[], |pending| pending.as_slice())
+    }
+
+    pub fn remove_attachment(&mut self, chat_id: i64, index: usize) {
+        if let Some(pending) = self.pending_attachments.get_mut(&chat_id)
+            && index < pending.len()
+        {
+            discard_attachments([pending.remove(index)]);
+        }
+    }
+
+    /// Reads an image off the clipboard for the composer of `chat_id`. The
+    /// result arrives through `handle_pasted_images`.
+    pub fn paste_image(&self, chat_id: i64) {
+        let sender = self.pasted_images.sender();
+        std::thread::spawn(move || {
+            let _ = sender.send((chat_id, clipboard::read_image()));
+        });
+    }
+
+    /// Attaches the pasted clipboard images as PNG files. Returns whether an
+    /// attachment was added or an error reported.
+    pub fn handle_pasted_images(&mut self) -> bool {
+        let mut changed = false;
+        while let Ok((chat_id, result)) = self.pasted_images.try_recv() {
+            let result = result.and_then(|data| match data {
+                Some(data) => self.attach_bytes(chat_id, "image.png", "image/png", &data),
+                None => Ok(()),
+            });
+            match result {
+                Ok(()) => changed = true,
+                Err(err) => {
+                    error!("Reading the clipboard failed: {}", err);
+                    if !self.clipboard_error_shown {
+                        self.clipboard_error_shown = true;
+                        self.notifications.push(Notification::error(format!(
+                            "Couldn't paste the image: {}",
+                            err
+                        )));
+                        changed = true;
+                    }
+                }
+            }
+        }
+        changed
+    }
+
+    /// Adds `data` to the composer of `chat_id` as a temporary file.
+    fn attach_bytes(
+        &mut self,
+        chat_id: i64,
+        filename: &str,
+        mime_type: &str,
+        data: &[u8],
+    ) -> Result<(), String> {
+        if data.len() as u64 > MAX_ATTACHMENT_BYTES {
+            return Err(format!(
+                "larger than {} MB",
+                MAX_ATTACHMENT_BYTES / (1024 * 1024)
+            ));
+        }
+        let store = self
+            .store
+            .as_ref()
+            .ok_or("No data directory to keep the file in")?;
+        let extension = Path::new(filename)
+            .extension()
+            .map(|ext| ext.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let name = format!("uploads/pasted-{:x}.{}", LiveId::unique().0, extension);
+        let path = store
+            .save_bytes(&name, data)
+            .ok_or("Failed to write the file")?;
+        self.pending_attachments
+            .entry(chat_id)
+            .or_default()
+            .push(OutboxAttachment {
+                filename: filename.to_string(),
+                mime_type: mime_type.to_string(),
+                path: path.to_string_lossy().into_owned(),
+                temporary: true,
+                uploaded: None,
+            });
+        Ok(())
+    }
+
+    /// Empties the composer attachments of `chat_id`, handing them to the caller.
+    pub fn take_attachments(&mut self, chat_id: i64) -> Vec<OutboxAttachment> {
+        self.pending_attachments
+            .remove(&chat_id)
+            .unwrap_or_default()
+    }
+
+    /// Sends `text` as a `.txt` attachment instead of a message body, for text
+    /// over the server's length limit.
+    pub fn queue_text_file(&mut self, cx: &mut Cx, chat_id: i64, text: &str) -> Result<(), String> {
+        let store = self
+            .store
+            .as_ref()
+            .ok_or("No data directory to keep the file in")?;
+        let name = format!("uploads/message-{:x}.txt", LiveId::unique().0);
+        let path = store
+            .save_bytes(&name, text.as_bytes())
+            .ok_or("Failed to write the file")?;
+        let mut attachments = vec![OutboxAttachment {
+            filename: "message.txt".to_string(),
+            mime_type: "text/plain; charset=utf-8".to_string(),
+            path: path.to_string_lossy().into_owned(),
+            temporary: true,
+            uploaded: None,
+        }];
+        attachments.extend(self.take_attachments(chat_id));
+        self.queue_with_attachments(cx, chat_id, String::new(), attachments);
+        Ok(())
+    }
+
+    /// Returns whether the upload was one of the outbox's.
+    pub fn upload_finished(&mut self, cx: &mut Cx, tag: &str, file: FileMetadata) -> bool {
+        if !self.outbox.upload_finished(tag, file) {
+            return false;
+        }
+        self.save_outbox();
+        self.flush_outbox(cx);
+        true
+    }
+
+    /// Schedules another attempt or gives up on the attachment, see
+    /// `Outbox::upload_failed`. `None` if the upload wasn't the outbox's.
+    pub fn upload_failed(&mut self, cx: &mut Cx, tag: &str) -> Option<UploadFailure> {
+        let failure = self.outbox.upload_failed(cx, tag)?;
+        if let UploadFailure::GaveUp(_) = failure {
+            self.save_outbox();
+            self.flush_outbox(cx);
+        }
+        Some(failure)
+    }
+
+    /// Returns how many queued messages went out.
+    pub fn flush_outbox(&mut self, cx: &mut Cx) -> usize {
+        let sent = self.outbox.flush(cx, &mut self.client);
+        if sent > 0 {
+            self.save_outbox();
+        }
+        sent
+    }
+
+    pub fn save_outbox(&self) {
+        if let Some(store) = &self.store {
+            self.outbox.save(store);
+        }
+    }
+
+    /// Asks only for messages newer than the last cached one, or for the whole
+    /// history when nothing is cached yet.
+    pub fn sync_history(&mut self, cx: &mut Cx, chat_id: i64) {
+        let last_id = self
+            .msg_history
+            .get(&chat_id)
+            .and_then(|msgs| msgs.last())
+            .map(|msg| msg.id);
+        match last_id {
+            Some(after_id) => self.client.get_history_after(cx, chat_id, after_id),
+            None => self.client.get_history(cx, chat_id),
+        }
+    }
+
+    /// Adds fetched messages to the history, skipping the ones already known.
+    pub fn merge_history(&mut self, chat_id: i64, messages: Vec<ChatMessage>) {
+        let first_load = !self.msg_history.contains_key(&chat_id);
+        let known: HashSet<i64> = self
+            .msg_history
+            .get(&chat_id)
+            .map_or_else(HashSet::new, |msgs| msgs.iter().map(|msg| msg.id).collect());
+        let mut added = vec![];
+        for msg in messages {
+            if !known.contains(&msg.id) && !added.iter().any(|m: &ChatMessage| m.id == msg.id) {
+                self.note_mention(&msg);
+                self.search_index.insert(&msg);
+                added.push(msg);
+            }
+        }
+        let history = self.msg_history.entry(chat_id).or_default();
+        history.extend(added);
+        history.sort_by_key(|msg| msg.id);
+        self.history_version += 1;
+        // A chat seen for the first time starts out read instead of flooding
+        // the list with old unread messages.
+        if (first_load && !self.last_read.contains_key(&chat_id))
+            || self.open_chat_id == Some(chat_id)
+        {
+            self.mark_read(chat_id);
+        }
+        self.refresh_chat_order();
+    }
+
+    /// Makes `chat_id` the open chat, syncs its history and marks it read.
+    pub fn open_chat(&mut self, cx: &mut Cx, chat_id: i64) {
+        self.flush_drafts();
+        self.open_chat_id = Some(chat_id);
+        self.show_chat_list = false;
+        self.sync_history(cx, chat_id);
+        self.mark_read(chat_id);
+    }
+
+    /// Opens `chat_id` from whichever screen is shown. It goes through the
+    /// router, so the current route always names the open chat.
+    pub fn go_to_chat(&mut self, cx: &mut Cx, chat_id: i64) {
+        let route = chat_route(chat_id);
+        if *self.router.current() == route {
+            // The route names it already, but it may be behind the chat list
+            // of a single pane or have a message to scroll to.
+            self.open_chat(cx, chat_id);
+            cx.redraw_all();
+        } else if self.router.current().screen == CHATS_SCREEN {
+            cx.action(RouterAction::Replace(route));
+        } else {
+            cx.action(RouterAction::Reset(route));
+        }
+    }
+
+    pub fn mark_read(&mut self, chat_id: i64) {
+        let Some(last_id) = self
+            .msg_history
+            .get(&chat_id)
+            .and_then(|msgs| msgs.last())
+            .map(|msg| msg.id)
+        else {
+            return;
+        };
+        self.unread_mentions.remove(&chat_id);
+        if self.last_read.insert(chat_id, last_id) != Some(last_id) {
+            self.save_last_read();
+        }
+    }
+
+    /// Messages from other people newer than the last read one.
+    pub fn unread_count(&self, chat_id: i64) -> usize {
+        let last_read = self.last_read.get(&chat_id).copied().unwrap_or(i64::MIN);
+        self.msg_history.get(&chat_id).map_or(0, |msgs| {
+            msgs.iter()
+                .filter(|msg| msg.id > last_read && Some(msg.sender_id) != self.current_user_id)
+                .count()
+        })
+    }
+
+    /// Whether someone else's message pings the current user.
+    pub fn mentions_me(&self, msg: &ChatMessage) -> bool {
+        Some(msg.sender_id) != self.current_user_id
+            && msg
+                .content
+                .as_deref()
+                .is_some_and(|content| mentions_user(content, &self.username))
+    }
+
+    /// Unread messages that mention the current user.
+    pub fn mention_count(&self, chat_id: i64) -> usize {
+        self.unread_mentions.get(&chat_id).copied().unwrap_or(0)
+    }
+
+    /// Unread mentions over all chats.
+    pub fn mention_total(&self) -> usize {
+        self.unread_mentions.values().sum()
+    }
+
+    /// Every loaded message mentioning the current user, newest first.
+    pub fn mentions(&self, limit: usize) -> Vec<SearchHit> {
+        let mut found: Vec<(Timestamp, SearchHit)> = self
+            .mention_ids
+            .iter()
+            .flat_map(|(&chat_id, ids)| {
+                ids.iter()
+                    .map(move |&(msg_id, time)| (time, SearchHit { chat_id, msg_id }))
+            })
+            .collect();
+        found.sort_unstable_by(|a, b| b.0.cmp(&a.0).then(b.1.msg_id.cmp(&a.1.msg_id)));
+        found.truncate(limit);
+        found.into_iter().map(|(_, hit)| hit).collect()
+    }
+
+    /// Counts `msg` if it mentions the current user. Called once per message
+    /// as it joins the history.
+    fn note_mention(&mut self, msg: &ChatMessage) {
+        if !self.mentions_me(msg) {
+            return;
+        }
+        let ids = self.mention_ids.entry(msg.chat_id).or_default();
+        if ids.iter().any(|&(id, _)| id == msg.id) {
+            return;
+        }
+        ids.push((msg.id, msg.timestamp));
+        let last_read = self
+            .last_read
+            .get(&msg.chat_id)
+            .copied()
+            .unwrap_or(i64::MIN);
+        if msg.id > last_read {
+            *self.unread_mentions.entry(msg.chat_id).or_default() += 1;
+        }
+    }
+
+    /// Recounts the mentions of the whole history, after loading it from disk
+    /// or learning who the current user is.
+    fn rebuild_mentions(&mut self) {
+        self.mention_ids.clear();
+        self.unread_mentions.clear();
+        let history = std::mem::take(&mut self.msg_history);
+        for msg in history.values().flatten() {
+            self.note_mention(msg);
+        }
+        self.msg_history = history;
+    }
+
+    /// Records who is logged in. Mentions are recounted if that changed, since
+    /// one's own messages never count as mentions.
+    pub fn set_current_user_id(&mut self, user_id: i64) {
+        if self.current_user_id != Some(user_id) {
+            self.current_user_id = Some(user_id);
+            self.rebuild_mentions();
+        }
+    }
+
+    /// Participants of `chat_id` other than the current user whose username or
+    /// display name starts with `query`, for the mention popup.
+    pub fn mention_candidates(
+        &self,
+        chat_id: i64,
+        query: &str,
+        limit: usize,
+    ) -> Vec<&UserInfoResponse> {
+        let query = query.to_lowercase();
+        let Some(chat) = self.chat_info.get(&chat_id) else {
+            return vec![];
+        };
+        let mut users: Vec<&UserInfoResponse> = chat
+            .participants
+            .iter()
+            .filter(|&&id| Some(id) != self.current_user_id)
+            .filter_map(|id| self.user_info.get(id))
+            .filter(|user| {
+                user.username.to_lowercase().starts_with(&query)
+                    || user
+                        .display_name
+                        .as_ref()
+                        .is_some_and(|name| name.to_lowercase().starts_with(&query))
+            })
+            .collect();
+        users.sort_by(|a, b| a.username.cmp(&b.username));
+        users.truncate(limit);
+        users
+    }
+
+    /// Forgets everything loaded for the current account and drops the client session.
+    /// The on-disk cache stays, so logging back in renders instantly.
+    pub fn end_session(&mut self, cx: &mut Cx) {
+        self.flush_drafts();
+        self.client.end_session(cx);
+        Store::clear_last_session();
+        self.store = None;
+        self.outbox = Outbox::default();
+        self.downloads.cancel_all(cx);
+        self.downloads = Downloads::default();
+        discard_attachments(
+            self.pending_attachments
+                .drain()
+                .flat_map(|(_, pending)| pending),
+        );
+        self.search_index.clear();
+        self.scroll_to_message = None;
+        self.chat_settings.clear();
+        self.chat_order.clear();
+        self.last_read.clear();
+        self.drafts.clear();
+        self.raw_messages.clear();
+        self.mention_ids.clear();
+        self.unread_mentions.clear();
+        #[cfg(feature = "ai")]
+        self.assistant.clear();
+        self.summary = None;
+        self.notifications.clear();
+        self.show_notifications = false;
+        self.show_archived = false;
+        self.show_switcher = false;
+        self.show_mentions = false;
+        self.show_chat_list = false;
+        self.preferences = Preferences::default();
+        self.select_theme();
+        self.recent_emoji.clear();
+        self.images.clear();
+        self.lightbox = None;
+        self.username.clear();
+        self.chat_info.clear();
+        self.msg_history.clear();
+        self.history_version += 1;
+        self.user_info.clear();
+        self.pending_user_fetches.clear();
+        self.open_chat_id = None;
+        self.current_user_id = None;
+    }
+
+    /// Whether the window is too narrow for the chat list and the chat side
+    /// by side.
+    pub fn single_pane(&self) -> bool {
+        self.window_width > 0.0 && self.window_width < SINGLE_PANE_WIDTH * self.theme.ui_scale
+    }
+
     pub fn get_chats_number(&self) -> usize {
         self.chat_info.len()
     }
@@ -47,9 +953,436 @@ impl State {
         }
     }
 
+    /// Toasts a message unless the user is looking at its chat, sent it, or
+    /// muted the chat. Mentions get through a mute.
+    pub fn notify_message(&mut self, msg: &ChatMessage) {
+        let chat_open =
+            self.router.current().screen == CHATS_SCREEN && self.open_chat_id == Some(msg.chat_id);
+        let mention = self.mentions_me(msg);
+        if chat_open
+            || Some(msg.sender_id) == self.current_user_id
+            || (self.is_muted(msg.chat_id) && !mention)
+        {
+            return;
+        }
+        let text = format!(
+            "{}: {}",
+            self.user_name(msg.sender_id),
+            msg.content.as_deref().unwrap_or("sent a file")
+        );
+        let text: String = text.chars().take(NOTIFICATION_PREVIEW_CHARS).collect();
+        let chat_name = self.get_chat_name(msg.chat_id);
+        let title = if mention {
+            format!("Mention in {}", chat_name)
+        } else {
+            chat_name
+        };
+        self.notifications
+            .push(Notification::message(msg.chat_id, title, text));
+    }
+
+    /// Switches a message between its formatted and its source text.
+    pub fn toggle_raw(&mut self, msg_id: i64) {
+        if !self.raw_messages.remove(&msg_id) {
+            self.raw_messages.insert(msg_id);
+        }
+        self.history_version += 1;
+    }
+
+    /// Adds a message that arrived on the socket, unless the history has it
+    /// already, e.g. from the sync after a reconnect.
     pub fn add_message(&mut self, msg: ChatMessage) {
         let chat_id = msg.chat_id;
-        self.msg_history.entry(chat_id).or_insert(vec![]).push(msg);
+        // Kept in id order like `merge_history`, so a late arrival lands in place.
+        let index = match self.msg_history.get(&chat_id).map_or(Err(0), |msgs| {
+            msgs.binary_search_by_key(&msg.id, |known| known.id)
+        }) {
+            Ok(_) => return,
+            Err(index) => index,
+        };
+        self.note_mention(&msg);
+        self.search_index.insert(&msg);
+        self.msg_history
+            .entry(chat_id)
+            .or_default()
+            .insert(index, msg);
+        self.history_version += 1;
+        self.save_history(chat_id);
+        if self.open_chat_id == Some(chat_id) {
+            self.mark_read(chat_id);
+        }
+        self.refresh_chat_order();
+    }
+
+    pub fn chat_settings(&self, chat_id: i64) -> ChatSettings {
+        self.chat_settings
+            .get(&chat_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn is_pinned(&self, chat_id: i64) -> bool {
+        self.chat_settings
+            .get(&chat_id)
+            .is_some_and(|settings| settings.pinned)
+    }
+
+    pub fn is_archived(&self, chat_id: i64) -> bool {
+        self.chat_settings
+            .get(&chat_id)
+            .is_some_and(|settings| settings.archived)
+    }
+
+    pub fn is_muted(&self, chat_id: i64) -> bool {
+        self.chat_settings.get(&chat_id).is_some_and(|settings| {
+            settings.muted
+                && settings
+                    .muted_until
+                    .is_none_or(|until| until > Timestamp::now())
+        })
+    }
+
+    fn update_chat_settings(&mut self, chat_id: i64, f: impl FnOnce(&mut ChatSettings)) {
+        f(self.chat_settings.entry(chat_id).or_default());
+        self.save_chat_settings();
+        self.refresh_chat_order();
+    }
+
+    pub fn toggle_pinned(&mut self, chat_id: i64) {
+        self.update_chat_settings(chat_id, |settings| settings.pinned = !settings.pinned);
+    }
+
+    pub fn toggle_archived(&mut self, chat_id: i64) {
+        self.update_chat_settings(chat_id, |settings| settings.archived = !settings.archived);
+    }
+
+    /// Mutes the chat until `until`, or indefinitely when it is `None`.
+    pub fn mute(&mut self, chat_id: i64, until: Option<Timestamp>) {
+        self.update_chat_settings(chat_id, |settings| {
+            settings.muted = true;
+            settings.muted_until = until;
+        });
+    }
+
+    pub fn unmute(&mut self, chat_id: i64) {
+        self.update_chat_settings(chat_id, |settings| {
+            settings.muted = false;
+            settings.muted_until = None;
+        });
+    }
+
+    /// Time of the last message, or of the chat creation for empty chats.
+    pub fn last_activity(&self, chat_id: i64) -> Timestamp {
+        self.msg_history
+            .get(&chat_id)
+            .and_then(|msgs| msgs.last())
+            .map(|msg| msg.timestamp)
+            .or_else(|| self.chat_info.get(&chat_id).map(|chat| chat.created_at))
+            .unwrap_or_default()
+    }
+
+    /// Pinned chats first, then by most recent activity. Ties fall back to the
+    /// chat id so the order never flickers.
+    pub fn refresh_chat_order(&mut self) {
+        let mut order: Vec<(bool, Timestamp, i64)> = self
+            .chat_info
+            .keys()
+            .map(|&id| (self.is_pinned(id), self.last_activity(id), id))
+            .collect();
+        order.sort_unstable_by(|a, b| b.cmp(a));
+        self.chat_order = order.into_iter().map(|(_, _, id)| id).collect();
+    }
+
+    /// `chat_order` split into active and archived chats.
+    pub fn listed_chats(&self) -> (Vec<i64>, Vec<i64>) {
+        self.chat_order
+            .iter()
+            .partition(|&&chat_id| !self.is_archived(chat_id))
+    }
+
+    /// Chats that can be opened from the chat list, top to bottom.
+    pub fn visible_chats(&self) -> Vec<i64> {
+        let (mut chats, archived) = self.listed_chats();
+        if self.show_archived {
+            chats.extend(archived);
+        }
+        chats
+    }
+
+    /// Opens the chat `step` rows below the open one in the chat list, or
+    /// above it when negative. Stops at the ends of the list.
+    pub fn step_chat(&mut self, cx: &mut Cx, step: isize) {
+        let chats = self.visible_chats();
+        let Some(last) = chats.len().checked_sub(1) else {
+            return;
+        };
+        let index = match self
+            .open_chat_id
+            .and_then(|open| chats.iter().position(|&id| id == open))
+        {
+            Some(index) => index.saturating_add_signed(step).min(last),
+            None if step > 0 => 0,
+            None => last,
+        };
+        self.go_to_chat(cx, chats[index]);
+    }
+
+    /// Chats whose name fuzzily matches `query`, best first. An empty query
+    /// lists every chat in chat list order.
+    pub fn switcher_matches(&self, query: &str) -> Vec<i64> {
+        let mut matches: Vec<(i32, usize, i64)> = self
+            .chat_order
+            .iter()
+            .enumerate()
+            .filter_map(|(index, &chat_id)| {
+                let score = fuzzy_score(query.trim(), &self.get_chat_name(chat_id))?;
+                Some((-score, index, chat_id))
+            })
+            .collect();
+        matches.sort_unstable();
+        matches.into_iter().map(|(_, _, chat_id)| chat_id).collect()
+    }
+
+    /// Turns the search box text into a query. `from:<user>` and `in:<chat>`
+    /// narrow it down by name, see `match_name`; a filter with no name yet is
+    /// ignored. `None` means a filter matched nobody.
+    pub fn parse_search(&self, text: &str) -> Option<SearchQuery> {
+        let mut query = SearchQuery::default();
+        let mut words = vec![];
+        for token in text.split_whitespace() {
+            if let Some(name) = token.strip_prefix("from:") {
+                if name.is_empty() {
+                    continue;
+                }
+                let names = self.user_info.values().flat_map(|u| {
+                    std::iter::once((u.id, u.username.as_str()))
+                        .chain(u.display_name.as_deref().map(|d| (u.id, d)))
+                });
+                query.sender_id = Some(match_name(name, names)?);
+            } else if let Some(name) = token.strip_prefix("in:") {
+                if name.is_empty() {
+                    continue;
+                }
+                let names = self
+                    .chat_info
+                    .keys()
+                    .map(|&id| (id, self.get_chat_name(id)));
+                query.chat_id = Some(match_name(name, names)?);
+            } else {
+                words.push(token);
+            }
+        }
+        query.words = SearchQuery::new(&words.join(" ")).words;
+        Some(query)
+    }
+
+    pub fn search_messages(&self, query: &SearchQuery, limit: usize) -> Vec<SearchHit> {
+        self.search_index.search(query, limit)
+    }
+
+    pub fn find_message(&self, chat_id: i64, msg_id: i64) -> Option<&ChatMessage> {
+        self.msg_history
+            .get(&chat_id)?
+            .iter()
+            .find(|msg| msg.id == msg_id)
+    }
+
+    pub fn user_name(&self, user_id: i64) -> String {
+        match self.user_info.get(&user_id) {
+            Some(user) => user
+                .display_name
+                .clone()
+                .unwrap_or_else(|| user.username.clone()),
+            None => user_id.to_string(),
+        }
+    }
+
+    /// Sends the recent conversation of `chat_id`, ending with `prompt`, to the
+    /// assistant. Its answer streams into the chat feed.
+    #[cfg(feature = "ai")]
+    pub fn ask_assistant(&mut self, cx: &mut Cx, chat_id: i64, prompt: &str) {
+        let limit = self.assistant.config().context_messages;
+        let history = self.msg_history.get(&chat_id).map_or(&[][..], |msgs| msgs);
+        let transcript: Vec<String> = history[history.len().saturating_sub(limit)..]
+            .iter()
+            .map(|msg| {
+                format!(
+                    "{}: {}",
+                    self.user_name(msg.sender_id),
+                    msg.content.as_deref().unwrap_or("")
+                )
+            })
+            .collect();
+        let me = match self.current_user_id {
+            Some(user_id) => self.user_name(user_id),
+            None => self.username.clone(),
+        };
+        let messages = chat_prompt(
+            &self.get_chat_name(chat_id),
+            &transcript,
+            format!("{}: {}", me, prompt),
+        );
+        self.assistant.ask(cx, ReplyTarget::Chat(chat_id), messages);
+    }
+
+    /// Summarizes the messages of `chat_id` newer than the last read one.
+    /// Conversations over the context budget are summarized chunk by chunk
+    /// first, see `continue_summary`.
+    #[cfg(feature = "ai")]
+    pub fn summarize_since_last_read(&mut self, cx: &mut Cx, chat_id: i64) {
+        self.close_summary();
+        let last_read = self.last_read.get(&chat_id).copied().unwrap_or(i64::MIN);
+        let lines: Vec<String> = self
+            .msg_history
+            .get(&chat_id)
+            .map_or(&[][..], |msgs| msgs)
+            .iter()
+            .filter(|msg| msg.id > last_read)
+            .map(|msg| {
+                format!(
+                    "[#{}] {}: {}",
+                    msg.id,
+                    self.user_name(msg.sender_id),
+                    msg.content.as_deref().unwrap_or("")
+                )
+            })
+            .collect();
+        let mut job = SummaryJob {
+            chat_id,
+            empty: lines.is_empty(),
+            ..Default::default()
+        };
+        let chunks = chunk_lines(&lines, self.assistant.config().context_budget_chars);
+        let chat_name = self.get_chat_name(chat_id);
+        if chunks.len() == 1 {
+            self.assistant.ask(
+                cx,
+                ReplyTarget::Summary,
+                vec![
+                    CompletionMessage::new("system", summary_prompt(&chat_name)),
+                    CompletionMessage::new("user", chunks[0].clone()),
+                ],
+            );
+            job.final_started = true;
+        } else {
+            for (index, chunk) in chunks.into_iter().enumerate() {
+                self.assistant.ask(
+                    cx,
+                    ReplyTarget::SummaryPart(index),
+                    vec![
+                        CompletionMessage::new("system", part_prompt()),
+                        CompletionMessage::new("user", chunk),
+                    ],
+                );
+                job.parts += 1;
+            }
+        }
+        self.summary = Some(job);
+    }
+
+    /// Once every chunk summary is in, merges them into the final summary.
+    #[cfg(feature = "ai")]
+    pub fn continue_summary(&mut self, cx: &mut Cx) {
+        let Some(job) = &self.summary else {
+            return;
+        };
+        if job.final_started {
+            return;
+        }
+        let mut parts = vec![];
+        let mut part_errors = vec![];
+        for index in 0..job.parts {
+            let Some(reply) = self.assistant.reply(ReplyTarget::SummaryPart(index)) else {
+                return;
+            };
+            if !reply.done {
+                return;
+            }
+            match &reply.error {
+                Some(err) => part_errors.push((index, err.clone())),
+                None if reply.text.trim().is_empty() => {
+                    part_errors.push((index, "the assistant sent nothing".to_string()))
+                }
+                None => parts.push(reply.text.clone()),
+            }
+        }
+        let chat_id = job.chat_id;
+        if let Some(job) = &mut self.summary {
+            job.part_errors = part_errors;
+            job.final_started = true;
+        }
+        if parts.is_empty() {
+            return;
+        }
+        let chat_name = self.get_chat_name(chat_id);
+        self.assistant.ask(
+            cx,
+            ReplyTarget::Summary,
+            vec![
+                CompletionMessage::new("system", combine_prompt(&chat_name)),
+                CompletionMessage::new("user", parts.join("\n")),
+            ],
+        );
+    }
+
+    pub fn close_summary(&mut self) {
+        #[cfg(feature = "ai")]
+        if let Some(job) = &self.summary {
+            for index in 0..job.parts {
+                self.assistant.forget(ReplyTarget::SummaryPart(index));
+            }
+            self.assistant.forget(ReplyTarget::Summary);
+        }
+        self.summary = None;
+    }
+
+    /// Markdown and status line of the summary panel.
+    pub fn summary_view(&self) -> Option<(String, String)> {
+        let job = self.summary.as_ref()?;
+        if job.empty {
+            return Some((
+                String::new(),
+                "Nothing new since you last read this chat.".to_string(),
+            ));
+        }
+        #[cfg(feature = "ai")]
+        {
+            let part_status = job.part_status();
+            if let Some(reply) = self.assistant.reply(ReplyTarget::Summary) {
+                let status = match (&reply.error, reply.done) {
+                    (Some(err), _) => err.clone(),
+                    (None, false) => "Summarizing...".to_string(),
+                    (None, true) => String::new(),
+                };
+                let status = [status, part_status]
+                    .into_iter()
+                    .filter(|line| !line.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                return Some((link_citations(&reply.text), status));
+            }
+            if job.final_started {
+                // Every part failed, so there was nothing to merge.
+                return Some((String::new(), part_status));
+            }
+            let done = (0..job.parts)
+                .filter(|&index| {
+                    self.assistant
+                        .reply(ReplyTarget::SummaryPart(index))
+                        .is_some_and(|reply| reply.done)
+                })
+                .count();
+            Some((
+                String::new(),
+                format!(
+                    "Reading part {} of {}...",
+                    (done + 1).min(job.parts),
+                    job.parts
+                ),
+            ))
+        }
+        #[cfg(not(feature = "ai"))]
+        None
     }
 
     pub fn fetch_user(&mut self, cx: &mut Cx, user_id: i64) {
@@ -116,3 +1449,119 @@ impl State {
         }
     }
 }
+
+/// Deletes the files the app made for attachments that are not sent after all.
+fn discard_attachments(attachments: impl IntoIterator<Item = OutboxAttachment>) {
+    for attachment in attachments.into_iter().filter(|a| a.temporary) {
+        let _ = std::fs::remove_file(&attachment.path);
+    }
+}
+
+fn history_file(chat_id: i64) -> String {
+    format!("messages/{}.json", chat_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: i64, username: &str, display_name: Option<&str>) -> UserInfoResponse {
+        UserInfoResponse {
+            id,
+            username: username.to_string(),
+            display_name: display_name.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    fn chat(id: i64, name: &str) -> ChatInfo {
+        ChatInfo {
+            id,
+            name: Some(name.to_string()),
+            chat_type: "group".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn state() -> State {
+        let mut state = State::default();
+        for user in [
+            user(1, "annabel", None),
+            user(2, "ann", Some("Ann Lee")),
+            user(3, "bob", Some("Annie")),
+        ] {
+            state.user_info.insert(user.id, user);
+        }
+        for chat in [chat(10, "Team"), chat(11, "Teamwork")] {
+            state.chat_info.insert(chat.id, chat);
+        }
+        state
+    }
+
+    fn message(id: i64, chat_id: i64) -> ChatMessage {
+        ChatMessage {
+            id,
+            chat_id,
+            sender_id: 1,
+            content: Some(format!("message {}", id)),
+            timestamp: Timestamp::now(),
+            files: vec![],
+        }
+    }
+
+    fn history_ids(state: &State, chat_id: i64) -> Vec<i64> {
+        state.msg_history[&chat_id]
+            .iter()
+            .map(|msg| msg.id)
+            .collect()
+    }
+
+    #[test]
+    fn add_message_skips_known_messages() {
+        let mut state = state();
+        state.merge_history(10, vec![message(1, 10), message(2, 10)]);
+        state.add_message(message(2, 10));
+        state.add_message(message(3, 10));
+        state.add_message(message(3, 10));
+        assert_eq!(history_ids(&state, 10), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn add_message_keeps_id_order() {
+        let mut state = state();
+        state.add_message(message(5, 10));
+        state.add_message(message(2, 10));
+        state.add_message(message(4, 10));
+        assert_eq!(history_ids(&state, 10), vec![2, 4, 5]);
+    }
+
+    #[test]
+    fn parse_search_prefers_exact_names() {
+        let state = state();
+        let query = state.parse_search("from:ann in:team deploy").unwrap();
+        assert_eq!(query.sender_id, Some(2));
+        assert_eq!(query.chat_id, Some(10));
+        assert_eq!(query.words, vec!["deploy"]);
+    }
+
+    #[test]
+    fn parse_search_takes_the_shortest_prefix_match() {
+        let state = state();
+        assert_eq!(state.parse_search("from:anni").unwrap().sender_id, Some(3));
+        assert_eq!(state.parse_search("in:teamw").unwrap().chat_id, Some(11));
+    }
+
+    #[test]
+    fn parse_search_ignores_empty_filters() {
+        let query = state().parse_search("from: in: hello").unwrap();
+        assert_eq!(query.sender_id, None);
+        assert_eq!(query.chat_id, None);
+        assert_eq!(query.words, vec!["hello"]);
+    }
+
+    #[test]
+    fn parse_search_fails_on_unknown_names() {
+        assert!(state().parse_search("from:zed hello").is_none());
+        assert!(state().parse_search("in:nowhere").is_none());
+    }
+}
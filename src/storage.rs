@@ -0,0 +1,157 @@
+use makepad_micro_serde::*;
+use makepad_widgets::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const SESSION_FILE: &str = "session.json";
+
+/// The account that was logged in when the app was last closed.
+#[derive(Clone, Debug, Default, DeJson, SerJson, PartialEq)]
+pub struct LastSession {
+    pub server: String,
+    pub username: String,
+}
+
+/// Per-account directory of JSON files, kept under the user's data directory
+/// and separated by server so two backends never share a cache.
+#[derive(Clone, Debug)]
+pub struct Store {
+    dir: PathBuf,
+}
+
+impl Store {
+    /// `$SHINDENSEN_DATA_DIR`, then the platform data directory.
+    pub fn root() -> Option<PathBuf> {
+        if let Some(dir) = std::env::var_os("SHINDENSEN_DATA_DIR") {
+            return Some(PathBuf::from(dir));
+        }
+        let base = if cfg!(windows) {
+            std::env::var_os("APPDATA").map(PathBuf::from)
+        } else if cfg!(target_os = "macos") {
+            std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join("Library/Application Support"))
+        } else {
+            std::env::var_os("XDG_DATA_HOME")
+                .map(PathBuf::from)
+                .or_else(|| {
+                    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+                })
+        };
+        base.map(|base| base.join("shindensen"))
+    }
+
+    pub fn open(server: &str, account: &str) -> Option<Store> {
+        let dir = Self::root()?.join(sanitize(server)).join(sanitize(account));
+        if let Err(err) = fs::create_dir_all(&dir) {
+            error!("Failed to create store at {}: {}", dir.display(), err);
+            return None;
+        }
+        Some(Store { dir })
+    }
+
+    pub fn last_session() -> Option<LastSession> {
+        read_json(&Self::root()?.join(SESSION_FILE))
+    }
+
+    pub fn save_last_session(session: &LastSession) {
+        if let Some(root) = Self::root() {
+            write_json(&root.join(SESSION_FILE), session);
+        }
+    }
+
+    pub fn clear_last_session() {
+        if let Some(root) = Self::root() {
+            let _ = fs::remove_file(root.join(SESSION_FILE));
+        }
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    pub fn load<T: DeJson>(&self, name: &str) -> Option<T> {
+        read_json(&self.path(name))
+    }
+
+    pub fn save<T: SerJson>(&self, name: &str, value: &T) {
+        write_json(&self.path(name), value);
+    }
+
+    /// Writes raw bytes, e.g. a file waiting for upload. Returns where they went.
+    pub fn save_bytes(&self, name: &str, data: &[u8]) -> Option<PathBuf> {
+        let path = self.path(name);
+        write_bytes(&path, data).then_some(path)
+    }
+
+    pub fn remove(&self, name: &str) {
+        let _ = fs::remove_file(self.path(name));
+    }
+}
+
+fn read_json<T: DeJson>(path: &Path) -> Option<T> {
+    let data = fs::read_to_string(path).ok()?;
+    match T::deserialize_json(&data) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            error!("Ignoring unreadable {}: {:?}", path.display(), err);
+            None
+        }
+    }
+}
+
+fn write_json<T: SerJson>(path: &Path, value: &T) {
+    write_bytes(path, value.serialize_json().as_bytes());
+}
+
+/// Writes through a temporary file so a crash never leaves half a file behind.
+fn write_bytes(path: &Path, data: &[u8]) -> bool {
+    if let Some(parent) = path.parent()
+        && let Err(err) = fs::create_dir_all(parent)
+    {
+        error!("Failed to create {}: {}", parent.display(), err);
+        return false;
+    }
+    let tmp = path.with_extension("tmp");
+    if let Err(err) = fs::write(&tmp, data).and_then(|_| fs::rename(&tmp, path)) {
+        error!("Failed to write {}: {}", path.display(), err);
+        return false;
+    }
+    true
+}
+
+/// Turns a server or account name into a single path component. Names made
+/// only of dots would point at the directory itself or its parent, so their
+/// dots are escaped too.
+fn sanitize(name: &str) -> String {
+    if name.chars().all(|c| c == '.') {
+        return "_".repeat(name.len().max(1));
+    }
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_keeps_plain_names() {
+        assert_eq!(sanitize("alice.b-c_d"), "alice.b-c_d");
+        assert_eq!(sanitize("http://127.0.0.1:3000"), "http___127.0.0.1_3000");
+    }
+
+    #[test]
+    fn sanitize_escapes_dot_only_names() {
+        assert_eq!(sanitize("."), "_");
+        assert_eq!(sanitize(".."), "__");
+        assert_eq!(sanitize(""), "_");
+        assert_eq!(sanitize("../x"), ".._x");
+    }
+}
@@ -0,0 +1,172 @@
+/// Scheme of the links a summary uses to point back at messages.
+pub const MESSAGE_LINK_SCHEME: &str = "msg:";
+
+/// A "summarize since last read" request in progress. Long conversations are
+/// summarized in `parts` chunks first and then once more as a whole.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SummaryJob {
+    pub chat_id: i64,
+    pub parts: usize,
+    pub final_started: bool,
+    /// Nothing was unread, so nothing was sent.
+    pub empty: bool,
+    /// Parts whose summary failed, with the reason. They are left out of the
+    /// merged summary.
+    pub part_errors: Vec<(usize, String)>,
+}
+
+impl SummaryJob {
+    /// One line per failed part, for the status of the summary panel.
+    pub fn part_status(&self) -> String {
+        self.part_errors
+            .iter()
+            .map(|(index, err)| {
+                format!(
+                    "Part {} of {} could not be summarized: {}",
+                    index + 1,
+                    self.parts,
+                    err
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Groups transcript lines into chunks of at most `budget` characters. A
+/// single line longer than the budget is cut rather than dropped.
+pub fn chunk_lines(lines: &[String], budget: usize) -> Vec<String> {
+    let budget = budget.max(1);
+    let mut chunks = vec![];
+    let mut chunk = String::new();
+    for line in lines {
+        let line: String = line.chars().take(budget).collect();
+        if !chunk.is_empty() && chunk.chars().count() + line.chars().count() + 1 > budget {
+            chunks.push(std::mem::take(&mut chunk));
+        }
+        chunk.push_str(&line);
+        chunk.push('\n');
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+pub fn summary_prompt(chat_name: &str) -> String {
+    format!(
+        "Summarize the conversation from the chat \"{}\" for someone catching up. \
+         Lines look like \"[#id] name: message\". Use short bullet points and cite \
+         the messages each point comes from as [#id].",
+        chat_name
+    )
+}
+
+pub fn part_prompt() -> &'static str {
+    "This is one part of a longer conversation. Lines look like \"[#id] name: message\". \
+     List its key points as short bullets and keep the [#id] citations."
+}
+
+pub fn combine_prompt(chat_name: &str) -> String {
+    format!(
+        "These are summaries of consecutive parts of the chat \"{}\". Merge them into one \
+         short bullet list for someone catching up, keeping the [#id] citations.",
+        chat_name
+    )
+}
+
+/// Turns `[#123]` citations into links the summary panel can follow.
+pub fn link_citations(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("[#") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let digits = after.chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits > 0 && after[digits..].starts_with(']') && !after[digits + 1..].starts_with('(') {
+            let id = &after[..digits];
+            out.push_str(&format!("[#{}]({}{})", id, MESSAGE_LINK_SCHEME, id));
+            rest = &after[digits + 1..];
+        } else {
+            out.push_str("[#");
+            rest = after;
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+pub fn parse_message_link(url: &str) -> Option<i64> {
+    url.strip_prefix(MESSAGE_LINK_SCHEME)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn chunk_lines_fills_chunks_up_to_the_budget() {
+        let chunks = chunk_lines(&lines(&["aaaa", "bbbb", "cccc"]), 10);
+        assert_eq!(chunks, vec!["aaaa\nbbbb\n", "cccc\n"]);
+        let chunks = chunk_lines(&lines(&["aaaa", "bbbb", "cccc"]), 100);
+        assert_eq!(chunks, vec!["aaaa\nbbbb\ncccc\n"]);
+    }
+
+    #[test]
+    fn chunk_lines_cuts_overlong_lines() {
+        let chunks = chunk_lines(&lines(&["abcdefghij", "xy"]), 4);
+        assert_eq!(chunks, vec!["abcd\n", "xy\n"]);
+        assert_eq!(chunk_lines(&lines(&["äöü"]), 0), vec!["ä\n"]);
+    }
+
+    #[test]
+    fn chunk_lines_of_nothing_is_empty() {
+        assert!(chunk_lines(&[], 10).is_empty());
+    }
+
+    #[test]
+    fn prompts_name_the_chat_and_ask_for_citations() {
+        assert!(summary_prompt("Team").contains("\"Team\""));
+        assert!(combine_prompt("Team").contains("\"Team\""));
+        for prompt in [
+            summary_prompt("Team").as_str(),
+            part_prompt(),
+            &combine_prompt("Team"),
+        ] {
+            assert!(prompt.contains("[#id]"), "{prompt}");
+        }
+    }
+
+    #[test]
+    fn part_status_lists_failed_parts() {
+        let job = SummaryJob {
+            parts: 3,
+            part_errors: vec![(0, "timeout".into()), (2, "busy".into())],
+            ..Default::default()
+        };
+        assert_eq!(
+            job.part_status(),
+            "Part 1 of 3 could not be summarized: timeout\n\
+             Part 3 of 3 could not be summarized: busy"
+        );
+        assert_eq!(SummaryJob::default().part_status(), "");
+    }
+
+    #[test]
+    fn citations_become_message_links() {
+        assert_eq!(
+            link_citations("- lunch [#12], [#3]"),
+            "- lunch [#12](msg:12), [#3](msg:3)"
+        );
+        assert_eq!(
+            link_citations("[#] [#x] [#5](msg:5)"),
+            "[#] [#x] [#5](msg:5)"
+        );
+        assert_eq!(parse_message_link("msg:42"), Some(42));
+        assert_eq!(parse_message_link("https://x"), None);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::state::*;
+use crate::summary::parse_message_link;
+use crate::theme::{Theme, ThemeStamp};
+use makepad_widgets::*;
+
+script_mod! {
+    use mod.prelude.widgets.*
+    use mod.widgets.*
+
+    mod.widgets.SummaryPanel = #(SummaryPanel::register_widget(vm)) {
+        width: 320.0
+        height: Fill
+        panel := View {
+            width: Fill
+            height: Fill
+            flow: Down
+            show_bg: true
+            draw_bg +: {
+                color: mod.sd_theme.panel
+            }
+            padding: Inset { top: 10.0, right: 10.0, bottom: 10.0, left: 10.0 }
+            spacing: 6.0
+            header := View {
+                width: Fill
+                height: Fit
+                flow: Right
+                align: Align { y: 0.5 }
+                title := H4 { width: Fill, text: "Summary" }
+                close := SDButton { text: "Close" }
+            }
+            status := Label {
+                width: Fill
+                text: ""
+                draw_text +: {
+                    color: mod.sd_theme.text_muted
+                    text_style +: { font_size: 10.0 }
+                }
+            }
+            ScrollYView {
+                width: Fill
+                height: Fill
+                body := Markdown {
+                    width: Fill
+                    height: Fit
+                    body: ""
+                }
+            }
+        }
+    }
+}
+
+/// Side panel with the streamed "since last read" summary of a chat. Only
+/// takes space while a summary is open.
+#[derive(Script, ScriptHook, Widget)]
+struct SummaryPanel {
+    #[deref]
+    view: View,
+    #[rust]
+    theme: ThemeStamp,
+}
+
+impl SummaryPanel {
+    fn apply_theme(&self, cx: &mut Cx, theme: &Theme) {
+        theme.style_background(cx, &self.widget(cx, ids!(panel)), theme.panel);
+        theme.style_label(cx, &self.widget(cx, ids!(header.title)), theme.text);
+        theme.style_label(cx, &self.widget(cx, ids!(status)), theme.text_muted);
+        theme.style_button(cx, &self.widget(cx, ids!(header.close)));
+        theme.style_markdown(cx, &self.widget(cx, ids!(body)));
+    }
+}
+
+impl Widget for SummaryPanel {
+    fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        let state = scope.data.get::<State>().expect("State not found.");
+        let Some((body, status)) = state.summary_view() else {
+            return DrawStep::done();
+        };
+        let chat_name = state
+            .summary
+            .as_ref()
+            .map(|job| state.get_chat_name(job.chat_id))
+            .unwrap_or_default();
+        if self.theme.update(state.theme_version) {
+            self.apply_theme(cx, &state.theme);
+        }
+        self.label(cx, ids!(header.title))
+            .set_text(cx, &format!("Summary of {}", chat_name));
+        self.label(cx, ids!(status)).set_text(cx, &status);
+        self.widget(cx, ids!(body)).set_text(cx, &body);
+        self.view.draw_walk(cx, scope, walk)
+    }
+
+    fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
+        let actions = cx.capture_actions(|cx| {
+            self.view.handle_event(cx, event, scope);
+        });
+        let state = scope.data.get_mut::<State>().expect("State not found.");
+        if self.button(cx, ids!(header.close)).clicked(&actions) {
+            state.close_summary();
+            cx.redraw_all();
+        }
+        for action in &actions {
+            if let MarkdownAction::LinkNavigated(url) = action.as_widget_action().cast()
+                && let Some(msg_id) = parse_message_link(&url)
+                && let Some(chat_id) = state.summary.as_ref().map(|job| job.chat_id)
+            {
+                state.scroll_to_message = Some(msg_id);
+                state.go_to_chat(cx, chat_id);
+            }
+        }
+        cx.extend_actions(actions);
+    }
+}
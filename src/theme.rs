@@ -0,0 +1,552 @@
+use crate::storage::Store;
+use makepad_micro_serde::*;
+use makepad_widgets::*;
+use std::collections::HashSet;
+use std::fs;
+
+const THEMES_DIR: &str = "themes";
+pub const DEFAULT_THEME: &str = "Dark";
+pub const DEFAULT_UI_SCALE: f64 = 1.0;
+pub const MIN_UI_SCALE: f64 = 0.75;
+pub const MAX_UI_SCALE: f64 = 2.0;
+pub const UI_SCALE_STEP: f64 = 0.1;
+pub const DEFAULT_MESSAGE_FONT_SIZE: f64 = 11.0;
+pub const MIN_MESSAGE_FONT_SIZE: f64 = 8.0;
+pub const MAX_MESSAGE_FONT_SIZE: f64 = 24.0;
+
+/// Named colors the UI is drawn with. The dark values mirror `mod.sd_theme`
+/// in `ui.rs`, which the widgets start out with before they are styled.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    /// Page behind the messages and the login and new chat screens.
+    pub background: Vec4,
+    /// Chat list, message bubbles and buttons.
+    pub surface: Vec4,
+    pub surface_hover: Vec4,
+    /// Side panels and the header bands of bubbles.
+    pub panel: Vec4,
+    pub border: Vec4,
+    pub input: Vec4,
+    pub input_border: Vec4,
+    pub text: Vec4,
+    pub text_input: Vec4,
+    pub text_muted: Vec4,
+    pub selection: Vec4,
+    /// Unread badges.
+    pub accent: Vec4,
+    /// Mention bars and badges.
+    pub highlight: Vec4,
+    pub danger: Vec4,
+    /// Factor all text and the chat list are drawn at. This and the message
+    /// font size come from the user's preferences, not from theme files.
+    pub ui_scale: f64,
+    /// Point size of message text, on top of `ui_scale`.
+    pub message_font_size: f64,
+}
+
+/// A theme as written in `themes/<name>.json` in the data directory. Colors
+/// are hex strings like `#26242b` or `#3d5afe80`; ones left out come from
+/// `base`, a built-in theme that defaults to dark.
+#[derive(Clone, Debug, Default, DeJson, SerJson, PartialEq)]
+pub struct ThemeFile {
+    pub name: String,
+    pub base: Option<String>,
+    pub background: Option<String>,
+    pub surface: Option<String>,
+    pub surface_hover: Option<String>,
+    pub panel: Option<String>,
+    pub border: Option<String>,
+    pub input: Option<String>,
+    pub input_border: Option<String>,
+    pub text: Option<String>,
+    pub text_input: Option<String>,
+    pub text_muted: Option<String>,
+    pub selection: Option<String>,
+    pub accent: Option<String>,
+    pub highlight: Option<String>,
+    pub danger: Option<String>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::dark()
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Theme {
+            name: DEFAULT_THEME.to_string(),
+            background: hex("#26242b"),
+            surface: hex("#3f497e"),
+            surface_hover: hex("#4f5ba0"),
+            panel: hex("#323456"),
+            border: hex("#2d2c40"),
+            input: hex("#1a1a2e"),
+            input_border: hex("#3a3a5a"),
+            text: hex("#ffffff"),
+            text_input: hex("#dcdcdc"),
+            text_muted: hex("#b0b0c8"),
+            selection: hex("#3d5afe80"),
+            accent: hex("#3d5afe"),
+            highlight: hex("#f4b842"),
+            danger: hex("#ff4444"),
+            ui_scale: DEFAULT_UI_SCALE,
+            message_font_size: DEFAULT_MESSAGE_FONT_SIZE,
+        }
+    }
+
+    pub fn light() -> Self {
+        Theme {
+            name: "Light".to_string(),
+            background: hex("#f4f5f9"),
+            surface: hex("#dfe3f3"),
+            surface_hover: hex("#c9d0ef"),
+            panel: hex("#ffffff"),
+            border: hex("#c5cae0"),
+            input: hex("#ffffff"),
+            input_border: hex("#b8bed6"),
+            text: hex("#1d1f2b"),
+            text_input: hex("#1d1f2b"),
+            text_muted: hex("#5c6178"),
+            selection: hex("#3d5afe40"),
+            accent: hex("#3d5afe"),
+            highlight: hex("#e0a100"),
+            danger: hex("#c62828"),
+            ui_scale: DEFAULT_UI_SCALE,
+            message_font_size: DEFAULT_MESSAGE_FONT_SIZE,
+        }
+    }
+
+    pub fn high_contrast() -> Self {
+        Theme {
+            name: "High contrast".to_string(),
+            background: hex("#000000"),
+            surface: hex("#000000"),
+            surface_hover: hex("#1a3cff"),
+            panel: hex("#000000"),
+            border: hex("#ffffff"),
+            input: hex("#000000"),
+            input_border: hex("#ffffff"),
+            text: hex("#ffffff"),
+            text_input: hex("#ffffff"),
+            text_muted: hex("#ffff00"),
+            selection: hex("#1a3cffa0"),
+            accent: hex("#1a3cff"),
+            highlight: hex("#ffff00"),
+            danger: hex("#ff5555"),
+            ui_scale: DEFAULT_UI_SCALE,
+            message_font_size: DEFAULT_MESSAGE_FONT_SIZE,
+        }
+    }
+
+    pub fn built_in() -> Vec<Theme> {
+        vec![Theme::dark(), Theme::light(), Theme::high_contrast()]
+    }
+
+    /// Built-in themes followed by the ones in the themes directory. A custom
+    /// theme with the name of a built-in one replaces it.
+    pub fn load_all() -> Vec<Theme> {
+        let mut themes = Theme::built_in();
+        let Some(dir) = Store::root().map(|root| root.join(THEMES_DIR)) else {
+            return themes;
+        };
+        let Ok(entries) = fs::read_dir(&dir) else {
+            return themes;
+        };
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let theme = fs::read_to_string(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|data| {
+                    ThemeFile::deserialize_json(&data).map_err(|err| format!("{:?}", err))
+                })
+                .and_then(|file| Theme::from_file(&file, &themes));
+            match theme {
+                Ok(theme) => match themes.iter_mut().find(|known| known.name == theme.name) {
+                    Some(known) => *known = theme,
+                    None => themes.push(theme),
+                },
+                Err(err) => error!("Ignoring theme {}: {}", path.display(), err),
+            }
+        }
+        themes
+    }
+
+    /// Builds a theme from a file on top of its base among `known`.
+    pub fn from_file(file: &ThemeFile, known: &[Theme]) -> Result<Theme, String> {
+        if file.name.trim().is_empty() {
+            return Err("the theme has no name".to_string());
+        }
+        let base_name = file.base.as_deref().unwrap_or(DEFAULT_THEME);
+        let mut theme = known
+            .iter()
+            .find(|theme| theme.name == base_name)
+            .cloned()
+            .ok_or_else(|| format!("unknown base theme {:?}", base_name))?;
+        theme.name = file.name.trim().to_string();
+        let overrides = [
+            (&file.background, &mut theme.background),
+            (&file.surface, &mut theme.surface),
+            (&file.surface_hover, &mut theme.surface_hover),
+            (&file.panel, &mut theme.panel),
+            (&file.border, &mut theme.border),
+            (&file.input, &mut theme.input),
+            (&file.input_border, &mut theme.input_border),
+            (&file.text, &mut theme.text),
+            (&file.text_input, &mut theme.text_input),
+            (&file.text_muted, &mut theme.text_muted),
+            (&file.selection, &mut theme.selection),
+            (&file.accent, &mut theme.accent),
+            (&file.highlight, &mut theme.highlight),
+            (&file.danger, &mut theme.danger),
+        ];
+        for (value, color) in overrides {
+            if let Some(value) = value {
+                *color = parse_color(value).ok_or_else(|| format!("bad color {:?}", value))?;
+            }
+        }
+        Ok(theme)
+    }
+
+    pub fn style_button(&self, cx: &mut Cx, button: &WidgetRef) {
+        script_apply_eval!(cx, button, {
+            draw_bg +: {
+                color: #(self.surface)
+                color_hover: #(self.surface_hover)
+                color_down: #(self.surface)
+            }
+            draw_text +: {
+                color: #(self.text)
+                font_scale: #(self.ui_scale)
+            }
+        });
+    }
+
+    pub fn style_input(&self, cx: &mut Cx, input: &WidgetRef) {
+        script_apply_eval!(cx, input, {
+            draw_bg +: {
+                color: #(self.input)
+                border_color: #(self.input_border)
+            }
+            draw_text +: {
+                color: #(self.text_input)
+                font_scale: #(self.ui_scale)
+            }
+            draw_selection +: { color: #(self.selection) }
+            draw_cursor +: { color: #(self.text_input) }
+        });
+    }
+
+    pub fn style_alert(&self, cx: &mut Cx, alert: &WidgetRef) {
+        let fill = vec4(self.danger.x, self.danger.y, self.danger.z, 0.13);
+        script_apply_eval!(cx, alert, {
+            draw_bg +: {
+                color: #(fill)
+                border_color: #(self.danger)
+            }
+        });
+        let text = alert.widget(cx, ids!(alert_text));
+        script_apply_eval!(cx, text, {
+            draw_text +: {
+                color: #(self.danger)
+                font_scale: #(self.ui_scale)
+            }
+        });
+    }
+
+    /// Background of a plain view, e.g. a page or a side panel.
+    pub fn style_background(&self, cx: &mut Cx, view: &WidgetRef, color: Vec4) {
+        script_apply_eval!(cx, view, {
+            draw_bg +: { color: #(color) }
+        });
+    }
+
+    pub fn style_label(&self, cx: &mut Cx, label: &WidgetRef, color: Vec4) {
+        script_apply_eval!(cx, label, {
+            draw_text +: {
+                color: #(color)
+                font_scale: #(self.ui_scale)
+            }
+        });
+    }
+
+    /// A rounded box with a border, e.g. a toast or a popup.
+    pub fn style_card(&self, cx: &mut Cx, card: &WidgetRef) {
+        script_apply_eval!(cx, card, {
+            draw_bg +: {
+                color: #(self.panel)
+                border_color: #(self.surface)
+            }
+        });
+    }
+
+    /// A message bubble in the feed.
+    pub fn style_post(&self, cx: &mut Cx, post: &WidgetRef) {
+        let body = post.widget(cx, ids!(body));
+        script_apply_eval!(cx, body, {
+            draw_bg +: {
+                color: #(self.surface)
+                border_color: #(self.border)
+            }
+        });
+        let username = post.widget(cx, ids!(body.username));
+        script_apply_eval!(cx, username, {
+            draw_bg +: {
+                color: #(self.panel)
+                border_color: #(self.border)
+            }
+        });
+        self.style_background(cx, &post.widget(cx, ids!(body.mention_bar)), self.highlight);
+        self.style_label(cx, &post.widget(cx, ids!(body.username.text)), self.text);
+        self.style_label(
+            cx,
+            &post.widget(cx, ids!(body.username.time)),
+            self.text_muted,
+        );
+        self.style_label(
+            cx,
+            &post.widget(cx, ids!(body.username.status)),
+            self.text_muted,
+        );
+        self.style_button(cx, &post.widget(cx, ids!(body.username.raw_toggle)));
+        let font_size = self.message_font_size;
+        let text = post.widget(cx, ids!(body.content.text));
+        script_apply_eval!(cx, text, {
+            draw_text +: {
+                color: #(self.text)
+                font_scale: #(self.ui_scale)
+                text_style +: { font_size: #(font_size) }
+            }
+        });
+        self.style_markdown(cx, &post.widget(cx, ids!(body.content.md)));
+    }
+
+    /// Markdown at the message font size, e.g. a message or the summary.
+    pub fn style_markdown(&self, cx: &mut Cx, md: &WidgetRef) {
+        let font_size = self.message_font_size * self.ui_scale;
+        script_apply_eval!(cx, md, {
+            font_size: #(font_size)
+            draw_normal +: { color: #(self.text) }
+            draw_italic +: { color: #(self.text) }
+            draw_bold +: { color: #(self.text) }
+            draw_bold_italic +: { color: #(self.text) }
+            draw_fixed +: { color: #(self.text) }
+        });
+    }
+
+    /// A row of the chat list, search results and the other chat-like lists.
+    pub fn style_chat_item(&self, cx: &mut Cx, item: &WidgetRef) {
+        let body = item.widget(cx, ids!(body));
+        script_apply_eval!(cx, body, {
+            draw_bg +: {
+                color: #(self.surface)
+                border_color: #(self.border)
+            }
+        });
+        let header = item.widget(cx, ids!(body.target_usr));
+        script_apply_eval!(cx, header, {
+            draw_bg +: {
+                color: #(self.panel)
+                border_color: #(self.border)
+            }
+        });
+        self.style_background(cx, &item.widget(cx, ids!(body.avatar.circle)), self.panel);
+        self.style_label(
+            cx,
+            &item.widget(cx, ids!(body.avatar.circle.initial)),
+            self.text,
+        );
+        self.style_label(cx, &item.widget(cx, ids!(body.target_usr.text)), self.text);
+        self.style_label(
+            cx,
+            &item.widget(cx, ids!(body.target_usr.time)),
+            self.text_muted,
+        );
+        self.style_background(
+            cx,
+            &item.widget(cx, ids!(body.target_usr.mentions)),
+            self.highlight,
+        );
+        self.style_label(
+            cx,
+            &item.widget(cx, ids!(body.target_usr.mentions.count)),
+            self.background,
+        );
+        self.style_background(
+            cx,
+            &item.widget(cx, ids!(body.target_usr.unread)),
+            self.accent,
+        );
+        self.style_background(
+            cx,
+            &item.widget(cx, ids!(body.avatar.badges.mention_badge)),
+            self.highlight,
+        );
+        self.style_label(
+            cx,
+            &item.widget(cx, ids!(body.avatar.badges.mention_badge.count)),
+            self.background,
+        );
+        self.style_background(
+            cx,
+            &item.widget(cx, ids!(body.avatar.badges.unread_badge)),
+            self.accent,
+        );
+        self.style_label(cx, &item.widget(cx, ids!(body.last_msg.text)), self.text);
+        for button in [
+            ids!(body.target_usr.menu_btn),
+            ids!(body.menu.pin),
+            ids!(body.menu.mute),
+            ids!(body.menu.mute_hour),
+            ids!(body.menu.archive),
+            ids!(body.menu.summarize),
+        ] {
+            self.style_button(cx, &item.widget(cx, button));
+        }
+    }
+}
+
+/// The theme a widget was last styled for. Widgets restyle their own parts
+/// on the first draw after the theme changed, so a widget that is not
+/// drawn, e.g. a closed panel, picks the theme up when it opens.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ThemeStamp(Option<u64>);
+
+impl ThemeStamp {
+    /// Whether `version` is new to the widget, which then counts as styled.
+    pub fn update(&mut self, version: u64) -> bool {
+        if self.0 == Some(version) {
+            return false;
+        }
+        self.0 = Some(version);
+        true
+    }
+}
+
+/// Rows of a portal list styled for the current theme. The list reuses its
+/// row widgets while scrolling, so each is styled once per theme rather
+/// than on every draw.
+#[derive(Debug, Default)]
+pub struct StyledRows {
+    version: u64,
+    rows: HashSet<WidgetUid>,
+}
+
+impl StyledRows {
+    /// Whether `row` still needs styling for theme `version`.
+    pub fn needs_style(&mut self, version: u64, row: &WidgetRef) -> bool {
+        if self.version != version {
+            self.version = version;
+            self.rows.clear();
+        }
+        self.rows.insert(row.widget_uid())
+    }
+}
+
+/// `#rgb`, `#rrggbb` or `#rrggbbaa`, the leading `#` being optional.
+pub fn parse_color(text: &str) -> Option<Vec4> {
+    let digits = text.trim().trim_start_matches('#');
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize, len: usize| {
+        u8::from_str_radix(&digits[i * len..(i + 1) * len], 16)
+            .ok()
+            .map(|value| (if len == 1 { value * 17 } else { value }) as f32 / 255.0)
+    };
+    let (r, g, b, a) = match digits.len() {
+        3 => (channel(0, 1)?, channel(1, 1)?, channel(2, 1)?, 1.0),
+        6 => (channel(0, 2)?, channel(1, 2)?, channel(2, 2)?, 1.0),
+        8 => (
+            channel(0, 2)?,
+            channel(1, 2)?,
+            channel(2, 2)?,
+            channel(3, 2)?,
+        ),
+        _ => return None,
+    };
+    Some(vec4(r, g, b, a))
+}
+
+fn hex(text: &str) -> Vec4 {
+    parse_color(text).expect("built-in theme colors are valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!(parse_color("#ffffff"), Some(vec4(1.0, 1.0, 1.0, 1.0)));
+        assert_eq!(parse_color(" 000 "), Some(vec4(0.0, 0.0, 0.0, 1.0)));
+        assert_eq!(parse_color("#f00"), parse_color("#ff0000"));
+        assert_eq!(parse_color("#3d5afe80"), Some(hex("#3d5afe80")));
+        assert_eq!(parse_color("#00000080").map(|c| c.w), Some(128.0 / 255.0));
+    }
+
+    #[test]
+    fn rejects_malformed_colors() {
+        for text in [
+            "", "#", "#12", "#12345", "#1234567", "#gggggg", "red", "#+1+2+3",
+        ] {
+            assert_eq!(parse_color(text), None, "{text}");
+        }
+    }
+
+    #[test]
+    fn theme_files_override_their_base() {
+        let file = ThemeFile {
+            name: " Dusk ".to_string(),
+            base: Some("Light".to_string()),
+            accent: Some("#ff8800".to_string()),
+            ..Default::default()
+        };
+        let theme = Theme::from_file(&file, &Theme::built_in()).unwrap();
+        assert_eq!(theme.name, "Dusk");
+        assert_eq!(theme.accent, hex("#ff8800"));
+        assert_eq!(theme.background, Theme::light().background);
+        assert_eq!(theme.text, Theme::light().text);
+    }
+
+    #[test]
+    fn theme_files_default_to_the_dark_base() {
+        let file = ThemeFile {
+            name: "Plain".to_string(),
+            ..Default::default()
+        };
+        let theme = Theme::from_file(&file, &Theme::built_in()).unwrap();
+        assert_eq!(
+            theme,
+            Theme {
+                name: "Plain".to_string(),
+                ..Theme::dark()
+            }
+        );
+    }
+
+    #[test]
+    fn bad_theme_files_are_rejected() {
+        let known = Theme::built_in();
+        let unnamed = ThemeFile::default();
+        assert!(Theme::from_file(&unnamed, &known).is_err());
+        let unknown_base = ThemeFile {
+            name: "X".to_string(),
+            base: Some("Sepia".to_string()),
+            ..Default::default()
+        };
+        assert!(Theme::from_file(&unknown_base, &known).is_err());
+        let bad_color = ThemeFile {
+            name: "X".to_string(),
+            text: Some("#12".to_string()),
+            ..Default::default()
+        };
+        assert!(Theme::from_file(&bad_color, &known).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime, SecondsFormat, Utc};
+use makepad_micro_serde::*;
+use std::fmt;
+use std::str::FromStr;
+
+/// Accepted when the server sends a timestamp without an offset; read as UTC.
+const NAIVE_FORMATS: &[&str] = &["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"];
+
+/// A point in time sent by the server, RFC 3339 on the wire.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(DateTime<Utc>);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimestampError(String);
+
+impl fmt::Display for TimestampError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid timestamp {:?}", self.0)
+    }
+}
+
+impl std::error::Error for TimestampError {}
+
+impl FromStr for Timestamp {
+    type Err = TimestampError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Timestamp(time.with_timezone(&Utc)));
+        }
+        NAIVE_FORMATS
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+            .map(|time| Timestamp(time.and_utc()))
+            .ok_or_else(|| TimestampError(s.to_string()))
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+    }
+}
+
+impl SerJson for Timestamp {
+    fn ser_json(&self, d: usize, s: &mut SerJsonState) {
+        self.to_string().ser_json(d, s);
+    }
+}
+
+/// A malformed value fails the parse with an error naming it.
+impl DeJson for Timestamp {
+    fn de_json(s: &mut DeJsonState, i: &mut std::str::Chars) -> Result<Self, DeJsonErr> {
+        let raw = String::de_json(s, i)?;
+        raw.parse()
+            .map_err(|err: TimestampError| s.err_parse(&err.to_string()))
+    }
+}
+
+impl Timestamp {
+    pub fn now() -> Self {
+        Timestamp(Utc::now())
+    }
+
+    pub fn after_hours(hours: i64) -> Self {
+        Timestamp(Utc::now() + chrono::Duration::hours(hours))
+    }
+
+    pub fn local(&self) -> DateTime<Local> {
+        self.0.with_timezone(&Local)
+    }
+
+    pub fn local_day(&self) -> NaiveDate {
+        self.local().date_naive()
+    }
+
+    /// "14:05" in local time.
+    pub fn time_label(&self) -> String {
+        self.local().format("%H:%M").to_string()
+    }
+
+    /// Short age of the timestamp for chat previews: "now", "5 min", "14:05",
+    /// "Yesterday", a weekday within the last week, then the date.
+    pub fn relative_label(&self, now: Timestamp) -> String {
+        let age = now.0.signed_duration_since(self.0);
+        if age.num_minutes() < 1 {
+            return "now".to_string();
+        }
+        if age.num_minutes() < 60 {
+            return format!("{} min", age.num_minutes());
+        }
+        let days = (now.local_day() - self.local_day()).num_days();
+        match days {
+            0 => self.time_label(),
+            1 => "Yesterday".to_string(),
+            2..=6 => self.local().format("%a").to_string(),
+            _ => self.local().format("%d.%m.%y").to_string(),
+        }
+    }
+}
+
+/// Separator text for a day in the message feed.
+pub fn day_label(day: NaiveDate, today: NaiveDate) -> String {
+    match (today - day).num_days() {
+        0 => "Today".to_string(),
+        1 => "Yesterday".to_string(),
+        _ if day.year() == today.year() => day.format("%-d %B").to_string(),
+        _ => day.format("%-d %B %Y").to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn utc(y: i32, mo: u32, d: u32, h: u32, mi: u32, s: u32) -> Timestamp {
+        Timestamp(Utc.with_ymd_and_hms(y, mo, d, h, mi, s).unwrap())
+    }
+
+    #[test]
+    fn parses_rfc3339_with_offsets() {
+        let time: Timestamp = "2024-03-05T10:15:30+02:00".parse().unwrap();
+        assert_eq!(time, utc(2024, 3, 5, 8, 15, 30));
+        let time: Timestamp = " 2024-03-05T10:15:30.250Z ".parse().unwrap();
+        assert_eq!(time.to_string(), "2024-03-05T10:15:30.250Z");
+    }
+
+    #[test]
+    fn reads_naive_timestamps_as_utc() {
+        let t: Timestamp = "2024-03-05T10:15:30".parse().unwrap();
+        assert_eq!(t, utc(2024, 3, 5, 10, 15, 30));
+        let t: Timestamp = "2024-03-05 10:15:30.5".parse().unwrap();
+        assert_eq!(t.to_string(), "2024-03-05T10:15:30.500Z");
+    }
+
+    #[test]
+    fn rejects_malformed_timestamps() {
+        for raw in ["", "yesterday", "2024-13-01T00:00:00Z", "2024-03-05"] {
+            assert_eq!(
+                raw.parse::<Timestamp>(),
+                Err(TimestampError(raw.to_string())),
+                "{raw}"
+            );
+        }
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let time = utc(2023, 12, 31, 23, 59, 59);
+        assert_eq!(time.to_string().parse::<Timestamp>(), Ok(time));
+    }
+
+    #[test]
+    fn json_rejects_malformed_timestamps() {
+        let time = Timestamp::deserialize_json("\"2024-03-05T10:15:30Z\"");
+        assert_eq!(time.ok(), Some(utc(2024, 3, 5, 10, 15, 30)));
+        let err = Timestamp::deserialize_json("\"yesterday\"").unwrap_err();
+        assert!(format!("{:?}", err).contains("yesterday"), "{err:?}");
+    }
+
+    #[test]
+    fn relative_label_for_recent_times() {
+        let now = Timestamp::now();
+        let ago = |secs: i64| Timestamp(now.0 - chrono::Duration::seconds(secs));
+        assert_eq!(ago(30).relative_label(now), "now");
+        assert_eq!(ago(5 * 60).relative_label(now), "5 min");
+        assert_eq!(
+            ago(8 * 24 * 3600).relative_label(now),
+            ago(8 * 24 * 3600).local().format("%d.%m.%y").to_string()
+        );
+    }
+
+    #[test]
+    fn day_labels() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 5).unwrap();
+        assert_eq!(day_label(today, today), "Today");
+        assert_eq!(day_label(today.pred_opt().unwrap(), today), "Yesterday");
+        let day = NaiveDate::from_ymd_opt(2024, 1, 9).unwrap();
+        assert_eq!(day_label(day, today), "9 January");
+        let day = NaiveDate::from_ymd_opt(2023, 1, 9).unwrap();
+        assert_eq!(day_label(day, today), "9 January 2023");
+    }
+}
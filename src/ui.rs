@@ -3,6 +3,26 @@ use makepad_widgets::*;
 script_mod! {
     use mod.prelude.widgets.*
 
+    // Colors of the dark theme, which every widget starts out with. Other
+    // themes are applied at runtime, each widget restyling itself when the
+    // theme changes; see `ThemeStamp`.
+    mod.sd_theme = {
+        background: #26242b
+        surface: #3f497e
+        surface_hover: #4f5ba0
+        panel: #323456
+        border: #2d2c40
+        input: #1a1a2e
+        input_border: #3a3a5a
+        text: #ffffff
+        text_input: #dcdcdc
+        text_muted: #b0b0c8
+        selection: #3d5afe80
+        accent: #3d5afe
+        highlight: #f4b842
+        danger: #ff4444
+    }
+
     mod.widgets.SDTextInput = TextInput{
         width: 500.0
         height: Fit
@@ -16,24 +36,24 @@ script_mod! {
         is_read_only: false
 
         draw_bg +: {
-            color: #1a1a2e
+            color: mod.sd_theme.input
             border_radius: 4.0
             border_size: 1.0
-            border_color: #3a3a5a
+            border_color: mod.sd_theme.input_border
         }
 
         draw_text +: {
             font_scale: 1.0
             text_style +: { font_size: 13.0, line_spacing: 1.2 }
-            color: #dcdcdc
+            color: mod.sd_theme.text_input
         }
 
         draw_selection +: {
-            color: #3d5afe80
+            color: mod.sd_theme.selection
         }
 
         draw_cursor +: {
-            color: #ffffff
+            color: mod.sd_theme.text
         }
     }
 
@@ -46,7 +66,7 @@ script_mod! {
         reset_hover_on_click: true
 
         draw_text +: {
-            color: #ffffff
+            color: mod.sd_theme.text
             text_style +: {
                 font_size: 11.0
                 line_spacing: 1.2
@@ -54,9 +74,9 @@ script_mod! {
         }
 
         draw_bg +: {
-            color: #3f497e
-            color_hover: #4f5ba0
-            color_down: #3f497e
+            color: mod.sd_theme.surface
+            color_hover: mod.sd_theme.surface_hover
+            color_down: mod.sd_theme.surface
             border_size: 0.0
             border_radius: 4.0
         }
@@ -70,7 +90,7 @@ script_mod! {
 
         draw_text +: {
             font_scale: 1.0
-            color: #ffffff
+            color: mod.sd_theme.text
             text_style +: {
                 font_size: 13.0
                 line_spacing: 1.2
@@ -85,56 +105,100 @@ script_mod! {
         padding: Inset { top: 8.0, right: 12.0, bottom: 8.0, left: 12.0 }
         margin: Inset { top: 5.0, right: 0.0, bottom: 0.0, left: 0.0 }
         draw_bg +: {
+            // The danger color, faintly; see `Theme::style_alert`.
             color: #ff444422
             border_size: 1.0
-            border_color: #ff4444
+            border_color: mod.sd_theme.danger
             border_radius: 4.0
         }
         alert_text := SDLabel {
             margin: Inset { top: 0.0, right: 0.0, bottom: 0.0, left: 0.0 }
-            draw_text +: { color: #ff4444, text_style +: { font_size: 11.0 } }
+            draw_text +: { color: mod.sd_theme.danger, text_style +: { font_size: 11.0 } }
         }
     }
 
     mod.widgets.Post = View {
-        width: Fit
+        width: Fill
         height: Fit
         padding: Inset { top: .0, bottom: .0 }
         margin: Inset { top: 10.0, right: 10.0, bottom: 10.0, left: 10.0 }
 
         body := RoundedView {
-            width: Fit
+            width: Fill
             height: Fit
             flow: Down
             draw_bg +: {
-                color: instance(#3f497e)
+                color: instance(mod.sd_theme.surface)
                 border_radius: 8.0
                 border_size: 1.5
-                border_color: instance(#2d2c40)
+                border_color: instance(mod.sd_theme.border)
             }
 
+            mention_bar := View {
+                visible: false
+                width: Fill
+                height: 3.0
+                show_bg: true
+                draw_bg +: {
+                    color: mod.sd_theme.highlight
+                }
+            }
             username := RoundedYView {
                 width: Fit
                 height: Fit
                 padding: Inset { top: 5.0, right: 10.0, bottom: 5.0, left: 10.0 }
                 show_bg: true
                 draw_bg +: {
-                    color: instance(#323456)
+                    color: instance(mod.sd_theme.panel)
                     border_radius: vec2(8.0, 0.0)
                     border_inset: vec4(0.0, 0.0, 0.0, -30.0)
                     border_size: 1.5
-                    border_color: instance(#2d2c40)
+                    border_color: instance(mod.sd_theme.border)
                 }
                 text := H4 {
                     width: Fit
                     text: ""
                 }
+                time := Label {
+                    width: Fit
+                    margin: Inset { left: 8.0 }
+                    text: ""
+                    draw_text +: {
+                        color: mod.sd_theme.text_muted
+                        text_style +: { font_size: 9.0 }
+                    }
+                }
+                status := Label {
+                    width: Fit
+                    margin: Inset { left: 8.0 }
+                    text: ""
+                    draw_text +: {
+                        color: mod.sd_theme.text_muted
+                        text_style +: { font_size: 9.0 }
+                    }
+                }
+                raw_toggle := SDButton {
+                    margin: Inset { left: 8.0 }
+                    padding: Inset { top: 2.0, right: 6.0, bottom: 2.0, left: 6.0 }
+                    text: "Raw"
+                    draw_text +: { text_style +: { font_size: 8.0 } }
+                }
             }
             content := RoundedView {
-                width: Fit
+                width: Fill
                 height: Fit
+                flow: Down
                 padding: Inset { top: 10.0, right: 10.0, bottom: 10.0, left: 10.0 }
-                text := Label { text: "" }
+                md := Markdown {
+                    width: Fill
+                    height: Fit
+                    body: ""
+                }
+                text := Label {
+                    visible: false
+                    width: Fill
+                    text: ""
+                }
             }
         }
     }
@@ -151,25 +215,141 @@ script_mod! {
             flow: Down
             cursor: MouseCursor.Hand
             draw_bg +: {
-                color: instance(#3f497e)
+                color: instance(mod.sd_theme.surface)
                 border_radius: 4.0
-                border_color: instance(#2d2c40)
+                border_color: instance(mod.sd_theme.border)
                 border_size: 1.5
             }
 
+            // Stands in for the rest while the chat list is collapsed.
+            avatar := View {
+                visible: false
+                width: Fill
+                height: Fit
+                flow: Down
+                align: Align { x: 0.5 }
+                spacing: 4.0
+                padding: Inset { top: 6.0, bottom: 6.0 }
+                circle := RoundedView {
+                    width: 40.0
+                    height: 40.0
+                    align: Align { x: 0.5, y: 0.5 }
+                    show_bg: true
+                    draw_bg +: {
+                        color: mod.sd_theme.panel
+                        border_radius: 20.0
+                    }
+                    initial := H4 { text: "" }
+                }
+                // The mention and unread counts of the hidden header.
+                badges := View {
+                    width: Fit
+                    height: Fit
+                    flow: Right
+                    spacing: 2.0
+                    mention_badge := RoundedView {
+                        visible: false
+                        width: Fit
+                        height: Fit
+                        padding: Inset { top: 1.0, right: 4.0, bottom: 1.0, left: 4.0 }
+                        show_bg: true
+                        draw_bg +: {
+                            color: mod.sd_theme.highlight
+                            border_radius: 6.0
+                        }
+                        count := Label {
+                            text: ""
+                            draw_text +: {
+                                color: mod.sd_theme.background
+                                text_style +: { font_size: 8.0 }
+                            }
+                        }
+                    }
+                    unread_badge := RoundedView {
+                        visible: false
+                        width: Fit
+                        height: Fit
+                        padding: Inset { top: 1.0, right: 4.0, bottom: 1.0, left: 4.0 }
+                        show_bg: true
+                        draw_bg +: {
+                            color: mod.sd_theme.accent
+                            border_radius: 6.0
+                        }
+                        count := Label {
+                            text: ""
+                            draw_text +: {
+                                color: mod.sd_theme.text
+                                text_style +: { font_size: 8.0 }
+                            }
+                        }
+                    }
+                }
+            }
             target_usr := RoundedYView {
                 width: Fill
                 height: Fit
                 padding: Inset { top: 5.0, right: 10.0, bottom: 5.0, left: 10.0 }
                 show_bg: true
                 draw_bg +: {
-                    color: instance(#323456)
+                    color: instance(mod.sd_theme.panel)
                     border_radius: vec2(4.0, 0.0)
                     border_inset: vec4(0.0, 0.0, 0.0, -30.0)
-                    border_color: instance(#2d2c40)
+                    border_color: instance(mod.sd_theme.border)
                     border_size: 1.5
                 }
-                text := H4 { text: "" }
+                flow: Right
+                align: Align { y: 0.5 }
+                spacing: 6.0
+                text := H4 { width: Fill, text: "" }
+                time := Label {
+                    width: Fit
+                    text: ""
+                    draw_text +: {
+                        color: mod.sd_theme.text_muted
+                        text_style +: { font_size: 9.0 }
+                    }
+                }
+                mentions := RoundedView {
+                    visible: false
+                    width: Fit
+                    height: Fit
+                    padding: Inset { top: 2.0, right: 6.0, bottom: 2.0, left: 6.0 }
+                    show_bg: true
+                    draw_bg +: {
+                        color: mod.sd_theme.highlight
+                        border_radius: 8.0
+                    }
+                    count := Label {
+                        text: ""
+                        draw_text +: {
+                            color: mod.sd_theme.background
+                            text_style +: { font_size: 9.0 }
+                        }
+                    }
+                }
+                unread := RoundedView {
+                    visible: false
+                    width: Fit
+                    height: Fit
+                    padding: Inset { top: 2.0, right: 6.0, bottom: 2.0, left: 6.0 }
+                    show_bg: true
+                    draw_bg +: {
+                        color: mod.sd_theme.accent
+                        border_radius: 8.0
+                    }
+                    count := Label {
+                        text: ""
+                        draw_text +: {
+                            color: mod.sd_theme.text
+                            text_style +: { font_size: 9.0 }
+                        }
+                    }
+                }
+                menu_btn := SDButton {
+                    visible: false
+                    padding: Inset { top: 2.0, right: 6.0, bottom: 2.0, left: 6.0 }
+                    text: "..."
+                }
             }
             last_msg := RoundedView {
                 width: Fill
@@ -177,6 +357,19 @@ script_mod! {
                 padding: Inset { top: 10.0, right: 10.0, bottom: 10.0, left: 10.0 }
                 text := P { text: "" }
             }
+            menu := View {
+                visible: false
+                width: Fill
+                height: Fit
+                flow: Right
+                spacing: 6.0
+                padding: Inset { top: 0.0, right: 10.0, bottom: 10.0, left: 10.0 }
+                pin := SDButton { text: "Pin" }
+                mute := SDButton { text: "Mute" }
+                mute_hour := SDButton { text: "Mute 1h" }
+                archive := SDButton { text: "Archive" }
+                summarize := SDButton { visible: false, text: "Summarize" }
+            }
         }
     }
 